@@ -0,0 +1,14 @@
+#![no_main]
+use {cypher_interactive_fuzz::serum_slab::Slab, libfuzzer_sys::fuzz_target};
+
+// arbitrary account data must be rejected with an error, never panic or loop forever
+fuzz_target!(|data: &[u8]| {
+    if let Ok(slab) = Slab::from_account_data(data) {
+        _ = slab.get_orders(1, 1, true);
+        _ = slab.get_orders(1, 1, false);
+    }
+    if let Ok(slab) = Slab::new(data) {
+        _ = slab.get_depth(10, 100, 10, true);
+        _ = slab.get_depth(u64::MAX, u64::MAX, 1, false);
+    }
+});
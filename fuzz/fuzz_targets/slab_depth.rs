@@ -0,0 +1,46 @@
+#![no_main]
+use {
+    arbitrary::Arbitrary,
+    cypher_interactive_fuzz::{build_slab, serum_slab::Slab},
+    libfuzzer_sys::fuzz_target,
+};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    keys: Vec<(u64, u64)>,
+    depth: u8,
+}
+
+// a well formed critbit tree must decode to the same orders, in the same order, as sorting the keys
+fuzz_target!(|input: Input| {
+    // orders with a zero price never make it into the book
+    let mut keys: Vec<u128> = input
+        .keys
+        .iter()
+        .map(|(price, seq)| (u128::from((*price).max(1)) << 64) | u128::from(*seq))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let bytes = build_slab(&keys);
+    let slab = Slab::new(&bytes).unwrap();
+
+    let asks = slab.get_orders(1, 1, true).unwrap();
+    let ask_ids: Vec<u128> = asks.iter().map(|o| o.order_id).collect();
+    assert_eq!(ask_ids, keys);
+
+    let bids = slab.get_orders(1, 1, false).unwrap();
+    let bid_ids: Vec<u128> = bids.iter().map(|o| o.order_id).collect();
+    let mut reversed = keys.clone();
+    reversed.reverse();
+    assert_eq!(bid_ids, reversed);
+
+    let depth = input.depth as usize;
+    let top_asks = slab.get_depth(depth as u64, 1, 1, true).unwrap();
+    let top_ask_ids: Vec<u128> = top_asks.iter().map(|o| o.order_id).collect();
+    assert_eq!(top_ask_ids, keys[..depth.min(keys.len())]);
+
+    for order in asks {
+        assert_eq!(u128::from(order.price_lots), order.order_id >> 64);
+    }
+});
@@ -0,0 +1,75 @@
+#[path = "../../src/serum_slab.rs"]
+pub mod serum_slab;
+
+use {
+    bytemuck::bytes_of,
+    serum_slab::{FeeTier, LeafNode},
+};
+
+const NODE_SIZE: usize = 72;
+const INNER_NODE_TAG: u32 = 1;
+
+/// Builds the bytes of a slab holding a critbit tree with one leaf per distinct key,
+/// laid out the same way the dex lays out the bids and asks accounts, minus the account padding.
+pub fn build_slab(keys: &[u128]) -> Vec<u8> {
+    let mut keys = keys.to_vec();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut nodes: Vec<[u8; NODE_SIZE]> = Vec::with_capacity(keys.len() * 2);
+    let root = if keys.is_empty() {
+        0
+    } else {
+        build_node(&keys, &mut nodes)
+    };
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(32 + nodes.len() * NODE_SIZE);
+    bytes.extend_from_slice(&(nodes.len() as u64).to_le_bytes()); // bump_index
+    bytes.extend_from_slice(&0_u64.to_le_bytes()); // free_list_len
+    bytes.extend_from_slice(&0_u32.to_le_bytes()); // free_list_head
+    bytes.extend_from_slice(&root.to_le_bytes()); // root_node
+    bytes.extend_from_slice(&(keys.len() as u64).to_le_bytes()); // leaf_count
+    for node in nodes {
+        bytes.extend_from_slice(&node);
+    }
+
+    bytes
+}
+
+/// Builds the subtree for the given sorted keys and returns the handle of its root.
+fn build_node(keys: &[u128], nodes: &mut Vec<[u8; NODE_SIZE]>) -> u32 {
+    if keys.len() == 1 {
+        let key = keys[0];
+        let leaf = LeafNode::new(
+            (key % 128) as u8,
+            key,
+            [key as u64, 0, 0, 0],
+            (key as u64 % 1_000) + 1,
+            FeeTier::Base,
+            key as u64,
+        );
+        let mut node = [0_u8; NODE_SIZE];
+        node.copy_from_slice(bytes_of(&leaf));
+        nodes.push(node);
+        return (nodes.len() - 1) as u32;
+    }
+
+    // the keys are sorted, so the highest bit in which any of them differ is the one where the first and last differ
+    let first = keys[0];
+    let last = keys[keys.len() - 1];
+    let prefix_len = (first ^ last).leading_zeros();
+    let crit_bit_mask = (1_u128 << 127) >> prefix_len;
+    let split = keys.iter().position(|k| k & crit_bit_mask != 0).unwrap();
+
+    let left = build_node(&keys[..split], nodes);
+    let right = build_node(&keys[split..], nodes);
+
+    let mut node = [0_u8; NODE_SIZE];
+    node[0..4].copy_from_slice(&INNER_NODE_TAG.to_le_bytes());
+    node[4..8].copy_from_slice(&prefix_len.to_le_bytes());
+    node[8..24].copy_from_slice(&first.to_le_bytes());
+    node[24..28].copy_from_slice(&left.to_le_bytes());
+    node[28..32].copy_from_slice(&right.to_le_bytes());
+    nodes.push(node);
+    (nodes.len() - 1) as u32
+}
@@ -1,45 +1,219 @@
 use {
+    crate::CypherInteractiveError,
     serde::{Deserialize, Serialize},
     serde_json,
-    std::{error::Error, fs::File, io::BufReader},
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fs::File,
+        io::BufReader,
+        path::Path,
+        str::FromStr,
+    },
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+pub const LOCALNET_RPC_URL: &str = "http://127.0.0.1:8899";
+pub const LOCALNET_PUBSUB_URL: &str = "ws://127.0.0.1:8900";
+const EXPLORER_TX_URL: &str = "https://explorer.solana.com/tx";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CypherConfig {
-    pub clusters: Clusters,
+    /// Clusters keyed by the name given with `--cluster`.
+    pub clusters: BTreeMap<String, ClusterConfig>,
     pub groups: Vec<CypherGroupConfig>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct Clusters {
-    pub devnet: ClusterConfig,
-    pub mainnet: ClusterConfig,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClusterConfig {
     pub rpc_url: String,
     pub pubsub_url: String,
 }
 
+/// The cluster a session runs against, either one from the config or an RPC URL given on the command line.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub name: String,
+    pub config: ClusterConfig,
+}
+
+impl Cluster {
+    /// Links the transaction on the explorer, clusters the explorer does not know are passed as a custom URL.
+    pub fn get_explorer_tx_url(&self, signature: &Signature) -> String {
+        match self.name.as_str() {
+            "mainnet" | "mainnet-beta" => format!("{}/{}", EXPLORER_TX_URL, signature),
+            "devnet" | "testnet" => {
+                format!("{}/{}?cluster={}", EXPLORER_TX_URL, signature, self.name)
+            }
+            _ => format!(
+                "{}/{}?cluster=custom&customUrl={}",
+                EXPLORER_TX_URL,
+                signature,
+                encode_query_value(&self.config.rpc_url)
+            ),
+        }
+    }
+}
+
+fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::new();
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Derives the websocket URL the way the solana cli does, swapping the scheme and using the next port if one is given.
+fn get_pubsub_url(rpc_url: &str) -> String {
+    let (scheme, rest) = match rpc_url.split_once("://") {
+        Some(("https", r)) => ("wss", r),
+        Some((_, r)) => ("ws", r),
+        None => ("ws", rpc_url),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let host = match host.rsplit_once(':') {
+        Some((h, port)) => match port.parse::<u16>() {
+            Ok(p) => format!("{}:{}", h, p.saturating_add(1)),
+            Err(_) => host.to_string(),
+        },
+        None => host.to_string(),
+    };
+
+    format!("{}://{}{}", scheme, host, path)
+}
+
 impl CypherConfig {
-    pub fn get_config_for_cluster(&self, cluster: &str) -> &ClusterConfig {
-        match cluster {
-            "devnet" => &self.clusters.devnet,
-            "mainnet" => &self.clusters.mainnet,
-            "" => &self.clusters.devnet,
-            &_ => &self.clusters.devnet,
+    /// Resolves `--cluster`, which is a cluster name from the config, `localnet` or an RPC URL.
+    ///
+    /// Unknown names are an error rather than falling back to another cluster.
+    pub fn get_cluster(&self, cluster: &str) -> Result<Cluster, CypherInteractiveError> {
+        if let Some(config) = self.clusters.get(cluster) {
+            return Ok(Cluster {
+                name: cluster.to_string(),
+                config: config.clone(),
+            });
+        }
+
+        if cluster == "localnet" {
+            return Ok(Cluster {
+                name: cluster.to_string(),
+                config: ClusterConfig {
+                    rpc_url: LOCALNET_RPC_URL.to_string(),
+                    pubsub_url: LOCALNET_PUBSUB_URL.to_string(),
+                },
+            });
+        }
+
+        if cluster.starts_with("http://") || cluster.starts_with("https://") {
+            return Ok(Cluster {
+                name: "custom".to_string(),
+                config: ClusterConfig {
+                    rpc_url: cluster.to_string(),
+                    pubsub_url: get_pubsub_url(cluster),
+                },
+            });
+        }
+
+        Err(CypherInteractiveError::UnknownCluster(cluster.to_string()))
+    }
+
+    pub fn get_cluster_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.clusters.keys().cloned().collect();
+        if !self.clusters.contains_key("localnet") {
+            names.push("localnet".to_string());
         }
+        names
     }
 
     pub fn get_group(&self, cluster: &str) -> Option<&CypherGroupConfig> {
         self.groups.iter().find(|&g| g.name.as_str() == cluster)
     }
+
+    /// Replaces the group with the same name, as after syncing it from chain.
+    pub fn set_group(&mut self, group: CypherGroupConfig) {
+        match self.groups.iter_mut().find(|g| g.name == group.name) {
+            Some(g) => *g = group,
+            None => self.groups.push(group),
+        }
+    }
+
+    /// Checks that every address in the config parses and that market indices are unique within each group,
+    /// reporting every problem found rather than only the first.
+    pub fn validate(&self) -> Result<(), CypherInteractiveError> {
+        let mut problems: Vec<String> = Vec::new();
+
+        for group in &self.groups {
+            let g = format!("group {}", group.name);
+            if !self.get_cluster_names().contains(&group.cluster) {
+                problems.push(format!("{}: unknown cluster {}", g, group.cluster));
+            }
+            check_pubkey(&mut problems, &g, "address", &group.address);
+            check_pubkey(&mut problems, &g, "programId", &group.program_id);
+            // left empty until the group is synced from chain
+            if !group.serum_program_id.is_empty() {
+                check_pubkey(&mut problems, &g, "serumProgramId", &group.serum_program_id);
+            }
+            for token in &group.tokens {
+                check_pubkey(
+                    &mut problems,
+                    &format!("{} token {}", g, token.symbol),
+                    "mint",
+                    &token.mint,
+                );
+            }
+            for oracle in &group.oracles {
+                check_pubkey(
+                    &mut problems,
+                    &format!("{} oracle {}", g, oracle.symbol),
+                    "address",
+                    &oracle.address,
+                );
+            }
+
+            let mut market_indices: HashMap<usize, &str> = HashMap::new();
+            for market in &group.markets {
+                let m = format!("{} market {}", g, market.name);
+                check_pubkey(&mut problems, &m, "address", &market.address);
+                check_pubkey(&mut problems, &m, "bids", &market.bids);
+                check_pubkey(&mut problems, &m, "asks", &market.asks);
+                check_pubkey(&mut problems, &m, "eventQueue", &market.event_queue);
+                if let Some(other) = market_indices.insert(market.market_index, &market.name) {
+                    problems.push(format!(
+                        "{}: marketIndex {} is also used by market {}",
+                        m, market.market_index, other
+                    ));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CypherInteractiveError::ConfigInvalid(problems))
+        }
+    }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+fn check_pubkey(problems: &mut Vec<String>, owner: &str, field: &str, value: &str) {
+    if Pubkey::from_str(value).is_err() {
+        problems.push(format!(
+            "{}: {} '{}' is not a valid pubkey",
+            owner, field, value
+        ));
+    }
+}
+
+/// A group in the config, only the cluster, name, address and program are needed when the rest is
+/// filled in with `sync-config` or `--sync-config`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CypherGroupConfig {
     pub cluster: String,
@@ -47,9 +221,13 @@ pub struct CypherGroupConfig {
     pub quote_symbol: String,
     pub address: String,
     pub program_id: String,
+    #[serde(default)]
     pub serum_program_id: String,
+    #[serde(default)]
     pub tokens: Vec<CypherTokenConfig>,
+    #[serde(default)]
     pub oracles: Vec<CypherOracleConfig>,
+    #[serde(default)]
     pub markets: Vec<CypherMarketConfig>,
 }
 
@@ -59,19 +237,19 @@ impl CypherGroupConfig {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CypherTokenConfig {
     pub symbol: String,
     pub mint: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CypherOracleConfig {
     pub symbol: String,
     pub address: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct CypherMarketConfig {
     pub name: String,
@@ -89,9 +267,21 @@ pub struct CypherMarketConfig {
     pub event_queue: String,
 }
 
-pub fn load_cypher_config(path: &str) -> Result<CypherConfig, Box<dyn Error>> {
-    let file = File::open(path)?;
+pub fn load_cypher_config(path: &Path) -> Result<CypherConfig, CypherInteractiveError> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("Failed to open config {}: {}", path.display(), e);
+            return Err(CypherInteractiveError::ConfigRead);
+        }
+    };
     let reader = BufReader::new(file);
-    let cypher_config: CypherConfig = serde_json::from_reader(reader).unwrap();
-    Ok(cypher_config)
+    match serde_json::from_reader(reader) {
+        Ok(c) => Ok(c),
+        Err(e) => Err(CypherInteractiveError::ConfigParse(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))),
+    }
 }
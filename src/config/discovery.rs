@@ -0,0 +1,173 @@
+use {
+    super::{load_cypher_config, ClusterConfig, CypherConfig, CypherGroupConfig},
+    crate::{CypherInteractiveError, CYPHER_CONFIG_PATH},
+    serde::Deserialize,
+    std::{
+        collections::HashMap,
+        env,
+        fs::File,
+        io::BufReader,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Points at the group config when `--config` is not given.
+pub const CYPHER_CONFIG_ENV_VAR: &str = "CYPHER_CONFIG";
+/// Points at the overlay when `--config-overlay` is not given.
+pub const CYPHER_CONFIG_OVERLAY_ENV_VAR: &str = "CYPHER_CONFIG_OVERLAY";
+const CONFIG_DIR_NAME: &str = "cypher-interactive";
+const CONFIG_FILE_NAME: &str = "group.json";
+const OVERLAY_FILE_NAME: &str = "overlay.json";
+
+/// The per user config directory, `$XDG_CONFIG_HOME/cypher-interactive` or `~/.config/cypher-interactive`.
+pub fn get_user_config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(base.join(CONFIG_DIR_NAME))
+}
+
+/// Finds the group config, looking at the given path, `CYPHER_CONFIG`, `./cfg/group.json`
+/// and the user config directory, in that order.
+///
+/// An explicitly given path is used even if it does not exist, so the error names the file the user asked for.
+pub fn find_cypher_config(path: Option<&Path>) -> Result<PathBuf, CypherInteractiveError> {
+    if let Some(p) = path {
+        return Ok(p.to_path_buf());
+    }
+    if let Some(p) = env::var_os(CYPHER_CONFIG_ENV_VAR) {
+        return Ok(PathBuf::from(p));
+    }
+
+    let mut candidates = vec![PathBuf::from(CYPHER_CONFIG_PATH)];
+    if let Some(dir) = get_user_config_dir() {
+        candidates.push(dir.join(CONFIG_FILE_NAME));
+    }
+    match candidates.iter().find(|p| p.exists()) {
+        Some(p) => Ok(p.to_path_buf()),
+        None => Err(CypherInteractiveError::ConfigNotFound(candidates)),
+    }
+}
+
+/// Finds the user overlay, looking at the given path, `CYPHER_CONFIG_OVERLAY` and the user config directory.
+/// There is no overlay unless one of these is set or the file exists.
+pub fn find_config_overlay(path: Option<&Path>) -> Option<PathBuf> {
+    if let Some(p) = path {
+        return Some(p.to_path_buf());
+    }
+    if let Some(p) = env::var_os(CYPHER_CONFIG_OVERLAY_ENV_VAR) {
+        return Some(PathBuf::from(p));
+    }
+
+    get_user_config_dir()
+        .map(|d| d.join(OVERLAY_FILE_NAME))
+        .filter(|p| p.exists())
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterOverlay {
+    pub rpc_url: Option<String>,
+    pub pubsub_url: Option<String>,
+}
+
+/// User settings applied on top of the group config, so a private RPC endpoint or a custom group
+/// does not need a copy of the whole file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CypherConfigOverlay {
+    /// Endpoint overrides keyed by cluster name, a cluster which is not in the config yet is added.
+    #[serde(default)]
+    pub clusters: HashMap<String, ClusterOverlay>,
+    /// Groups added to the config, replacing any group with the same name.
+    #[serde(default)]
+    pub groups: Vec<CypherGroupConfig>,
+}
+
+pub fn load_config_overlay(path: &Path) -> Result<CypherConfigOverlay, CypherInteractiveError> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("Failed to open config overlay {}: {}", path.display(), e);
+            return Err(CypherInteractiveError::ConfigRead);
+        }
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(CypherInteractiveError::ConfigParse(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+impl CypherConfig {
+    pub fn apply_overlay(
+        &mut self,
+        overlay: CypherConfigOverlay,
+    ) -> Result<(), CypherInteractiveError> {
+        let mut problems: Vec<String> = Vec::new();
+
+        for (name, cluster_overlay) in overlay.clusters {
+            match self.clusters.get_mut(&name) {
+                Some(cluster) => {
+                    if let Some(url) = cluster_overlay.rpc_url {
+                        cluster.rpc_url = url;
+                    }
+                    if let Some(url) = cluster_overlay.pubsub_url {
+                        cluster.pubsub_url = url;
+                    }
+                }
+                None => match (cluster_overlay.rpc_url, cluster_overlay.pubsub_url) {
+                    (Some(rpc_url), Some(pubsub_url)) => {
+                        self.clusters.insert(
+                            name,
+                            ClusterConfig {
+                                rpc_url,
+                                pubsub_url,
+                            },
+                        );
+                    }
+                    _ => {
+                        problems.push(format!(
+                            "overlay: new cluster {} needs both an rpcUrl and a pubsubUrl",
+                            name
+                        ));
+                    }
+                },
+            }
+        }
+
+        for group in overlay.groups {
+            self.set_group(group);
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CypherInteractiveError::ConfigInvalid(problems))
+        }
+    }
+}
+
+/// Finds and loads the group config, applies the user overlay if there is one and validates the result.
+pub fn discover_cypher_config(
+    path: Option<&Path>,
+    overlay_path: Option<&Path>,
+) -> Result<CypherConfig, CypherInteractiveError> {
+    let config_path = find_cypher_config(path)?;
+    println!("Loading config from: {}", config_path.display());
+    let mut cypher_config = load_cypher_config(&config_path)?;
+
+    if let Some(p) = find_config_overlay(overlay_path) {
+        println!("Applying config overlay from: {}", p.display());
+        cypher_config.apply_overlay(load_config_overlay(&p)?)?;
+    }
+
+    cypher_config.validate()?;
+
+    Ok(cypher_config)
+}
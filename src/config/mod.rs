@@ -1,3 +1,7 @@
 pub mod cypher_config;
+pub mod discovery;
+pub mod sync;
 
 pub use cypher_config::*;
+pub use discovery::*;
+pub use sync::*;
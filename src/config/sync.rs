@@ -0,0 +1,197 @@
+use {
+    super::{CypherGroupConfig, CypherMarketConfig, CypherTokenConfig},
+    crate::{rpc::RpcBackend, CypherInteractiveError},
+    cypher::{
+        client::ToPubkey,
+        constants::QUOTE_TOKEN_IDX,
+        utils::{get_zero_copy_account, parse_dex_account},
+        CypherGroup,
+    },
+    serum_dex::state::MarketStateV2,
+    solana_sdk::pubkey::Pubkey,
+    std::{convert::identity, sync::Arc},
+};
+
+/// The markets a sync added, changed or dropped, by name.
+#[derive(Debug, Default)]
+pub struct ConfigSyncReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ConfigSyncReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No markets were added, changed or removed.");
+            return;
+        }
+        for name in &self.added {
+            println!("\tAdded market {}.", name);
+        }
+        for name in &self.updated {
+            println!("\tUpdated market {}.", name);
+        }
+        for name in &self.removed {
+            println!(
+                "\tRemoved market {}, it is no longer listed in the group.",
+                name
+            );
+        }
+    }
+}
+
+/// Fetches the cypher group account, for when the group provider is not running yet.
+pub async fn fetch_cypher_group(
+    cypher_group_pubkey: &Pubkey,
+    rpc_client: Arc<dyn RpcBackend>,
+) -> Result<Box<CypherGroup>, CypherInteractiveError> {
+    match rpc_client.get_account(cypher_group_pubkey).await {
+        Ok(Some(a)) => Ok(get_zero_copy_account::<CypherGroup>(&a)),
+        Ok(None) => Err(CypherInteractiveError::CypherGroupNotFound),
+        Err(e) => Err(CypherInteractiveError::CouldNotFetchCypherGroup(e)),
+    }
+}
+
+/// Builds the group config from the markets listed in the group account and their dex markets.
+///
+/// Names and symbols are not on chain, so markets and tokens which are already in the config keep theirs,
+/// new ones are named after their market index until they are renamed in the config or an overlay.
+/// The report only lists markets, compare the returned config with the given one to see whether anything changed.
+pub async fn sync_group_config(
+    group: &CypherGroup,
+    group_config: &CypherGroupConfig,
+    rpc_client: Arc<dyn RpcBackend>,
+) -> Result<(CypherGroupConfig, ConfigSyncReport), CypherInteractiveError> {
+    // c-assets share their index with their market, the quote token takes the slot after the last market
+    let mut listed: Vec<(usize, Pubkey)> = Vec::new();
+    for market_index in 0..QUOTE_TOKEN_IDX {
+        if let Some(cypher_market) = group.get_cypher_market(market_index) {
+            if cypher_market.dex_market != Pubkey::default() {
+                listed.push((market_index, cypher_market.dex_market));
+            }
+        }
+    }
+
+    let dex_market_pks: Vec<Pubkey> = listed.iter().map(|(_, pk)| *pk).collect();
+    let dex_market_accounts = match rpc_client.get_multiple_accounts(&dex_market_pks).await {
+        Ok((_, a)) => a,
+        Err(e) => {
+            return Err(CypherInteractiveError::ConfigSync(format!(
+                "could not fetch the dex markets: {}",
+                e
+            )));
+        }
+    };
+
+    let quote_token = match group.get_cypher_token(QUOTE_TOKEN_IDX) {
+        Some(t) => t,
+        None => {
+            return Err(CypherInteractiveError::ConfigSync(
+                "the group has no quote token".to_string(),
+            ));
+        }
+    };
+    let quote_symbol = group_config.quote_symbol.to_string();
+    let mut tokens: Vec<CypherTokenConfig> = Vec::new();
+    let mut markets: Vec<CypherMarketConfig> = Vec::new();
+    let mut serum_program_id = group_config.serum_program_id.to_string();
+    let mut report = ConfigSyncReport::default();
+
+    for ((market_index, dex_market_pk), account) in listed.iter().zip(dex_market_accounts) {
+        let account = match account {
+            Some(a) => a,
+            None => {
+                return Err(CypherInteractiveError::ConfigSync(format!(
+                    "dex market {} of market index {} does not exist",
+                    dex_market_pk, market_index
+                )));
+            }
+        };
+        serum_program_id = account.owner.to_string();
+        let dex_market: MarketStateV2 = parse_dex_account(account.data);
+        let cypher_token = match group.get_cypher_token(*market_index) {
+            Some(t) => t,
+            None => {
+                return Err(CypherInteractiveError::ConfigSync(format!(
+                    "market index {} has no token",
+                    market_index
+                )));
+            }
+        };
+        let mint = cypher_token.mint.to_string();
+
+        let existing = group_config
+            .markets
+            .iter()
+            .find(|m| m.market_index == *market_index);
+        let base_symbol = match group_config.tokens.iter().find(|t| t.mint == mint) {
+            Some(t) => t.symbol.to_string(),
+            None => match existing {
+                Some(m) => m.base_symbol.to_string(),
+                None => format!("MARKET{}", market_index),
+            },
+        };
+        let mut market = match existing {
+            Some(m) => m.clone(),
+            None => CypherMarketConfig {
+                name: base_symbol.to_string(),
+                base_symbol: base_symbol.to_string(),
+                quote_symbol: quote_symbol.to_string(),
+                market_type: "Pair".to_string(),
+                pair_base_symbol: base_symbol.to_string(),
+                pair_quote_symbol: quote_symbol.to_string(),
+                market_index: *market_index,
+                ..Default::default()
+            },
+        };
+        market.address = dex_market_pk.to_string();
+        market.bids = identity(dex_market.bids).to_pubkey().to_string();
+        market.asks = identity(dex_market.asks).to_pubkey().to_string();
+        market.event_queue = identity(dex_market.event_q).to_pubkey().to_string();
+        market.base_decimals = cypher_token.decimals;
+        market.quote_decimals = quote_token.decimals;
+
+        match existing {
+            Some(m) if *m == market => (),
+            Some(_) => report.updated.push(market.name.to_string()),
+            None => report.added.push(market.name.to_string()),
+        }
+        tokens.push(CypherTokenConfig {
+            symbol: base_symbol,
+            mint,
+        });
+        markets.push(market);
+    }
+
+    for market in &group_config.markets {
+        if !markets
+            .iter()
+            .any(|m| m.market_index == market.market_index)
+        {
+            report.removed.push(market.name.to_string());
+        }
+    }
+    tokens.push(CypherTokenConfig {
+        symbol: quote_symbol.to_string(),
+        mint: quote_token.mint.to_string(),
+    });
+
+    let synced = CypherGroupConfig {
+        cluster: group_config.cluster.to_string(),
+        name: group_config.name.to_string(),
+        quote_symbol,
+        address: group_config.address.to_string(),
+        program_id: group_config.program_id.to_string(),
+        serum_program_id,
+        tokens,
+        oracles: group_config.oracles.clone(),
+        markets,
+    };
+
+    Ok((synced, report))
+}
@@ -1,6 +1,6 @@
 use solana_sdk::{
     hash::Hash, instruction::Instruction, message::Message, signature::Keypair, signer::Signer,
-    transaction::Transaction,
+    signer::SignerError, transaction::Transaction,
 };
 
 #[derive(Debug, Default)]
@@ -28,20 +28,22 @@ impl FastTxnBuilder {
         self.ixs.clear();
     }
 
+    /// Builds and signs the transaction, signing fails if a remote signer can not be reached.
     pub fn build(
         &self,
         recent_blockhash: Hash,
-        payer: &Keypair,
+        payer: &dyn Signer,
         additional_signers: Option<&Vec<Keypair>>,
-    ) -> Transaction {
-        let message = Message::new(&self.ixs[..], Some(&payer.pubkey()));
+    ) -> Result<Transaction, SignerError> {
+        let message = Message::new(&self.ixs[..], Some(&payer.try_pubkey()?));
         let mut txn = Transaction::new_unsigned(message);
-        txn.partial_sign(&[payer], recent_blockhash);
+        let payer_signers: Vec<&dyn Signer> = vec![payer];
+        txn.try_partial_sign(&payer_signers, recent_blockhash)?;
         if let Some(adsigners) = additional_signers {
             for adsigner in adsigners {
-                txn.partial_sign(&[adsigner], recent_blockhash);
+                txn.try_partial_sign(&[adsigner], recent_blockhash)?;
             }
         }
-        txn
+        Ok(txn)
     }
 }
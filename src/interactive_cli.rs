@@ -1,47 +1,78 @@
 use std::{
-    io::{self, Write},
+    collections::HashSet,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
+    thread,
+    time::Duration,
 };
 
 use cypher::{
-    constants::QUOTE_TOKEN_IDX, utils::derive_open_orders_address, CypherGroup, CypherUser,
+    constants::QUOTE_TOKEN_IDX,
+    utils::{
+        derive_cypher_user_address, derive_cypher_user_address_with_number,
+        derive_open_orders_address, parse_dex_account,
+    },
+    CypherGroup, CypherUser,
 };
 use jet_proto_math::Number;
-use serum_dex::matching::Side;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use serum_dex::{matching::Side, state::OpenOrders};
+use solana_sdk::{
+    native_token::lamports_to_sol, pubkey::Pubkey, signature::Keypair, signer::Signer,
+};
 use tokio::{
     select,
-    sync::broadcast::{channel, Sender},
+    sync::{
+        broadcast::{channel, Sender},
+        mpsc::{unbounded_channel, UnboundedReceiver},
+    },
     task::JoinHandle,
+    time::{sleep, timeout_at, Instant},
 };
 
 use crate::{
     accounts_cache::AccountsCache,
-    config::CypherConfig,
+    config::{sync_group_config, Cluster, CypherConfig, CypherMarketConfig},
     cypher_context::CypherContext,
+    kill_switch::KillSwitch,
     market_handler::{
         CancelOrderInfo, Handler, HandlerContext, LimitOrderInfo, MarketContext, MarketOrderInfo,
     },
+    pnl::PnlTracker,
     providers::{
-        CypherAccountProvider, CypherGroupProvider, OpenOrdersContext, OpenOrdersProvider,
-        OrderBook, OrderBookContext, OrderBookProvider,
+        CypherAccountProvider, CypherGroupProvider, Fill, FillsContext, FillsProvider,
+        OpenOrdersContext, OpenOrdersProvider, OrderBook, OrderBookContext, OrderBookProvider,
     },
-    services::{AccountInfoService, ChainMetaService},
+    risk::RiskEngine,
+    rpc::RpcBackend,
+    services::{
+        read_recording, reset_recording, AccountInfoService, AccountRecorderService,
+        AccountReplayService, ChainMetaService, ReplayConfig,
+    },
+    trade_log::{TradeLog, TRADE_LOG_DIR},
     utils::{
-        create_cypher_user, deposit_quote_token, get_open_orders_with_qty, get_or_init_open_orders,
-        get_serum_market, request_airdrop, set_delegate,
+        close_open_orders, create_cypher_user, deposit_quote_token, derive_cypher_user_addresses,
+        get_open_orders, get_open_orders_with_qty, get_or_init_open_orders, get_serum_market,
+        native_price_to_ui, number_to_f64, request_airdrop, set_delegate, MarginCheck,
+        MarginSummary, MarketUnits, MAX_SUB_ACCOUNTS,
     },
     CypherInteractiveError,
 };
 
+/// How long the services get to stop once the session ends before they are aborted.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a market loaded on first use gets to receive its accounts before the command runs anyway.
+const MARKET_LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug, PartialEq, Clone)]
 enum InteractiveCommand {
     NewAccount(u64),
     Help,
     Airdrop,
     Delegate(String),
+    Undelegate,
+    DelegateStatus,
     Deposit(f64),
     MarketsStatus,
     TokensStatus,
@@ -50,21 +81,120 @@ enum InteractiveCommand {
     Limit(LimitOrderInfo),
     Market(MarketOrderInfo),
     Cancel(CancelOrderInfo),
+    Trades(TradesInfo),
+    Pnl,
+    Panic(bool),
+    Unlock,
+    Accounts,
+    Use(Option<u64>),
+    SyncConfig,
+    Load(String),
+    CloseOpenOrders(CloseOpenOrdersInfo),
     Exit,
 }
 
+impl InteractiveCommand {
+    /// The market the command trades or reads, which gets loaded first if it is not yet.
+    fn get_market(&self) -> Option<&str> {
+        match self {
+            InteractiveCommand::OrderBookStatus(info) => Some(&info.symbol),
+            InteractiveCommand::Limit(info) => Some(&info.symbol),
+            InteractiveCommand::Market(info) => Some(&info.symbol),
+            InteractiveCommand::Cancel(info) => Some(&info.symbol),
+            _ => None,
+        }
+    }
+
+    /// Whether the command signs and sends transactions, which must not happen while replaying a recording.
+    fn sends_transaction(&self) -> bool {
+        matches!(
+            self,
+            InteractiveCommand::NewAccount(_)
+                | InteractiveCommand::Airdrop
+                | InteractiveCommand::Delegate(_)
+                | InteractiveCommand::Undelegate
+                | InteractiveCommand::Deposit(_)
+                | InteractiveCommand::Limit(_)
+                | InteractiveCommand::Market(_)
+                | InteractiveCommand::Cancel(_)
+                | InteractiveCommand::Panic(_)
+                | InteractiveCommand::Load(_)
+                | InteractiveCommand::CloseOpenOrders(_)
+        )
+    }
+
+    /// Whether the command is signed with the owner keypair, the program rejects these from a delegate.
+    fn requires_owner(&self) -> bool {
+        matches!(
+            self,
+            InteractiveCommand::NewAccount(_)
+                | InteractiveCommand::Airdrop
+                | InteractiveCommand::Delegate(_)
+                | InteractiveCommand::Undelegate
+                | InteractiveCommand::Deposit(_)
+                | InteractiveCommand::CloseOpenOrders(_)
+        )
+    }
+
+    /// Whether the command places or cancels orders, which both the owner and the delegate may sign.
+    fn trades(&self) -> bool {
+        matches!(
+            self,
+            InteractiveCommand::Limit(_)
+                | InteractiveCommand::Market(_)
+                | InteractiveCommand::Cancel(_)
+                | InteractiveCommand::Panic(_)
+        )
+    }
+}
+
+/// What the loaded signer is allowed to do on the active account.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum SignerRole {
+    Owner,
+    Delegate,
+    /// Neither the owner nor the delegate, the program rejects everything it signs.
+    Unauthorized,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct OrderBookInfo {
     symbol: String,
     depth: usize,
+    l3: bool,
+    tick: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct CloseOpenOrdersInfo {
+    /// `None` closes the open orders accounts of every market in the group.
+    symbol: Option<String>,
+    settle: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct TradesInfo {
+    symbol: Option<String>,
+    count: usize,
+}
+
+/// Everything the services and providers need to start tracking a market, along with its handler.
+struct PreparedMarket {
+    ob_ctx: OrderBookContext,
+    fills_ctx: FillsContext,
+    open_orders_pk: Pubkey,
+    ais_pks: Vec<Pubkey>,
+    handler: Arc<Handler>,
 }
 
 pub struct InteractiveCli {
     cypher_config: Arc<CypherConfig>,
-    cluster: String,
+    cluster: Cluster,
     group: String,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
     shutdown: Sender<bool>,
+    /// Stops the services, providers and handlers of the active account without ending the session.
+    services_shutdown: Sender<bool>,
     ai_service: Arc<AccountInfoService>,
     cm_service: Arc<ChainMetaService>,
     accounts_cache: Arc<AccountsCache>,
@@ -74,12 +204,36 @@ pub struct InteractiveCli {
     cypher_group_provider: Arc<CypherGroupProvider>,
     cypher_group_provider_sender: Arc<Sender<Box<CypherGroup>>>,
     open_orders_provider: Arc<OpenOrdersProvider>,
+    open_orders_sender: Arc<Sender<OpenOrdersContext>>,
     orderbook_provider: Arc<OrderBookProvider>,
+    orderbook_sender: Arc<Sender<Arc<OrderBook>>>,
+    fills_provider: Arc<FillsProvider>,
+    fills_sender: Arc<Sender<Fill>>,
+    trade_log: Arc<TradeLog>,
+    pnl_tracker: Arc<PnlTracker>,
     handlers: Vec<Arc<Handler>>,
+    /// The markets which get a handler, `None` loads every market in the group.
+    active_markets: Option<HashSet<String>>,
+    /// Active markets whose serum market or open orders account could not be prepared, retried when first used.
+    failed_markets: HashSet<String>,
     cypher_context: Arc<CypherContext>,
-    keypair: Arc<Keypair>,
+    /// Signs orders, cancels and open orders accounts, either the owner or the account's delegate.
+    keypair: Arc<dyn Signer + Send + Sync>,
+    /// Signs owner-only instructions, not available when the session runs with a delegate key only.
+    owner_keypair: Option<Arc<Keypair>>,
+    owner_pk: Pubkey,
     cypher_user_pk: Pubkey,
+    /// The number of the active sub-account, `None` for the owner's default account.
+    account_number: Option<u64>,
     cypher_group_pk: Pubkey,
+    own_open_orders_pks: HashSet<Pubkey>,
+    record_path: Option<PathBuf>,
+    replay: Option<ReplayConfig>,
+    margin_check: MarginCheck,
+    risk_engine: Arc<RiskEngine>,
+    kill_switch: Arc<KillSwitch>,
+    recorder_service: Option<Arc<AccountRecorderService>>,
+    replay_service: Option<Arc<AccountReplayService>>,
     tasks: Vec<JoinHandle<()>>,
 }
 
@@ -87,13 +241,21 @@ impl InteractiveCli {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         cypher_config: Arc<CypherConfig>,
-        cluster: String,
+        cluster: Cluster,
         group: String,
-        rpc_client: Arc<RpcClient>,
+        rpc_client: Arc<dyn RpcBackend>,
         shutdown: Sender<bool>,
-        keypair: Arc<Keypair>,
+        keypair: Arc<dyn Signer + Send + Sync>,
+        owner_keypair: Option<Arc<Keypair>>,
+        owner_pk: Pubkey,
         cypher_user_pk: Pubkey,
         cypher_group_pk: Pubkey,
+        record_path: Option<PathBuf>,
+        replay: Option<ReplayConfig>,
+        margin_check: MarginCheck,
+        risk_engine: Arc<RiskEngine>,
+        kill_switch: Arc<KillSwitch>,
+        markets: Option<Vec<String>>,
     ) -> Self {
         Self {
             cypher_config,
@@ -101,9 +263,21 @@ impl InteractiveCli {
             group,
             rpc_client,
             shutdown,
+            services_shutdown: channel::<bool>(1).0,
             keypair,
+            owner_keypair,
+            owner_pk,
             cypher_user_pk,
+            account_number: None,
             cypher_group_pk,
+            own_open_orders_pks: HashSet::new(),
+            record_path,
+            replay,
+            margin_check,
+            risk_engine,
+            kill_switch,
+            recorder_service: None,
+            replay_service: None,
             cm_service: Arc::new(ChainMetaService::default()),
             ai_service: Arc::new(AccountInfoService::default()),
             accounts_cache: Arc::new(AccountsCache::default()),
@@ -115,14 +289,34 @@ impl InteractiveCli {
                 channel::<Box<CypherGroup>>(u16::MAX as usize).0,
             ),
             open_orders_provider: Arc::new(OpenOrdersProvider::default()),
+            open_orders_sender: Arc::new(channel::<OpenOrdersContext>(u16::MAX as usize).0),
             orderbook_provider: Arc::new(OrderBookProvider::default()),
+            orderbook_sender: Arc::new(channel::<Arc<OrderBook>>(u16::MAX as usize).0),
+            fills_provider: Arc::new(FillsProvider::default()),
+            fills_sender: Arc::new(channel::<Fill>(u16::MAX as usize).0),
+            trade_log: Arc::new(TradeLog::default()),
+            pnl_tracker: Arc::new(PnlTracker::default()),
             handlers: Vec::new(),
+            active_markets: markets.map(|m| m.into_iter().collect()),
+            failed_markets: HashSet::new(),
             cypher_context: Arc::new(CypherContext::default()),
             tasks: Vec::new(),
         }
     }
 
     pub async fn start(mut self) -> Result<(), CypherInteractiveError> {
+        // the recorder appends across service restarts, the recording only starts over with the session
+        if let Some(record_path) = &self.record_path {
+            if let Err(e) = reset_recording(record_path) {
+                println!(
+                    "An error occurred while creating the recording at {}: {:?}",
+                    record_path.display(),
+                    e
+                );
+                return Err(e);
+            }
+        }
+
         // launch all necessary services to operate in all available markets
         match self.start_services().await {
             Ok(_) => (),
@@ -135,18 +329,55 @@ impl InteractiveCli {
             }
         }
 
-        let ai_service = Arc::clone(&self.ai_service);
-        // start the services
-        let ai_t = tokio::spawn(async move {
-            ai_service.start_service().await;
-        });
-        self.tasks.push(ai_t);
+        self.spawn_tasks();
 
-        let cm_service = Arc::clone(&self.cm_service);
-        let cm_t = tokio::spawn(async move {
-            cm_service.start_service().await;
-        });
-        self.tasks.push(cm_t);
+        match self.run().await {
+            Ok(_) => (),
+            Err(e) => {
+                println!(
+                    "There was an error while running the interactive command line: {:?}",
+                    e
+                );
+            }
+        }
+
+        self.shutdown_gracefully().await;
+
+        Ok(())
+    }
+
+    fn spawn_tasks(&mut self) {
+        // start the services, when replaying a recording it takes the place of the rpc backed ones
+        match &self.replay_service {
+            Some(replay_service) => {
+                let replay_service = Arc::clone(replay_service);
+                let rp_t = tokio::spawn(async move {
+                    replay_service.start_service().await;
+                });
+                self.tasks.push(rp_t);
+            }
+            None => {
+                let ai_service = Arc::clone(&self.ai_service);
+                let ai_t = tokio::spawn(async move {
+                    ai_service.start_service().await;
+                });
+                self.tasks.push(ai_t);
+
+                let cm_service = Arc::clone(&self.cm_service);
+                let cm_t = tokio::spawn(async move {
+                    cm_service.start_service().await;
+                });
+                self.tasks.push(cm_t);
+            }
+        }
+
+        if let Some(recorder_service) = &self.recorder_service {
+            let recorder_service = Arc::clone(recorder_service);
+            let rc_t = tokio::spawn(async move {
+                recorder_service.start_service().await;
+            });
+            self.tasks.push(rc_t);
+        }
 
         let cgp = Arc::clone(&self.cypher_group_provider);
         let cg_t = tokio::spawn(async move {
@@ -172,38 +403,66 @@ impl InteractiveCli {
         });
         self.tasks.push(oop_t);
 
+        let fp = Arc::clone(&self.fills_provider);
+        let fp_t = tokio::spawn(async move {
+            fp.start().await;
+        });
+        self.tasks.push(fp_t);
+
+        let tl = Arc::clone(&self.trade_log);
+        let tl_t = tokio::spawn(async move {
+            tl.start().await;
+        });
+        self.tasks.push(tl_t);
+
+        let pnl = Arc::clone(&self.pnl_tracker);
+        let pnl_t = tokio::spawn(async move {
+            pnl.start().await;
+        });
+        self.tasks.push(pnl_t);
+
         let cc = Arc::clone(&self.cypher_context);
         let cc_t = tokio::spawn(async move {
             cc.start().await;
         });
         self.tasks.push(cc_t);
 
+        let re = Arc::clone(&self.risk_engine);
+        let re_fills = self.fills_sender.subscribe();
+        let re_shutdown = self.services_shutdown.subscribe();
+        let re_t = tokio::spawn(async move {
+            re.watch_fills(re_fills, re_shutdown).await;
+        });
+        self.tasks.push(re_t);
+
         for handler in self.handlers.clone() {
             let t = tokio::spawn(async move {
                 handler.start().await;
             });
             self.tasks.push(t);
         }
-
-        match self.run().await {
-            Ok(_) => (),
-            Err(e) => {
-                println!(
-                    "There was an error while running the interactive command line: {:?}",
-                    e
-                );
-            }
-        }
-
-        Ok(())
     }
 
     async fn start_services(&mut self) -> Result<(), CypherInteractiveError> {
         let mut ais_pks: Vec<Pubkey> = Vec::new();
         let mut ob_ctxs: Vec<OrderBookContext> = Vec::new();
         let mut open_orders_pks: Vec<Pubkey> = Vec::new();
+        let mut fills_ctxs: Vec<FillsContext> = Vec::new();
 
-        let group_config = self.cypher_config.get_group(&self.group).unwrap();
+        // an owned handle on the config, so markets can be prepared while it is borrowed
+        let cypher_config = Arc::clone(&self.cypher_config);
+        let group_config = cypher_config.get_group(&self.group).unwrap();
+        self.handlers.clear();
+        if let Some(replay) = &self.replay {
+            println!("Loading recording from {}.", replay.path.display());
+            self.replay_service = Some(Arc::new(AccountReplayService::new(
+                Arc::clone(&self.accounts_cache),
+                read_recording(&replay.path)?,
+                replay.speed,
+                self.services_shutdown.subscribe(),
+            )));
+        }
+        let cypher_user_pks = derive_cypher_user_addresses(&self.cypher_group_pk, &self.owner_pk);
 
         // unbounded channel for the accounts cache to send messages whenever a given account gets updated
         let (accounts_cache_s, _) = channel::<Pubkey>(u16::MAX as usize);
@@ -212,7 +471,7 @@ impl InteractiveCli {
 
         self.cm_service = Arc::new(ChainMetaService::new(
             Arc::clone(&self.rpc_client),
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
         ));
 
         let (ca_s, _) = channel::<Box<CypherUser>>(u16::MAX as usize);
@@ -222,7 +481,7 @@ impl InteractiveCli {
             Arc::clone(&self.accounts_cache),
             Arc::clone(&arc_ca_s),
             self.accounts_cache_sender.subscribe(),
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
             self.cypher_user_pk,
         ));
 
@@ -233,7 +492,7 @@ impl InteractiveCli {
             Arc::clone(&self.accounts_cache),
             Arc::clone(&arc_cg_s),
             self.accounts_cache_sender.subscribe(),
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
             self.cypher_group_pk,
         ));
 
@@ -243,85 +502,40 @@ impl InteractiveCli {
         let (oo_s, _) = channel::<OpenOrdersContext>(u16::MAX as usize);
         let arc_oo_s = Arc::new(oo_s);
 
-        for market in &group_config.markets {
-            let dex_market_bids = Pubkey::from_str(market.bids.as_str()).unwrap();
-            let dex_market_asks = Pubkey::from_str(market.asks.as_str()).unwrap();
-            let dex_market_pk = Pubkey::from_str(&market.address).unwrap();
-            let dex_market_account = match get_serum_market(
-                Arc::clone(&self.rpc_client),
-                dex_market_pk,
-            )
-            .await
-            {
-                Ok(m) => m,
-                Err(e) => {
-                    println!("An error occurred while fetching the serum market account for {}. Ignoring market. Error: {}", market.name, e);
-                    continue;
-                }
-            };
+        let (fill_s, _) = channel::<Fill>(u16::MAX as usize);
+        let arc_fill_s = Arc::new(fill_s);
 
-            let open_orders_pk = derive_open_orders_address(&dex_market_pk, &self.cypher_user_pk).0;
-            open_orders_pks.push(open_orders_pk);
+        self.orderbook_sender = Arc::clone(&arc_ob_s);
+        self.open_orders_sender = Arc::clone(&arc_oo_s);
+        self.fills_sender = Arc::clone(&arc_fill_s);
 
-            let _ooa = match get_or_init_open_orders(
-                &self.keypair,
-                &self.cypher_group_pk,
-                &self.cypher_user_pk,
-                &dex_market_pk,
-                &open_orders_pk,
-                Arc::clone(&self.rpc_client),
-                self.cluster.to_string(),
-            )
-            .await
-            {
-                Ok(ooa) => ooa,
-                Err(e) => {
-                    println!("An error occurred while fetching or creating open orders account for {}. Ignoring market. Error: {:?}", market.name, e);
+        let mut unloaded_markets: Vec<String> = Vec::new();
+        self.failed_markets.clear();
+        for market in &group_config.markets {
+            if !self.is_market_active(&market.name) {
+                unloaded_markets.push(market.name.to_string());
+                continue;
+            }
+            let prepared = match self.prepare_market(market, &cypher_user_pks).await {
+                Some(p) => p,
+                None => {
+                    self.failed_markets.insert(market.name.to_string());
+                    unloaded_markets.push(market.name.to_string());
                     continue;
                 }
             };
-            println!(
-                "Preparing orderbook context for market {}. Market: {} Bids: {} Asks: {}.",
-                market.name, dex_market_pk, dex_market_bids, dex_market_asks
-            );
-            ob_ctxs.push(OrderBookContext {
-                market: dex_market_pk,
-                bids: dex_market_bids,
-                asks: dex_market_asks,
-                coin_lot_size: dex_market_account.coin_lot_size,
-                pc_lot_size: dex_market_account.pc_lot_size,
-            });
-
-            ais_pks.extend(vec![
-                dex_market_pk,
-                dex_market_bids,
-                dex_market_asks,
-                open_orders_pk,
-            ]);
-
-            println!("Preparing handler for market {}.", market.name);
-            self.handlers.push(Arc::new(Handler::new(
-                Box::new(MarketContext {
-                    name: market.name.to_string(),
-                    market_index: market.market_index,
-                    signer: Arc::clone(&self.keypair),
-                    cypher_user_pk: self.cypher_user_pk,
-                    dex_market_pk,
-                    open_orders_pk,
-                }),
-                Arc::clone(&self.rpc_client),
-                self.shutdown.subscribe(),
-                arc_oo_s.subscribe(),
-                arc_ob_s.subscribe(),
-                Some(dex_market_account),
-            )));
+            ob_ctxs.push(prepared.ob_ctx);
+            fills_ctxs.push(prepared.fills_ctx);
+            open_orders_pks.push(prepared.open_orders_pk);
+            ais_pks.extend(prepared.ais_pks);
+            self.handlers.push(prepared.handler);
         }
 
         self.orderbook_provider = Arc::new(OrderBookProvider::new(
             Arc::clone(&self.accounts_cache),
             arc_ob_s,
             self.accounts_cache_sender.subscribe(),
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
             ob_ctxs,
         ));
 
@@ -329,10 +543,32 @@ impl InteractiveCli {
             Arc::clone(&self.accounts_cache),
             arc_oo_s,
             self.accounts_cache_sender.subscribe(),
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
             open_orders_pks,
         ));
 
+        self.trade_log = Arc::new(TradeLog::new(
+            arc_fill_s.subscribe(),
+            self.services_shutdown.subscribe(),
+            Path::new(TRADE_LOG_DIR),
+        ));
+
+        let pnl_state_path =
+            Path::new(TRADE_LOG_DIR).join(format!("pnl-{}.json", self.cypher_user_pk));
+        self.pnl_tracker = Arc::new(PnlTracker::new(
+            arc_fill_s.subscribe(),
+            self.services_shutdown.subscribe(),
+            &pnl_state_path,
+        )?);
+
+        self.fills_provider = Arc::new(FillsProvider::new(
+            Arc::clone(&self.accounts_cache),
+            arc_fill_s,
+            self.accounts_cache_sender.subscribe(),
+            self.services_shutdown.subscribe(),
+            fills_ctxs,
+        ));
+
         ais_pks.push(self.cypher_group_pk);
         ais_pks.push(self.cypher_user_pk);
 
@@ -340,47 +576,184 @@ impl InteractiveCli {
             Arc::clone(&self.accounts_cache),
             Arc::clone(&self.rpc_client),
             &ais_pks,
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
         ));
 
+        if let Some(record_path) = &self.record_path {
+            self.recorder_service = Some(Arc::new(AccountRecorderService::new(
+                Arc::clone(&self.accounts_cache),
+                self.accounts_cache_sender.subscribe(),
+                self.services_shutdown.subscribe(),
+                &ais_pks,
+                record_path,
+            )));
+        }
+
         self.cypher_context = Arc::new(CypherContext::new(
-            self.shutdown.subscribe(),
+            self.services_shutdown.subscribe(),
             arc_ca_s.subscribe(),
             arc_cg_s.subscribe(),
         ));
 
+        self.kill_switch
+            .register(
+                self.handlers.clone(),
+                unloaded_markets,
+                Arc::clone(&self.cypher_context),
+                Arc::clone(&self.cm_service),
+            )
+            .await;
+
         Ok(())
     }
 
-    async fn run(&self) -> Result<(), CypherInteractiveError> {
-        let mut shutdown = self.shutdown.subscribe();
-
-        select! {
-            res = self.run_loop() => {
-                match res {
-                    Ok(_) => (),
-                    Err(e) => {
-                        println!("An error occurred while running the application loop: {:?}", e);
-                    }
+    /// Fetches the serum market of a market, makes sure its open orders account exists and prepares its handler,
+    /// returns `None` if the market has to be skipped.
+    async fn prepare_market(
+        &mut self,
+        market: &CypherMarketConfig,
+        cypher_user_pks: &[Pubkey],
+    ) -> Option<PreparedMarket> {
+        let dex_market_bids = Pubkey::from_str(market.bids.as_str()).unwrap();
+        let dex_market_asks = Pubkey::from_str(market.asks.as_str()).unwrap();
+        let dex_market_event_queue = Pubkey::from_str(market.event_queue.as_str()).unwrap();
+        let dex_market_pk = Pubkey::from_str(&market.address).unwrap();
+        let dex_market_account = match &self.replay_service {
+            Some(replay_service) => match replay_service.get_account(&dex_market_pk) {
+                Some(ai) => parse_dex_account(ai.data.to_vec()),
+                None => {
+                    println!("The recording does not contain the serum market account for {}. Ignoring market.", market.name);
+                    return None;
                 }
             },
-            _ = shutdown.recv() => {
-                println!(" Received shutdown signal, stopping.");
-            }
+            None => match get_serum_market(Arc::clone(&self.rpc_client), dex_market_pk).await {
+                Ok(m) => m,
+                Err(e) => {
+                    println!("An error occurred while fetching the serum market account for {}. Ignoring market. Error: {}", market.name, e);
+                    return None;
+                }
+            },
+        };
+
+        let open_orders_pk = derive_open_orders_address(&dex_market_pk, &self.cypher_user_pk).0;
+
+        // open orders accounts of every sub-account, used to spot our own orders in the book
+        self.own_open_orders_pks.insert(open_orders_pk);
+        for cypher_user_pk in cypher_user_pks {
+            self.own_open_orders_pks
+                .insert(derive_open_orders_address(&dex_market_pk, cypher_user_pk).0);
         }
 
-        Ok(())
+        // the open orders account is part of the recording, there is nothing to create when replaying
+        if self.replay.is_none() {
+            match get_or_init_open_orders(
+                self.keypair.as_ref(),
+                &self.cypher_group_pk,
+                &self.cypher_user_pk,
+                &dex_market_pk,
+                &open_orders_pk,
+                Arc::clone(&self.rpc_client),
+                &self.cluster,
+            )
+            .await
+            {
+                Ok(_) => (),
+                Err(e) => {
+                    println!("An error occurred while fetching or creating open orders account for {}. Ignoring market. Error: {:?}", market.name, e);
+                    return None;
+                }
+            };
+        }
+        println!(
+            "Preparing orderbook context for market {}. Market: {} Bids: {} Asks: {}.",
+            market.name, dex_market_pk, dex_market_bids, dex_market_asks
+        );
+        let ob_ctx = OrderBookContext {
+            market: dex_market_pk,
+            bids: dex_market_bids,
+            asks: dex_market_asks,
+            coin_lot_size: dex_market_account.coin_lot_size,
+            pc_lot_size: dex_market_account.pc_lot_size,
+        };
+
+        let fills_ctx = FillsContext {
+            market_name: market.name.to_string(),
+            event_queue: dex_market_event_queue,
+            open_orders: open_orders_pk,
+            base_decimals: market.base_decimals,
+            quote_decimals: market.quote_decimals,
+        };
+
+        let ais_pks = vec![
+            dex_market_pk,
+            dex_market_bids,
+            dex_market_asks,
+            dex_market_event_queue,
+            open_orders_pk,
+        ];
+
+        println!("Preparing handler for market {}.", market.name);
+        let handler = Arc::new(Handler::new(
+            Box::new(MarketContext {
+                name: market.name.to_string(),
+                market_index: market.market_index,
+                signer: Arc::clone(&self.keypair),
+                cypher_user_pk: self.cypher_user_pk,
+                dex_market_pk,
+                open_orders_pk,
+                units: MarketUnits::new(
+                    market.base_decimals,
+                    market.quote_decimals,
+                    dex_market_account.coin_lot_size,
+                    dex_market_account.pc_lot_size,
+                ),
+            }),
+            Arc::clone(&self.rpc_client),
+            self.services_shutdown.subscribe(),
+            self.open_orders_sender.subscribe(),
+            self.orderbook_sender.subscribe(),
+            Some(dex_market_account),
+            self.margin_check,
+            Arc::clone(&self.risk_engine),
+        ));
+
+        Some(PreparedMarket {
+            ob_ctx,
+            fills_ctx,
+            open_orders_pk,
+            ais_pks,
+            handler,
+        })
     }
 
-    async fn run_loop(&self) -> Result<(), CypherInteractiveError> {
+    async fn run(&mut self) -> Result<(), CypherInteractiveError> {
         println!(
             "Welcome to the cypher.trade interactive CLI.\nType 'help' to get a list of available commands."
         );
 
+        let mut shutdown = self.shutdown.subscribe();
+        let mut lines = spawn_stdin_reader();
+
         loop {
-            let input = match self.read_input() {
-                Ok(i) => i,
-                Err(e) => {
+            print!(">");
+            io::stdout().flush().unwrap();
+
+            // the shutdown signal is only awaited between commands, so a command
+            // which is already submitting a transaction gets to see it confirmed
+            let line = select! {
+                line = lines.recv() => line,
+                _ = shutdown.recv() => {
+                    println!(" Received shutdown signal, no longer accepting commands.");
+                    break;
+                }
+            };
+            let input = match line {
+                Some(Ok(l)) => l,
+                None => {
+                    println!("Reached the end of the input, exiting.");
+                    break;
+                }
+                Some(Err(e)) => {
                     println!(
                         "There was an error processing the input, please try again. Err: {:?}",
                         e
@@ -411,6 +784,43 @@ impl InteractiveCli {
                 break;
             }
 
+            // the book on screen is recorded, anything sent would still land on the live cluster
+            if self.replay.is_some() && command.sends_transaction() {
+                println!("This command sends transactions, which is not possible while replaying a recording.");
+                continue;
+            }
+            if !self.is_signer_allowed(&command).await {
+                continue;
+            }
+
+            // switching accounts rebuilds the session's services, so it needs exclusive access
+            if let InteractiveCommand::Use(account_number) = command {
+                self.use_account(account_number).await;
+                continue;
+            }
+            if command == InteractiveCommand::SyncConfig {
+                self.sync_config().await;
+                continue;
+            }
+            if let InteractiveCommand::Load(symbol) = &command {
+                self.load_market(symbol).await;
+                continue;
+            }
+            if let InteractiveCommand::CloseOpenOrders(info) = command {
+                self.close_open_orders(info).await;
+                continue;
+            }
+            if let Some(symbol) = command.get_market() {
+                let symbol = symbol.to_string();
+                if !self.is_market_active(&symbol) && self.is_market_in_group(&symbol) {
+                    println!("Loading market {} on first use.", symbol);
+                    if !self.load_market(&symbol).await {
+                        continue;
+                    }
+                    self.wait_for_market(&symbol).await;
+                }
+            }
+
             match self.process_command(command.clone()).await {
                 Ok(_) => (),
                 Err(e) => {
@@ -425,24 +835,96 @@ impl InteractiveCli {
         Ok(())
     }
 
-    fn read_input(&self) -> Result<String, CypherInteractiveError> {
-        let mut buffer = String::new();
+    /// Stops every service, provider and handler, giving them up to `SHUTDOWN_TIMEOUT` to finish,
+    /// and reports the orders and unsettled funds still on chain.
+    async fn shutdown_gracefully(&mut self) {
+        println!(
+            "Shutting down, waiting for {} tasks to stop.",
+            self.tasks.len()
+        );
+        self.stop_tasks().await;
+        self.left_open_status().await;
+    }
+
+    /// Signals every task of the active account to stop and waits up to `SHUTDOWN_TIMEOUT` for them.
+    async fn stop_tasks(&mut self) {
+        // nobody is left to receive it if the tasks already stopped, which is fine
+        _ = self.services_shutdown.send(true);
+
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        let mut stopped: usize = 0;
+        let mut aborted: usize = 0;
+        for mut task in self.tasks.drain(..) {
+            match timeout_at(deadline, &mut task).await {
+                Ok(_) => stopped += 1,
+                Err(_) => {
+                    task.abort();
+                    aborted += 1;
+                }
+            }
+        }
+        if aborted == 0 {
+            println!("Stopped {} tasks.", stopped);
+        } else {
+            println!(
+                "Stopped {} tasks, {} did not stop within {:?} and were aborted.",
+                stopped, aborted, SHUTDOWN_TIMEOUT
+            );
+        }
+    }
 
-        print!(">");
-        io::stdout().flush().unwrap();
+    async fn left_open_status(&self) {
+        println!("----- Left Open -----");
+        for handler in &self.handlers {
+            let name = &handler.market_context.name;
+            let units = &handler.market_context.units;
+            let open_orders_pk = handler.market_context.open_orders_pk;
+
+            let open_orders_account: OpenOrders = match self
+                .rpc_client
+                .get_account(&open_orders_pk)
+                .await
+            {
+                Ok(Some(a)) => parse_dex_account(a.data),
+                Ok(None) => {
+                    continue;
+                }
+                Err(e) => {
+                    println!(
+                            "\tCould not fetch open orders for market {}, showing the last known state. Err: {}",
+                            name, e
+                        );
+                    match handler.get_open_orders().await {
+                        Ok(oo) => oo,
+                        Err(_) => {
+                            continue;
+                        }
+                    }
+                }
+            };
+            let open_orders = get_open_orders(&open_orders_account);
 
-        match io::stdin().read_line(&mut buffer) {
-            Ok(_) => (),
-            Err(e) => {
+            println!("\tMarket: {}", name);
+            println!("\t\tOpen Orders: {}", open_orders.len());
+            for order in open_orders {
                 println!(
-                    "There was an error processing the input, please try again. Err: {:?}",
-                    e
+                    "\t\t\t{:?} at {} - Order ID: {}",
+                    order.side,
+                    units.price_lots_to_ui(order.price),
+                    order.order_id
                 );
             }
+            println!(
+                "\t\tUnsettled coin (ui): {}",
+                units.native_base_to_ui(open_orders_account.native_coin_free)
+            );
+            println!(
+                "\t\tUnsettled price coin (ui): {}",
+                open_orders_account.native_pc_free as f64
+                    / 10_f64.powi(units.quote_decimals as i32)
+            );
         }
-        trim_newline(&mut buffer);
-
-        Ok(buffer)
+        println!("----- Left Open -----");
     }
 
     async fn process_command(
@@ -451,17 +933,42 @@ impl InteractiveCli {
     ) -> Result<(), CypherInteractiveError> {
         match command {
             InteractiveCommand::Help => {
-                println!(">>> new {{account_number}}\n\t- creates a new account with the specified account number");
-                println!(">>> airdrop \n\t- airdrop quote token (devnet only)");
-                println!(">>> deposit {{amount_ui}}\n\t- deposits quote token");
-                println!(">>> delegate {{pubkey}}\n\t- delegates the account to the given public key, delegates cannot close the account or withdraw");
+                // owner-only commands stay listed but are marked when there is no owner keypair to sign them
+                let owner_only = match self.owner_keypair {
+                    Some(_) => "",
+                    None => " (owner only, unavailable)",
+                };
+                println!(">>> new {{account_number}}{}\n\t- creates a new account with the specified account number", owner_only);
+                println!(
+                    ">>> airdrop{}\n\t- airdrop quote token (devnet only)",
+                    owner_only
+                );
+                println!(
+                    ">>> deposit {{amount_ui}}{}\n\t- deposits quote token",
+                    owner_only
+                );
+                println!(">>> delegate {{pubkey}}{}\n\t- delegates the account to the given public key, delegates cannot close the account or withdraw", owner_only);
+                println!(
+                    ">>> undelegate{}\n\t- removes the account's delegate",
+                    owner_only
+                );
+                println!(">>> delegate status\n\t- displays the account's owner and delegate and which of them the loaded signer is");
                 println!(">>> status\n\t- displays cypher account status and open orders information for available markets");
                 println!(">>> markets\n\t- displays cypher group's available markets and relevant information");
                 println!(">>> tokens\n\t- displays cypher group's available tokens and relevant information");
-                println!(">>> orderbook {{symbol}} {{max_depth}}\n\t- displays the given market's orderbook up to a given depth");
-                println!(">>> limit {{side}} {{symbol}} {{amount}} {{price}}\n\t- submits a limit order on the given order book side at the given price for the given amount");
-                println!(">>> market {{side}} {{symbol}} {{amount}}\n\t- submits a market order on the given order book side at the best available price for the given amount");
+                println!(">>> orderbook {{symbol}} {{max_depth}} [--l3] [--group {{tick}}]\n\t- displays the given market's orderbook up to a given depth, aggregated by price level or per order with '--l3', optionally grouping prices into ticks of the given size in ui units");
+                println!(">>> limit {{side}} {{symbol}} {{amount}} {{price}} [--force]\n\t- submits a limit order on the given order book side at the given price for the given amount, both in ui units and rounded to the market's lot and tick sizes, e.g. 'limit buy SOL 1.5 32.25', '--force' submits it even if it would leave the account below the minimum c-ratio");
+                println!(">>> market {{side}} {{symbol}} {{amount}} [--force]\n\t- submits an immediate-or-cancel order on the given order book side for the given amount, priced at what the book would fill it at plus 1% slippage, whatever does not fill is cancelled, '--force' submits it even if it would leave the account below the minimum c-ratio");
                 println!(">>> cancel {{symbol}} {{order_id}}\n\t- cancels the order with the given order id and symbol");
+                println!(">>> trades [symbol] [n]\n\t- displays the last n fills from the local trade log, optionally for the given market only");
+                println!(">>> pnl\n\t- displays realized and unrealized pnl and fees paid per market and in aggregate");
+                println!(">>> panic [settle]\n\t- cancels every open order on every market, optionally settling funds, and blocks new orders until 'unlock'");
+                println!(">>> unlock\n\t- allows new orders again after 'panic'");
+                println!(">>> accounts\n\t- lists the owner's existing cypher accounts, marking the active one with '*'");
+                println!(">>> use {{account_number|default}}\n\t- switches the session to the given sub-account, or back to the default account");
+                println!(">>> load {{symbol}}\n\t- loads a market which is not loaded yet, creating its open orders account if needed, markets are also loaded when first used, or at startup with '--markets' or '--load-all-markets'");
+                println!(">>> close-oo {{symbol|all}} [--settle]\n\t- closes the open orders account of the given market or of every market in the group and returns its rent to the owner, signed by the owner keypair, the market must have no open orders and nothing unsettled, '--settle' settles funds first on a loaded market");
+                println!(">>> sync-config\n\t- rebuilds the group's markets and tokens from the group account so newly listed markets can be loaded, the synced config only lasts for the session");
                 println!(">>> exit\n\t- exits the application");
             }
             InteractiveCommand::NewAccount(account_number) => {
@@ -469,6 +976,8 @@ impl InteractiveCli {
             }
             InteractiveCommand::Airdrop => self.airdrop().await,
             InteractiveCommand::Delegate(pk) => self.delegate(pk).await,
+            InteractiveCommand::Undelegate => self.undelegate().await,
+            InteractiveCommand::DelegateStatus => self.delegate_status().await,
             InteractiveCommand::Deposit(amount) => self.deposit(amount).await,
             InteractiveCommand::TokensStatus => self.tokens_status().await,
             InteractiveCommand::MarketsStatus => self.markets_status().await,
@@ -477,6 +986,16 @@ impl InteractiveCli {
             InteractiveCommand::Limit(info) => self.limit_order(info).await,
             InteractiveCommand::Market(info) => self.market_order(info).await,
             InteractiveCommand::Cancel(info) => self.cancel_order(info).await,
+            InteractiveCommand::Trades(info) => self.trades(info),
+            InteractiveCommand::Pnl => self.pnl_status().await,
+            InteractiveCommand::Panic(settle) => self.kill_switch.engage(settle).await,
+            InteractiveCommand::Unlock => self.unlock(),
+            InteractiveCommand::Accounts => self.accounts_status().await,
+            // handled by the interactive loop
+            InteractiveCommand::Use(_) => (),
+            InteractiveCommand::SyncConfig => (),
+            InteractiveCommand::Load(_) => (),
+            InteractiveCommand::CloseOpenOrders(_) => (),
             InteractiveCommand::Exit => (),
         }
 
@@ -505,16 +1024,34 @@ impl InteractiveCli {
         Ok(handler)
     }
 
+    /// The keypair for instructions only the account's owner may sign.
+    fn get_owner_keypair(&self) -> Result<Arc<Keypair>, CypherInteractiveError> {
+        match &self.owner_keypair {
+            Some(kp) => Ok(Arc::clone(kp)),
+            None => Err(CypherInteractiveError::OwnerKeypairRequired),
+        }
+    }
+
     async fn airdrop(&self) {
-        if self.cluster != "devnet" {
+        if self.cluster.name != "devnet" {
             println!("This command is only available for 'devnet' cluster.");
             return;
         }
-        let req_res = request_airdrop(&self.keypair, Arc::clone(&self.rpc_client)).await;
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!("There was an error requesting airdrop: {:?}", e);
+                return;
+            }
+        };
+        let req_res = request_airdrop(owner.as_ref(), Arc::clone(&self.rpc_client)).await;
 
         match req_res {
             Ok(s) => {
-                println!("Successfully requested airdrop. https://explorer.solana.com/tx/{}?cluster=devnet", s);
+                println!(
+                    "Successfully requested airdrop. {}",
+                    self.cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 println!("There was an error requesting airdrop: {:?}", e);
@@ -523,9 +1060,16 @@ impl InteractiveCli {
     }
 
     async fn new_account(&self, account_number: u64) {
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!("There was an error creating a new account: {:?}", e);
+                return;
+            }
+        };
         let req_res = create_cypher_user(
             &self.cypher_group_pk,
-            &self.keypair,
+            owner.as_ref(),
             account_number,
             Arc::clone(&self.rpc_client),
         )
@@ -533,7 +1077,11 @@ impl InteractiveCli {
 
         match req_res {
             Ok(s) => {
-                println!("Successfully created new account with number {}. https://explorer.solana.com/tx/{}?cluster=devnet", account_number, s);
+                println!(
+                    "Successfully created new account with number {}. {}",
+                    account_number,
+                    self.cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 println!("There was an error creating a new account: {:?}", e);
@@ -542,19 +1090,30 @@ impl InteractiveCli {
     }
 
     async fn delegate(&self, pubkey: String) {
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!("There was an error delegating to account: {:?}", e);
+                return;
+            }
+        };
         let delegate_pk = Pubkey::from_str(&pubkey).unwrap();
         let req_res = set_delegate(
             &self.cypher_group_pk,
             &self.cypher_user_pk,
             &delegate_pk,
-            &self.keypair,
+            owner.as_ref(),
             Arc::clone(&self.rpc_client),
         )
         .await;
 
         match req_res {
             Ok(s) => {
-                println!("Successfully delegated account to {}. https://explorer.solana.com/tx/{}?cluster=devnet", pubkey, s);
+                println!(
+                    "Successfully delegated account to {}. {}",
+                    pubkey,
+                    self.cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 println!("There was an error delegating to account: {:?}", e);
@@ -562,18 +1121,143 @@ impl InteractiveCli {
         }
     }
 
-    async fn deposit(&self, amount: f64) {
-        let maybe_group = self.cypher_context.get_group().await;
-        let group = match maybe_group {
-            Ok(g) => g,
-            Err(_) => {
-                println!("Cypher group not available.");
+    async fn undelegate(&self) {
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!("There was an error removing the delegate: {:?}", e);
                 return;
             }
         };
-        let native_amount = amount * 10_u64.checked_pow(6).unwrap() as f64;
-        let res = deposit_quote_token(
-            &self.keypair,
+        let req_res = set_delegate(
+            &self.cypher_group_pk,
+            &self.cypher_user_pk,
+            &Pubkey::default(),
+            owner.as_ref(),
+            Arc::clone(&self.rpc_client),
+        )
+        .await;
+
+        match req_res {
+            Ok(s) => {
+                println!(
+                    "Successfully removed the account's delegate. {}",
+                    self.cluster.get_explorer_tx_url(&s)
+                );
+            }
+            Err(e) => {
+                println!("There was an error removing the delegate: {:?}", e);
+            }
+        }
+    }
+
+    async fn get_signer_role(&self) -> Result<SignerRole, CypherInteractiveError> {
+        let signer_pk = self.keypair.pubkey();
+        if signer_pk == self.owner_pk {
+            return Ok(SignerRole::Owner);
+        }
+
+        let user = self.cypher_context.get_user().await?;
+        if user.delegate != Pubkey::default() && signer_pk == user.delegate {
+            Ok(SignerRole::Delegate)
+        } else {
+            Ok(SignerRole::Unauthorized)
+        }
+    }
+
+    /// Checks the command against what the loaded signers may do on the account,
+    /// so a command the program would reject is never sent.
+    async fn is_signer_allowed(&self, command: &InteractiveCommand) -> bool {
+        if command.requires_owner() {
+            return match self.get_owner_keypair() {
+                Ok(_) => true,
+                Err(e) => {
+                    println!(
+                        "Only the account owner {} can sign this command, load its keypair to use it. Err: {:?}",
+                        self.owner_pk, e
+                    );
+                    false
+                }
+            };
+        }
+        if !command.trades() {
+            return true;
+        }
+
+        match self.get_signer_role().await {
+            Ok(SignerRole::Owner) | Ok(SignerRole::Delegate) => true,
+            Ok(SignerRole::Unauthorized) => {
+                println!(
+                    "The signer {} is neither the owner nor the delegate of the account, the program would reject its orders.",
+                    self.keypair.pubkey()
+                );
+                false
+            }
+            Err(e) => {
+                println!(
+                    "Could not check whether the signer may trade on the account. Err: {:?}",
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    async fn delegate_status(&self) {
+        let user = match self.cypher_context.get_user().await {
+            Ok(u) => u,
+            Err(_) => {
+                println!("Cypher user not available.");
+                return;
+            }
+        };
+        let role = match self.get_signer_role().await {
+            Ok(r) => r,
+            Err(e) => {
+                println!("There was an error checking the signer. Err: {:?}", e);
+                return;
+            }
+        };
+
+        println!("----- Delegation -----");
+        println!("\tOwner: {}", self.owner_pk);
+        if user.delegate == Pubkey::default() {
+            println!("\tDelegate: none");
+        } else {
+            println!("\tDelegate: {}", user.delegate);
+        }
+        println!("\tSigner: {}", self.keypair.pubkey());
+        match role {
+            SignerRole::Owner => println!("\tThe signer is the account owner."),
+            SignerRole::Delegate => println!(
+                "\tThe signer is the account's delegate, it cannot close the account or withdraw."
+            ),
+            SignerRole::Unauthorized => println!(
+                "\tThe signer is neither the owner nor the delegate, its transactions will be rejected."
+            ),
+        }
+        println!("----- Delegation -----");
+    }
+
+    async fn deposit(&self, amount: f64) {
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!("There was an error depositing USDC: {:?}", e);
+                return;
+            }
+        };
+        let maybe_group = self.cypher_context.get_group().await;
+        let group = match maybe_group {
+            Ok(g) => g,
+            Err(_) => {
+                println!("Cypher group not available.");
+                return;
+            }
+        };
+        let native_amount = amount * 10_u64.checked_pow(6).unwrap() as f64;
+        let res = deposit_quote_token(
+            owner.as_ref(),
             &self.cypher_user_pk,
             &group,
             Arc::clone(&self.rpc_client),
@@ -584,8 +1268,8 @@ impl InteractiveCli {
         match res {
             Ok(s) => {
                 println!(
-                    "Successfully deposited USDC. https://explorer.solana.com/tx/{}?cluster=devnet",
-                    s
+                    "Successfully deposited USDC. {}",
+                    self.cluster.get_explorer_tx_url(&s)
                 );
             }
             Err(e) => {
@@ -625,8 +1309,18 @@ impl InteractiveCli {
         println!("\tAssets Value (ui): {}", assets_value_ui);
         println!("\tLiabilities Value (ui): {}", liabs_value_ui);
         println!("\tC Ratio: {}", c_ratio);
+        let margin = MarginSummary::new(&user, &group);
+        let quote_multiplier = number_to_f64(quote_divisor);
+        println!("\tInitial Margin Ratio: {}", margin.init_ratio);
+        println!("\tMaintenance Margin Ratio: {}", margin.maint_ratio);
+        println!(
+            "\tFree Collateral (ui): {}",
+            margin.free_collateral() / quote_multiplier
+        );
         for market in &group_config.markets {
             let cypher_token = group.get_cypher_token(market.market_index).unwrap();
+            let cypher_market = group.get_cypher_market(market.market_index).unwrap();
+            let oracle_price = number_to_f64(cypher_market.oracle_price.price);
             let maybe_position = user.get_position(market.market_index);
             let position = match maybe_position {
                 Some(p) => p,
@@ -664,6 +1358,32 @@ impl InteractiveCli {
                 "\t\tLocked price coin (native): {}",
                 position.oo_info.pc_total - position.oo_info.pc_free
             );
+
+            // coin sitting in the open orders account still counts towards our deposits
+            let exposure_deposits =
+                number_to_f64(native_deposits) + position.oo_info.coin_total as f64;
+            let available = number_to_f64(cypher_token.total_deposits())
+                - number_to_f64(cypher_token.total_borrows());
+            let max_borrowable = margin.max_borrowable(oracle_price).min(available.max(0.0));
+            println!(
+                "\t\tOracle Price (ui): {}",
+                native_price_to_ui(oracle_price, market.base_decimals, market.quote_decimals)
+            );
+            println!(
+                "\t\tMax Borrowable (ui): {}",
+                max_borrowable / number_to_f64(divisor)
+            );
+            match margin.liquidation_price(
+                exposure_deposits,
+                number_to_f64(native_borrows),
+                oracle_price,
+            ) {
+                Some(p) => println!(
+                    "\t\tLiquidation Price (ui): {}",
+                    native_price_to_ui(p, market.base_decimals, market.quote_decimals)
+                ),
+                None => println!("\t\tLiquidation Price (ui): none"),
+            }
         }
 
         let maybe_usdc_position = user.get_position(QUOTE_TOKEN_IDX);
@@ -682,31 +1402,124 @@ impl InteractiveCli {
         println!("\t\tBorrows (native): {}", usdc_native_borrows);
         println!("\t\tDeposits (ui): {}", usdc_deposits);
         println!("\t\tBorrows (ui): {}", usdc_borrows);
+        let usdc_token = group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        let usdc_available =
+            number_to_f64(usdc_token.total_deposits()) - number_to_f64(usdc_token.total_borrows());
+        println!(
+            "\t\tMax Borrowable (ui): {}",
+            margin.max_borrowable(1.0).min(usdc_available.max(0.0)) / quote_multiplier
+        );
         println!("----- Open Orders -----");
 
         for market in &group_config.markets {
             println!("\t----- {} Orders -----", market.name);
+            if !self.is_market_active(&market.name) {
+                println!("\t\tNot loaded, use 'load {}' to load it.", market.name);
+                println!("\t----- {} Orders -----", market.name);
+                continue;
+            }
             let res = self.get_handler(market.name.to_string());
             if res.is_ok() {
                 let handler = res.unwrap();
-                let open_orders_account = handler.get_open_orders().await.unwrap();
-                let ob = handler.get_orderbook().await.unwrap();
+                let (open_orders_account, ob) = match (
+                    handler.get_open_orders().await,
+                    handler.get_orderbook().await,
+                ) {
+                    (Ok(oo), Ok(ob)) => (oo, ob),
+                    _ => {
+                        println!("\t\tNot available yet.");
+                        println!("\t----- {} Orders -----", market.name);
+                        continue;
+                    }
+                };
 
                 let open_orders = get_open_orders_with_qty(&open_orders_account, &ob).await;
+                let units = &handler.market_context.units;
 
                 for order in open_orders {
                     println!(
                         "\t\t{:?} {} for {} - Order ID: {}",
-                        order.side, order.quantity, order.price, order.order_id
+                        order.side,
+                        units.native_base_to_ui(order.quantity),
+                        units.price_lots_to_ui(order.price),
+                        order.order_id
                     );
                 }
             }
             println!("\t----- {} Orders -----", market.name);
         }
         println!("----- Open Orders -----");
+        self.pnl_status().await;
         println!("----- Account Status -----");
     }
 
+    async fn pnl_status(&self) {
+        let cypher_config = &self.cypher_config;
+        let group_config = cypher_config.get_group(&self.group).unwrap();
+        let maybe_group = self.cypher_context.get_group().await;
+        let group = match maybe_group {
+            Ok(g) => g,
+            Err(_) => {
+                println!("Cypher group not available.");
+                return;
+            }
+        };
+        let markets_pnl = self.pnl_tracker.get_markets().await;
+
+        let mut total_realized: f64 = 0.0;
+        let mut total_unrealized_oracle: f64 = 0.0;
+        let mut total_unrealized_mark: f64 = 0.0;
+        let mut total_fees: f64 = 0.0;
+
+        println!("----- PnL -----");
+        for market in &group_config.markets {
+            let market_pnl = match markets_pnl.get(&market.name) {
+                Some(p) => p,
+                None => {
+                    continue;
+                }
+            };
+            let cypher_market = group.get_cypher_market(market.market_index).unwrap();
+            let oracle_price = native_price_to_ui(
+                cypher_market.oracle_price.price,
+                market.base_decimals,
+                market.quote_decimals,
+            );
+            let mark_price = native_price_to_ui(
+                cypher_market.market_price,
+                market.base_decimals,
+                market.quote_decimals,
+            );
+            let unrealized_oracle = market_pnl.unrealized_pnl(oracle_price);
+            let unrealized_mark = market_pnl.unrealized_pnl(mark_price);
+
+            total_realized += market_pnl.realized_pnl;
+            total_unrealized_oracle += unrealized_oracle;
+            total_unrealized_mark += unrealized_mark;
+            total_fees += market_pnl.fees_paid;
+
+            println!("\tMarket: {}", market.name);
+            println!("\t\tPosition: {}", market_pnl.position);
+            println!("\t\tAverage Entry: {}", market_pnl.average_entry);
+            println!("\t\tOracle Price: {}", oracle_price);
+            println!("\t\tMark Price: {}", mark_price);
+            println!("\t\tRealized PnL: {}", market_pnl.realized_pnl);
+            println!("\t\tUnrealized PnL (oracle): {}", unrealized_oracle);
+            println!("\t\tUnrealized PnL (mark): {}", unrealized_mark);
+            println!("\t\tFees Paid: {}", market_pnl.fees_paid);
+        }
+        println!("\tTotal");
+        println!("\t\tRealized PnL: {}", total_realized);
+        println!("\t\tUnrealized PnL (oracle): {}", total_unrealized_oracle);
+        println!("\t\tUnrealized PnL (mark): {}", total_unrealized_mark);
+        println!("\t\tFees Paid: {}", total_fees);
+        println!(
+            "\t\tNet PnL (oracle): {}",
+            total_realized + total_unrealized_oracle - total_fees
+        );
+        println!("----- PnL -----");
+    }
+
     async fn markets_status(&self) {
         let cypher_config = &self.cypher_config;
         let group_config = cypher_config.get_group(&self.group).unwrap();
@@ -764,7 +1577,11 @@ impl InteractiveCli {
         let usdc_deposits =
             total_deposits / 10_u64.checked_pow(usdc_token.decimals().into()).unwrap();
         let one_hundred: Number = 100.into();
-        let utilization: Number = (total_borrows * one_hundred) / total_deposits;
+        let utilization: Number = if total_deposits == Number::ZERO {
+            Number::ZERO
+        } else {
+            (total_borrows * one_hundred) / total_deposits
+        };
 
         let optimal_util: Number = Number::from_percent(usdc_token.config.optimal_util);
         let optimal_apr: Number = Number::from_percent(usdc_token.config.optimal_apr);
@@ -774,6 +1591,8 @@ impl InteractiveCli {
             let extra_util = utilization - optimal_util;
             let slope = (max_apr - optimal_apr) / (one_hundred - optimal_util);
             optimal_apr + slope * extra_util
+        } else if optimal_util == Number::ZERO {
+            Number::ZERO
         } else {
             let slope = optimal_apr / optimal_util;
             slope * utilization
@@ -823,65 +1642,194 @@ impl InteractiveCli {
             }
         };
 
-        let mut bids = ob.bids.read().await.clone();
-        let mut asks = ob.asks.read().await.clone();
+        let units = &handler.market_context.units;
+
+        if info.l3 {
+            let bids = ob.bids.read().await;
+            let asks = ob.asks.read().await;
+
+            if bids.is_empty() && asks.is_empty() {
+                println!("OrderBook for {} is empty.", info.symbol);
+                return;
+            }
+
+            println!("----- OrderBook Status (L3) -----");
+            println!("Bids: {:^5} Asks: {:^5}", bids.len(), asks.len());
+            println!(
+                "{:^3} {:^5} {:^10} {:^10} {:^40} {:^44}",
+                "", "Side", "Price", "Size", "Order ID", "Owner"
+            );
+            for ask in asks.iter().take(info.depth).rev() {
+                println!(
+                    "{:^3} {:^5} {:^10} {:^10} {:^40} {:^44}",
+                    self.own_order_marker(&ask.owner),
+                    "Ask",
+                    units.price_lots_to_ui(ask.price_lots),
+                    units.native_base_to_ui(ask.quantity),
+                    ask.order_id,
+                    ask.owner
+                );
+            }
+            for bid in bids.iter().take(info.depth) {
+                println!(
+                    "{:^3} {:^5} {:^10} {:^10} {:^40} {:^44}",
+                    self.own_order_marker(&bid.owner),
+                    "Bid",
+                    units.price_lots_to_ui(bid.price_lots),
+                    units.native_base_to_ui(bid.quantity),
+                    bid.order_id,
+                    bid.owner
+                );
+            }
+            println!("----- OrderBook Status (L3) -----");
+            drop(bids);
+            drop(asks);
+            self.queue_positions_status(&ob, units).await;
+            return;
+        }
+
+        let tick_lots = match info.tick {
+            Some(tick) => match units.ui_price_to_lots(tick) {
+                Ok(t) => t,
+                Err(e) => {
+                    println!(
+                        "Invalid tick {} for market {}, the minimum tick is {}. Err: {:?}",
+                        tick,
+                        info.symbol,
+                        units.tick_size(),
+                        e
+                    );
+                    return;
+                }
+            },
+            None => 1,
+        };
+        let bids = ob.get_levels(Side::Bid, tick_lots).await;
+        let asks = ob.get_levels(Side::Ask, tick_lots).await;
+        let own_bids = ob
+            .get_owned_levels(Side::Bid, tick_lots, &self.own_open_orders_pks)
+            .await;
+        let own_asks = ob
+            .get_owned_levels(Side::Ask, tick_lots, &self.own_open_orders_pks)
+            .await;
 
         if bids.is_empty() && asks.is_empty() {
             println!("OrderBook for {} is empty.", info.symbol);
             return;
         }
 
-        bids.sort_by(|a, b| b.price.cmp(&a.price));
-        asks.sort_by(|a, b| a.price.cmp(&b.price));
-        let num_bids = bids.len();
-        let num_asks = asks.len();
+        let num_levels = bids.len().max(asks.len()).min(info.depth);
+        let mut bid_cum_size: f64 = 0.0;
+        let mut bid_cum_notional: f64 = 0.0;
+        let mut ask_cum_size: f64 = 0.0;
+        let mut ask_cum_notional: f64 = 0.0;
 
         println!("----- OrderBook Status -----");
-        println!("Bids: {:^5} Asks: {:^5}", num_bids, num_asks);
-
         println!(
-            "{:^10} {:^10} | {:^10} {:^10}",
-            "Bid Size", "Bid Price", "Ask Price", "Ask Size"
+            "Bid Levels: {:^5} Ask Levels: {:^5} Tick: {:^5}",
+            bids.len(),
+            asks.len(),
+            units.price_lots_to_ui(tick_lots)
         );
-        if num_bids >= num_asks {
-            for (idx, bid) in bids.iter().enumerate() {
-                let ask = asks.get(idx);
-
-                if ask.is_none() {
-                    println!(
-                        "{:^10} {:^10} | {:^10} {:^10}",
-                        bid.quantity, bid.price, 0, 0
-                    );
-                } else {
-                    let ask = ask.unwrap();
-                    println!(
-                        "{:^10} {:^10} | {:^10} {:^10}",
-                        bid.quantity, bid.price, ask.price, ask.quantity
-                    );
+        println!(
+            "{:^14} {:^12} {:^10} {:^10} {:^1} | {:^1} {:^10} {:^10} {:^12} {:^14}",
+            "Bid Cum Notnl",
+            "Bid Cum Size",
+            "Bid Size",
+            "Bid Price",
+            "",
+            "",
+            "Ask Price",
+            "Ask Size",
+            "Ask Cum Size",
+            "Ask Cum Notnl"
+        );
+        for idx in 0..num_levels {
+            let (bid_size, bid_price, bid_marker) = match bids.get(idx) {
+                Some(b) => {
+                    let size = units.native_base_to_ui(b.quantity);
+                    bid_cum_size += size;
+                    bid_cum_notional += units.native_quote_to_ui(ob.notional(b));
+                    let marker = if own_bids.contains(&b.price_lots) {
+                        "*"
+                    } else {
+                        ""
+                    };
+                    (size, units.price_lots_to_ui(b.price_lots), marker)
                 }
-            }
-        } else {
-            for (idx, ask) in asks.iter().enumerate() {
-                let bid = bids.get(idx);
-
-                if bid.is_none() {
-                    println!(
-                        "{:^10} {:^10} | {:^10} {:^10}",
-                        0, 0, ask.price, ask.quantity
-                    );
-                } else {
-                    let bid = bid.unwrap();
-                    println!(
-                        "{:^10} {:^10} | {:^10} {:^10}",
-                        bid.quantity, bid.price, ask.price, ask.quantity
-                    );
+                None => (0.0, 0.0, ""),
+            };
+            let (ask_marker, ask_price, ask_size) = match asks.get(idx) {
+                Some(a) => {
+                    let size = units.native_base_to_ui(a.quantity);
+                    ask_cum_size += size;
+                    ask_cum_notional += units.native_quote_to_ui(ob.notional(a));
+                    let marker = if own_asks.contains(&a.price_lots) {
+                        "*"
+                    } else {
+                        ""
+                    };
+                    (marker, units.price_lots_to_ui(a.price_lots), size)
                 }
-            }
+                None => ("", 0.0, 0.0),
+            };
+            println!(
+                "{:^14} {:^12} {:^10} {:^10} {:^1} | {:^1} {:^10} {:^10} {:^12} {:^14}",
+                bid_cum_notional,
+                bid_cum_size,
+                bid_size,
+                bid_price,
+                bid_marker,
+                ask_marker,
+                ask_price,
+                ask_size,
+                ask_cum_size,
+                ask_cum_notional
+            );
         }
         println!("----- OrderBook Status -----");
+        self.queue_positions_status(&ob, units).await;
+    }
+
+    fn own_order_marker(&self, owner: &Pubkey) -> &'static str {
+        if self.own_open_orders_pks.contains(owner) {
+            "*"
+        } else {
+            ""
+        }
+    }
+
+    async fn queue_positions_status(&self, ob: &OrderBook, units: &MarketUnits) {
+        let positions = ob.get_queue_positions(&self.own_open_orders_pks).await;
+        if positions.is_empty() {
+            return;
+        }
+
+        println!("----- Our Orders -----");
+        println!(
+            "{:^5} {:^10} {:^10} {:^10} {:^12} {:^40} {:^44}",
+            "Side", "Price", "Size", "Queue", "Size Ahead", "Order ID", "Owner"
+        );
+        for position in positions {
+            println!(
+                "{:^5} {:^10} {:^10} {:^10} {:^12} {:^40} {:^44}",
+                format!("{:?}", position.side),
+                units.price_lots_to_ui(position.order.price_lots),
+                units.native_base_to_ui(position.order.quantity),
+                format!("{}/{}", position.position, position.orders_at_level),
+                units.native_base_to_ui(position.size_ahead),
+                position.order.order_id,
+                position.order.owner
+            );
+        }
+        println!("----- Our Orders -----");
     }
 
     async fn limit_order(&self, info: LimitOrderInfo) {
+        if self.kill_switch.is_locked() {
+            println!("Orders are blocked by the kill switch, use 'unlock' to resume trading.");
+            return;
+        }
         let maybe_group = self.cypher_context.get_group().await;
         let group = match maybe_group {
             Ok(g) => g,
@@ -909,6 +1857,42 @@ impl InteractiveCli {
                 return;
             }
         };
+        let units = &handler.market_context.units;
+        let price_lots = match units.ui_price_to_lots(info.price) {
+            Ok(p) => p,
+            Err(e) => {
+                println!(
+                    "Invalid price {}, the tick size for market {} is {}. Err: {:?}",
+                    info.price,
+                    info.symbol,
+                    units.tick_size(),
+                    e
+                );
+                return;
+            }
+        };
+        let quantity_lots = match units.ui_quantity_to_lots(info.amount) {
+            Ok(q) => q,
+            Err(e) => {
+                println!(
+                    "Invalid amount {}, the lot size for market {} is {}. Err: {:?}",
+                    info.amount,
+                    info.symbol,
+                    units.lot_size(),
+                    e
+                );
+                return;
+            }
+        };
+        let rounded_price = units.price_lots_to_ui(price_lots);
+        let rounded_amount = units.native_base_to_ui(quantity_lots * units.coin_lot_size);
+        if rounded_price != info.price || rounded_amount != info.amount {
+            println!(
+                "Order rounded to the market's tick and lot sizes: {} for {}.",
+                rounded_amount, rounded_price
+            );
+        }
+
         let hash = self.cm_service.get_latest_blockhash().await;
         let ctx = HandlerContext {
             user: Box::new(user),
@@ -918,8 +1902,8 @@ impl InteractiveCli {
         match handler.limit_order(ctx, &info).await {
             Ok(s) => {
                 println!(
-                    "Successfully placed order. https://explorer.solana.com/tx/{}?cluster=devnet",
-                    s
+                    "Successfully placed order. {}",
+                    self.cluster.get_explorer_tx_url(&s)
                 );
             }
             Err(e) => {
@@ -929,6 +1913,10 @@ impl InteractiveCli {
     }
 
     async fn market_order(&self, info: MarketOrderInfo) {
+        if self.kill_switch.is_locked() {
+            println!("Orders are blocked by the kill switch, use 'unlock' to resume trading.");
+            return;
+        }
         let maybe_group = self.cypher_context.get_group().await;
         let group = match maybe_group {
             Ok(g) => g,
@@ -965,8 +1953,8 @@ impl InteractiveCli {
         match handler.market_order(ctx, &info).await {
             Ok(s) => {
                 println!(
-                    "Successfully placed order. https://explorer.solana.com/tx/{}?cluster=devnet",
-                    s
+                    "Successfully placed order. {}",
+                    self.cluster.get_explorer_tx_url(&s)
                 );
             }
             Err(e) => {
@@ -975,6 +1963,554 @@ impl InteractiveCli {
         }
     }
 
+    fn get_account_address(&self, account_number: Option<u64>) -> Pubkey {
+        match account_number {
+            Some(n) => {
+                derive_cypher_user_address_with_number(&self.cypher_group_pk, &self.owner_pk, n).0
+            }
+            None => derive_cypher_user_address(&self.cypher_group_pk, &self.owner_pk).0,
+        }
+    }
+
+    async fn accounts_status(&self) {
+        let default_pk = self.get_account_address(None);
+        let mut addresses = vec![(None, default_pk)];
+        for account_number in 0..MAX_SUB_ACCOUNTS {
+            let pk = self.get_account_address(Some(account_number));
+            if pk != default_pk {
+                addresses.push((Some(account_number), pk));
+            }
+        }
+
+        let pks: Vec<Pubkey> = addresses.iter().map(|(_, pk)| *pk).collect();
+        let accounts = match self.rpc_client.get_multiple_accounts(&pks).await {
+            Ok((_, a)) => a,
+            Err(e) => {
+                println!(
+                    "There was an error fetching the cypher accounts. Err: {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        println!("----- Accounts -----");
+        for ((account_number, pk), account) in addresses.iter().zip(accounts) {
+            if account.is_none() {
+                continue;
+            }
+            let active = if *pk == self.cypher_user_pk { "*" } else { " " };
+            let name = match account_number {
+                Some(n) => n.to_string(),
+                None => "default".to_string(),
+            };
+            println!("\t{} {:<8} {}", active, name, pk);
+        }
+        println!("----- Accounts -----");
+    }
+
+    /// Makes the given sub-account the active one, rebuilding the providers and handlers for its open orders accounts.
+    async fn use_account(&mut self, account_number: Option<u64>) {
+        if self.replay.is_some() {
+            println!("Switching accounts is not possible while replaying a recording.");
+            return;
+        }
+
+        let cypher_user_pk = self.get_account_address(account_number);
+        if cypher_user_pk == self.cypher_user_pk {
+            println!("Account {} is already in use.", cypher_user_pk);
+            return;
+        }
+        match self.rpc_client.get_account(&cypher_user_pk).await {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                println!(
+                    "Account {} does not exist, create it with 'new {{account_number}}' first.",
+                    cypher_user_pk
+                );
+                return;
+            }
+            Err(e) => {
+                println!(
+                    "There was an error fetching account {}. Err: {}",
+                    cypher_user_pk, e
+                );
+                return;
+            }
+        }
+
+        println!("Switching to account {}.", cypher_user_pk);
+        self.stop_tasks().await;
+        self.services_shutdown = channel::<bool>(1).0;
+
+        let previous = (self.cypher_user_pk, self.account_number);
+        self.cypher_user_pk = cypher_user_pk;
+        self.account_number = account_number;
+        if let Err(e) = self.start_services().await {
+            println!(
+                "An error occurred while starting the services for account {}, switching back. Err: {:?}",
+                cypher_user_pk, e
+            );
+            (self.cypher_user_pk, self.account_number) = previous;
+            if let Err(e) = self.start_services().await {
+                println!(
+                    "An error occurred while restarting the services for account {}. Err: {:?}",
+                    self.cypher_user_pk, e
+                );
+                return;
+            }
+        }
+        self.spawn_tasks();
+
+        println!("Now using account {}.", self.cypher_user_pk);
+    }
+
+    /// Rebuilds the group config from the latest group account and restarts the services, so markets listed
+    /// since the session started can be loaded.
+    async fn sync_config(&mut self) {
+        if self.replay.is_some() {
+            println!("Syncing the config is not possible while replaying a recording.");
+            return;
+        }
+
+        let group = match self.cypher_context.get_group().await {
+            Ok(g) => g,
+            Err(e) => {
+                println!(
+                    "The cypher group is not available yet, try again shortly. Err: {:?}",
+                    e
+                );
+                return;
+            }
+        };
+        let group_config = self.cypher_config.get_group(&self.group).unwrap();
+        let (synced, report) =
+            match sync_group_config(&group, group_config, Arc::clone(&self.rpc_client)).await {
+                Ok(r) => r,
+                Err(e) => {
+                    println!("There was an error syncing the group config. Err: {:?}", e);
+                    return;
+                }
+            };
+        report.print();
+        if synced == *group_config {
+            println!("The config is up to date.");
+            return;
+        }
+
+        let mut cypher_config = (*self.cypher_config).clone();
+        cypher_config.set_group(synced);
+        match cypher_config.validate() {
+            Ok(_) => (),
+            Err(CypherInteractiveError::ConfigInvalid(problems)) => {
+                println!("The synced config is invalid, keeping the current one:");
+                for problem in problems {
+                    println!("\t{}", problem);
+                }
+                return;
+            }
+            Err(e) => {
+                println!(
+                    "There was an error validating the synced config, keeping the current one. Err: {:?}",
+                    e
+                );
+                return;
+            }
+        }
+
+        println!("Restarting the services with the synced config.");
+        self.stop_tasks().await;
+        self.services_shutdown = channel::<bool>(1).0;
+
+        let previous = Arc::clone(&self.cypher_config);
+        self.cypher_config = Arc::new(cypher_config);
+        if let Err(e) = self.start_services().await {
+            println!(
+                "An error occurred while starting the services with the synced config, reverting. Err: {:?}",
+                e
+            );
+            self.cypher_config = previous;
+            if let Err(e) = self.start_services().await {
+                println!(
+                    "An error occurred while restarting the services. Err: {:?}",
+                    e
+                );
+                return;
+            }
+        }
+        self.spawn_tasks();
+
+        println!("Synced group {}.", self.group);
+    }
+
+    /// Whether the market has a handler, a market which failed to load is not active so its next use retries it.
+    fn is_market_active(&self, market: &str) -> bool {
+        if self.failed_markets.contains(market) {
+            return false;
+        }
+        match &self.active_markets {
+            Some(markets) => markets.contains(market),
+            None => true,
+        }
+    }
+
+    fn is_market_in_group(&self, market: &str) -> bool {
+        let group_config = self.cypher_config.get_group(&self.group).unwrap();
+        group_config.get_market(market).is_some()
+    }
+
+    /// Starts the handler and orderbook subscription of a market which was not loaded, creating its open orders
+    /// account if it does not exist yet. Returns whether the market is loaded afterwards.
+    async fn load_market(&mut self, symbol: &str) -> bool {
+        if !self.is_market_in_group(symbol) {
+            println!("Market {} is not in group {}.", symbol, self.group);
+            return false;
+        }
+        if self.is_market_active(symbol) {
+            println!("Market {} is already loaded.", symbol);
+            return true;
+        }
+        if self.replay.is_some() {
+            println!("Loading markets is not possible while replaying a recording, pass them with '--markets' instead.");
+            return false;
+        }
+
+        println!("Loading market {}.", symbol);
+        let cypher_config = Arc::clone(&self.cypher_config);
+        let market = cypher_config
+            .get_group(&self.group)
+            .unwrap()
+            .get_market(symbol)
+            .unwrap();
+        let cypher_user_pks = derive_cypher_user_addresses(&self.cypher_group_pk, &self.owner_pk);
+        let prepared = match self.prepare_market(market, &cypher_user_pks).await {
+            Some(p) => p,
+            None => {
+                println!("Market {} could not be loaded.", symbol);
+                return false;
+            }
+        };
+
+        // only the new market is added to the running services, restarting them could drop fills on the other markets
+        self.orderbook_provider.add_book(prepared.ob_ctx).await;
+        self.open_orders_provider
+            .add_open_orders(prepared.open_orders_pk)
+            .await;
+        self.fills_provider
+            .add_fills_context(prepared.fills_ctx)
+            .await;
+        if let Some(recorder_service) = &self.recorder_service {
+            recorder_service.add_keys(&prepared.ais_pks).await;
+        }
+
+        let handler = prepared.handler;
+        self.handlers.push(Arc::clone(&handler));
+        let t = tokio::spawn(async move {
+            handler.start().await;
+        });
+        self.tasks.push(t);
+
+        if let Err(e) = self.ai_service.add_keys(&prepared.ais_pks).await {
+            println!(
+                "An error occurred while fetching the accounts of market {}, they are fetched on the next poll. Err: {:?}",
+                symbol, e
+            );
+        }
+
+        if let Some(markets) = &mut self.active_markets {
+            markets.insert(symbol.to_string());
+        }
+        self.failed_markets.remove(symbol);
+        let unloaded_markets: Vec<String> = cypher_config
+            .get_group(&self.group)
+            .unwrap()
+            .markets
+            .iter()
+            .filter(|m| {
+                !self
+                    .handlers
+                    .iter()
+                    .any(|h| h.market_context.name == m.name)
+            })
+            .map(|m| m.name.to_string())
+            .collect();
+        self.kill_switch
+            .register(
+                self.handlers.clone(),
+                unloaded_markets,
+                Arc::clone(&self.cypher_context),
+                Arc::clone(&self.cm_service),
+            )
+            .await;
+
+        println!("Loaded market {}.", symbol);
+        true
+    }
+
+    /// Waits until the handler of a freshly loaded market has seen its orderbook and open orders,
+    /// or `MARKET_LOAD_TIMEOUT` passes.
+    async fn wait_for_market(&self, symbol: &str) {
+        let handler = match self
+            .handlers
+            .iter()
+            .find(|h| h.market_context.name == symbol)
+        {
+            Some(h) => h,
+            None => {
+                return;
+            }
+        };
+        let deadline = Instant::now() + MARKET_LOAD_TIMEOUT;
+        while Instant::now() < deadline {
+            let ready = handler.get_orderbook().await.is_ok()
+                && handler.get_open_orders().await.is_ok()
+                && self.cypher_context.get_user().await.is_ok();
+            if ready {
+                return;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        println!("Market {} has not received its orderbook yet.", symbol);
+    }
+
+    /// Closes the open orders accounts of the given market or of every market in the group and unloads those markets,
+    /// so the accounts are not created again when the services restart.
+    async fn close_open_orders(&mut self, info: CloseOpenOrdersInfo) {
+        if self.replay.is_some() {
+            println!("Closing open orders accounts is not possible while replaying a recording.");
+            return;
+        }
+        // the rent goes back to the signer, which has to be the owner
+        let owner = match self.get_owner_keypair() {
+            Ok(kp) => kp,
+            Err(e) => {
+                println!(
+                    "There was an error closing the open orders accounts: {:?}",
+                    e
+                );
+                return;
+            }
+        };
+
+        // markets which are not loaded still have an open orders account if they were used before
+        let cypher_config = Arc::clone(&self.cypher_config);
+        let group_config = cypher_config.get_group(&self.group).unwrap();
+        let markets: Vec<&CypherMarketConfig> = match &info.symbol {
+            Some(symbol) => match group_config.get_market(symbol) {
+                Some(m) => vec![m],
+                None => {
+                    println!("Market {} is not in group {}.", symbol, self.group);
+                    return;
+                }
+            },
+            None => group_config.markets.iter().collect(),
+        };
+
+        let mut closed: Vec<String> = Vec::new();
+        let mut reclaimed: u64 = 0;
+        for market in markets {
+            if let Some(lamports) = self
+                .close_market_open_orders(owner.as_ref(), market, info.settle)
+                .await
+            {
+                closed.push(market.name.to_string());
+                reclaimed += lamports;
+            }
+        }
+        if closed.is_empty() {
+            println!("No open orders accounts were closed.");
+            return;
+        }
+        println!(
+            "Closed {} open orders accounts, reclaimed {} SOL in total.",
+            closed.len(),
+            lamports_to_sol(reclaimed)
+        );
+
+        let mut active_markets: HashSet<String> = match &self.active_markets {
+            Some(markets) => markets.clone(),
+            None => self
+                .handlers
+                .iter()
+                .map(|h| h.market_context.name.to_string())
+                .collect(),
+        };
+        let mut unloaded: Vec<String> = Vec::new();
+        for name in &closed {
+            if active_markets.remove(name) {
+                unloaded.push(name.to_string());
+            }
+        }
+        self.active_markets = Some(active_markets);
+        if unloaded.is_empty() {
+            return;
+        }
+
+        self.stop_tasks().await;
+        self.services_shutdown = channel::<bool>(1).0;
+        if let Err(e) = self.start_services().await {
+            println!(
+                "An error occurred while restarting the services. Err: {:?}",
+                e
+            );
+            return;
+        }
+        self.spawn_tasks();
+
+        println!(
+            "Unloaded {}, use 'load {{symbol}}' to trade on them again.",
+            unloaded.join(", ")
+        );
+    }
+
+    /// Closes the market's open orders account if it has no orders and nothing unsettled, settling first if asked to,
+    /// and returns the lamports reclaimed. Funds can only be settled on a loaded market.
+    async fn close_market_open_orders(
+        &self,
+        owner: &Keypair,
+        market: &CypherMarketConfig,
+        settle: bool,
+    ) -> Option<u64> {
+        let name = &market.name;
+        let dex_market_pk = Pubkey::from_str(&market.address).unwrap();
+        let open_orders_pk = derive_open_orders_address(&dex_market_pk, &self.cypher_user_pk).0;
+
+        let account = match self.rpc_client.get_account(&open_orders_pk).await {
+            Ok(Some(a)) => a,
+            Ok(None) => {
+                println!("Market {} has no open orders account.", name);
+                return None;
+            }
+            Err(e) => {
+                println!(
+                    "There was an error fetching the open orders account for market {}. Err: {}",
+                    name, e
+                );
+                return None;
+            }
+        };
+        let lamports = account.lamports;
+        let open_orders_account: OpenOrders = parse_dex_account(account.data);
+
+        let open_orders = get_open_orders(&open_orders_account);
+        if !open_orders.is_empty() {
+            println!(
+                "Market {} still has {} open orders, cancel them first.",
+                name,
+                open_orders.len()
+            );
+            return None;
+        }
+
+        if has_unsettled_funds(&open_orders_account) {
+            if !settle {
+                println!(
+                    "Market {} has unsettled funds, settle them first or pass '--settle'.",
+                    name
+                );
+                return None;
+            }
+            let handler = match self
+                .handlers
+                .iter()
+                .find(|h| &h.market_context.name == name)
+            {
+                Some(h) => h,
+                None => {
+                    println!(
+                        "Market {} has unsettled funds but is not loaded, use 'load {}' to settle them first.",
+                        name, name
+                    );
+                    return None;
+                }
+            };
+
+            let (group, user) = match (
+                self.cypher_context.get_group().await,
+                self.cypher_context.get_user().await,
+            ) {
+                (Ok(g), Ok(u)) => (g, u),
+                _ => {
+                    println!("Cypher group or user not available, could not settle funds.");
+                    return None;
+                }
+            };
+            let hash = self.cm_service.get_latest_blockhash().await;
+            let ctx = HandlerContext {
+                user: Box::new(user),
+                group: Box::new(group),
+                hash: Box::new(hash),
+            };
+            match handler.settle_funds(ctx).await {
+                Ok(s) => {
+                    println!(
+                        "Settled funds on market {}. {}",
+                        name,
+                        self.cluster.get_explorer_tx_url(&s)
+                    );
+                }
+                Err(e) => {
+                    println!(
+                        "There was an error settling funds on market {}. Err: {:?}",
+                        name, e
+                    );
+                    return None;
+                }
+            }
+
+            // the account has to be empty for the dex to let it close
+            let settled = match self.rpc_client.get_account(&open_orders_pk).await {
+                Ok(Some(a)) => !has_unsettled_funds(&parse_dex_account(a.data)),
+                _ => false,
+            };
+            if !settled {
+                println!(
+                    "Market {} still has unsettled funds after settling, not closing its open orders account.",
+                    name
+                );
+                return None;
+            }
+        }
+
+        match close_open_orders(
+            &self.cypher_group_pk,
+            &self.cypher_user_pk,
+            &dex_market_pk,
+            &open_orders_pk,
+            owner,
+            Arc::clone(&self.rpc_client),
+        )
+        .await
+        {
+            Ok(s) => {
+                println!(
+                    "Closed open orders account {} for market {}, reclaimed {} SOL. {}",
+                    open_orders_pk,
+                    name,
+                    lamports_to_sol(lamports),
+                    self.cluster.get_explorer_tx_url(&s)
+                );
+                Some(lamports)
+            }
+            Err(e) => {
+                println!(
+                    "There was an error closing the open orders account for market {}. Err: {:?}",
+                    name, e
+                );
+                None
+            }
+        }
+    }
+
+    fn unlock(&self) {
+        if !self.kill_switch.is_locked() {
+            println!("The session is not locked.");
+            return;
+        }
+        self.kill_switch.unlock();
+        println!("Kill switch released, orders are allowed again.");
+    }
+
     async fn cancel_order(&self, info: CancelOrderInfo) {
         let maybe_group = self.cypher_context.get_group().await;
         let group = match maybe_group {
@@ -1011,24 +2547,86 @@ impl InteractiveCli {
         };
         match handler.cancel_order(ctx, info.order_id).await {
             Ok(s) => {
-                println!("Successfully cancelled order. https://explorer.solana.com/tx/{}?cluster=devnet", s);
+                println!(
+                    "Successfully cancelled order. {}",
+                    self.cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 println!("There was an error placing market order. Err: {:?}", e);
             }
         }
     }
-}
 
-fn trim_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
+    fn trades(&self, info: TradesInfo) {
+        let trades = match self.trade_log.read(info.symbol.as_deref(), info.count) {
+            Ok(t) => t,
+            Err(e) => {
+                println!("There was an error reading the trade log. Err: {:?}", e);
+                return;
+            }
+        };
+
+        if trades.is_empty() {
+            println!("No trades found in the trade log.");
+            return;
+        }
+
+        println!("----- Trades -----");
+        println!(
+            "{:^32} {:^10} {:^10} {:^5} {:^6} {:^12} {:^12} {:^10} {:^40} {:^10}",
+            "Timestamp",
+            "Slot",
+            "Market",
+            "Side",
+            "Maker",
+            "Price",
+            "Size",
+            "Fee",
+            "Order ID",
+            "Client ID"
+        );
+        for trade in trades {
+            println!(
+                "{:^32} {:^10} {:^10} {:^5} {:^6} {:^12} {:^12} {:^10} {:^40} {:^10}",
+                trade.timestamp,
+                trade.slot,
+                trade.market,
+                trade.side,
+                trade.maker,
+                trade.price,
+                trade.quantity,
+                trade.native_fee,
+                trade.order_id,
+                trade.client_order_id
+            );
         }
+        println!("----- Trades -----");
     }
 }
 
+/// Reads stdin on a dedicated thread, since a read from tokio's stdin would keep the runtime from shutting down.
+fn spawn_stdin_reader() -> UnboundedReceiver<io::Result<String>> {
+    let (sender, receiver) = unbounded_channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Whether the dex still holds base or quote tokens for the account, free or locked in orders.
+fn has_unsettled_funds(open_orders: &OpenOrders) -> bool {
+    let native_coin_total = open_orders.native_coin_total;
+    let native_pc_total = open_orders.native_pc_total;
+    native_coin_total != 0 || native_pc_total != 0
+}
+
 fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInteractiveError> {
     if buffer.is_empty() {
         return Ok(None);
@@ -1046,12 +2644,57 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
         return Ok(Some(InteractiveCommand::Help));
     } else if command_word == "exit" {
         return Ok(Some(InteractiveCommand::Exit));
+    } else if command_word == "panic" {
+        let settle = splits.iter().skip(1).any(|s| *s == "settle");
+        return Ok(Some(InteractiveCommand::Panic(settle)));
+    } else if command_word == "unlock" {
+        return Ok(Some(InteractiveCommand::Unlock));
+    } else if command_word == "accounts" {
+        return Ok(Some(InteractiveCommand::Accounts));
+    } else if command_word == "sync-config" {
+        return Ok(Some(InteractiveCommand::SyncConfig));
+    } else if command_word == "close-oo" {
+        if splits.len() < 2 {
+            return Ok(None);
+        }
+        let symbol = match splits[1] {
+            "all" => None,
+            s => Some(s.to_string()),
+        };
+        let settle = splits.iter().skip(2).any(|s| *s == "--settle");
+
+        return Ok(Some(InteractiveCommand::CloseOpenOrders(
+            CloseOpenOrdersInfo { symbol, settle },
+        )));
+    } else if command_word == "load" {
+        if splits.len() < 2 {
+            return Ok(None);
+        }
+
+        return Ok(Some(InteractiveCommand::Load(splits[1].to_string())));
+    } else if command_word == "use" {
+        if splits.len() < 2 {
+            return Ok(None);
+        }
+        if splits[1] == "default" {
+            return Ok(Some(InteractiveCommand::Use(None)));
+        }
+        let account_number = match splits[1].parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => {
+                return Err(CypherInteractiveError::Input);
+            }
+        };
+
+        return Ok(Some(InteractiveCommand::Use(Some(account_number))));
     } else if command_word == "status" {
         return Ok(Some(InteractiveCommand::AccountStatus));
     } else if command_word == "markets" {
         return Ok(Some(InteractiveCommand::MarketsStatus));
     } else if command_word == "tokens" {
         return Ok(Some(InteractiveCommand::TokensStatus));
+    } else if command_word == "pnl" {
+        return Ok(Some(InteractiveCommand::Pnl));
     } else if command_word == "airdrop" {
         return Ok(Some(InteractiveCommand::Airdrop));
     } else if command_word == "new" {
@@ -1066,10 +2709,15 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
         };
 
         return Ok(Some(InteractiveCommand::NewAccount(amount)));
+    } else if command_word == "undelegate" {
+        return Ok(Some(InteractiveCommand::Undelegate));
     } else if command_word == "delegate" {
         if splits.len() < 2 {
             return Ok(None);
         }
+        if splits[1] == "status" {
+            return Ok(Some(InteractiveCommand::DelegateStatus));
+        }
         let pk = splits[1].to_string();
 
         return Ok(Some(InteractiveCommand::Delegate(pk)));
@@ -1097,9 +2745,21 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
                 return Err(CypherInteractiveError::Input);
             }
         };
+        let l3 = splits.iter().skip(3).any(|s| *s == "--l3");
+        let tick = match splits.iter().skip(3).position(|s| *s == "--group") {
+            Some(idx) => match splits.get(idx + 4).map(|s| s.parse::<f64>()) {
+                Some(Ok(t)) if t > 0.0 => Some(t),
+                _ => {
+                    return Err(CypherInteractiveError::Input);
+                }
+            },
+            None => None,
+        };
         return Ok(Some(InteractiveCommand::OrderBookStatus(OrderBookInfo {
             symbol,
             depth,
+            l3,
+            tick,
         })));
     } else if command_word == "limit" {
         if splits.len() < 5 {
@@ -1112,25 +2772,28 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
             Side::Ask
         };
         let symbol = splits[2].to_string();
-        let amount = match splits[3].parse::<u64>() {
+        let amount = match splits[3].parse::<f64>() {
             Ok(a) => a,
             Err(_) => {
                 return Err(CypherInteractiveError::Input);
             }
         };
 
-        let price = match splits[4].parse::<u64>() {
+        let price = match splits[4].parse::<f64>() {
             Ok(a) => a,
             Err(_) => {
                 return Err(CypherInteractiveError::Input);
             }
         };
 
+        let force = splits.iter().skip(5).any(|s| *s == "--force");
+
         return Ok(Some(InteractiveCommand::Limit(LimitOrderInfo {
             symbol,
             price,
             amount,
             side,
+            force,
         })));
     } else if command_word == "market" {
         if splits.len() < 4 {
@@ -1143,19 +2806,22 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
             Side::Ask
         };
         let symbol = splits[2].to_string();
-        let amount = match splits[3].parse::<u64>() {
+        let amount = match splits[3].parse::<f64>() {
             Ok(a) => a,
             Err(_) => {
                 return Err(CypherInteractiveError::Input);
             }
         };
 
+        let force = splits.iter().skip(4).any(|s| *s == "--force");
+
         println!("market {:?} {} {}", side, symbol, amount);
 
         return Ok(Some(InteractiveCommand::Market(MarketOrderInfo {
             symbol,
             amount,
             side,
+            force,
         })));
     } else if command_word == "cancel" {
         if splits.len() < 3 {
@@ -1174,6 +2840,21 @@ fn get_command(buffer: String) -> Result<Option<InteractiveCommand>, CypherInter
             symbol,
             order_id,
         })));
+    } else if command_word == "trades" {
+        let mut symbol: Option<String> = None;
+        let mut count: usize = 20;
+
+        for split in splits.iter().skip(1) {
+            match split.parse::<usize>() {
+                Ok(c) => count = c,
+                Err(_) => symbol = Some(split.to_string()),
+            }
+        }
+
+        return Ok(Some(InteractiveCommand::Trades(TradesInfo {
+            symbol,
+            count,
+        })));
     }
 
     Ok(None)
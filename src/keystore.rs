@@ -0,0 +1,284 @@
+use {
+    crate::CypherInteractiveError,
+    aes_gcm::{
+        aead::{Aead, NewAead},
+        Aes256Gcm, Key, Nonce,
+    },
+    rand::{rngs::OsRng, RngCore},
+    serde::{Deserialize, Serialize},
+    solana_sdk::{signature::Keypair, signer::Signer},
+    std::{
+        fs::{File, OpenOptions},
+        io::{Read, Write},
+        path::Path,
+    },
+};
+
+/// Read before prompting for a keystore passphrase, for sessions which are not attached to a terminal.
+pub const PASSPHRASE_ENV_VAR: &str = "CYPHER_KEYPAIR_PASSPHRASE";
+
+const KEYSTORE_VERSION: u8 = 1;
+const KDF_SCRYPT: &str = "scrypt";
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+// interactive scrypt parameters, about a second on current hardware
+const SCRYPT_LOG_N: u8 = 17;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+/// A keypair encrypted with AES-256-GCM under a key derived from a passphrase with scrypt.
+///
+/// Binary fields are base64 encoded, the public key is kept in the clear so the file can be identified without the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keystore {
+    pub version: u8,
+    pub pubkey: String,
+    pub kdf: String,
+    pub kdf_params: ScryptParams,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self, CypherInteractiveError> {
+        let params = ScryptParams {
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+        };
+        let mut salt = [0_u8; SALT_LEN];
+        let mut nonce = [0_u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = derive_key(passphrase, &salt, &params)?;
+        let cipher = Aes256Gcm::new(Key::from_slice(&key));
+        let ciphertext =
+            match cipher.encrypt(Nonce::from_slice(&nonce), keypair.to_bytes().as_ref()) {
+                Ok(c) => c,
+                Err(_) => {
+                    return Err(CypherInteractiveError::KeypairEncrypt);
+                }
+            };
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            pubkey: keypair.pubkey().to_string(),
+            kdf: KDF_SCRYPT.to_string(),
+            kdf_params: params,
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the keypair, a wrong passphrase and a tampered file are both reported as `KeypairDecrypt`.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keypair, CypherInteractiveError> {
+        if self.version != KEYSTORE_VERSION || self.kdf != KDF_SCRYPT {
+            return Err(CypherInteractiveError::KeystoreUnsupported(format!(
+                "version {} with kdf {}",
+                self.version, self.kdf
+            )));
+        }
+        let salt = decode_field("salt", &self.salt)?;
+        let nonce = decode_field("nonce", &self.nonce)?;
+        let ciphertext = decode_field("ciphertext", &self.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(CypherInteractiveError::KeypairParse(
+                "keystore nonce has an invalid length".to_string(),
+            ));
+        }
+        // the parameters come from the file, a tampered one could make scrypt take unbounded time and memory
+        let params = &self.kdf_params;
+        if params.log_n > SCRYPT_LOG_N || params.r > SCRYPT_R || params.p > SCRYPT_P {
+            return Err(CypherInteractiveError::KeystoreUnsupported(format!(
+                "scrypt parameters {:?} above the ones keystores are written with",
+                params
+            )));
+        }
+
+        let key = derive_key(passphrase, &salt, &self.kdf_params)?;
+        let cipher = Aes256Gcm::new(Key::from_slice(&key));
+        let bytes = match cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref()) {
+            Ok(b) => b,
+            Err(_) => {
+                return Err(CypherInteractiveError::KeypairDecrypt);
+            }
+        };
+
+        keypair_from_bytes(&bytes)
+    }
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: &ScryptParams,
+) -> Result<[u8; KEY_LEN], CypherInteractiveError> {
+    let scrypt_params = match scrypt::Params::new(params.log_n, params.r, params.p) {
+        Ok(p) => p,
+        Err(_) => {
+            return Err(CypherInteractiveError::KeystoreUnsupported(format!(
+                "scrypt parameters {:?}",
+                params
+            )));
+        }
+    };
+    let mut key = [0_u8; KEY_LEN];
+    if scrypt::scrypt(passphrase.as_bytes(), salt, &scrypt_params, &mut key).is_err() {
+        return Err(CypherInteractiveError::KeypairEncrypt);
+    }
+
+    Ok(key)
+}
+
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>, CypherInteractiveError> {
+    match base64::decode(value) {
+        Ok(b) => Ok(b),
+        Err(_) => Err(CypherInteractiveError::KeypairParse(format!(
+            "keystore {} is not valid base64",
+            name
+        ))),
+    }
+}
+
+fn keypair_from_bytes(bytes: &[u8]) -> Result<Keypair, CypherInteractiveError> {
+    match Keypair::from_bytes(bytes) {
+        Ok(kp) => Ok(kp),
+        Err(e) => {
+            println!("Failed to load keypair from bytes: {}", e);
+            Err(CypherInteractiveError::KeypairLoad)
+        }
+    }
+}
+
+/// The contents of a keypair file, which is either in the clear or a keystore which needs a passphrase.
+pub enum KeypairFile {
+    Plain(Keypair),
+    Encrypted(Keystore),
+}
+
+/// Parses a keypair given as a JSON byte array like the solana cli writes, a base58 encoded
+/// private key, or a keystore.
+pub fn parse_keypair_file(contents: &str) -> Result<KeypairFile, CypherInteractiveError> {
+    let contents = contents.trim();
+
+    if contents.starts_with('{') {
+        return match serde_json::from_str::<Keystore>(contents) {
+            Ok(k) => Ok(KeypairFile::Encrypted(k)),
+            Err(e) => Err(CypherInteractiveError::KeypairParse(format!(
+                "invalid keystore: {}",
+                e
+            ))),
+        };
+    }
+
+    if contents.starts_with('[') {
+        let bytes: Vec<u8> = match serde_json::from_str(contents) {
+            Ok(b) => b,
+            Err(e) => {
+                return Err(CypherInteractiveError::KeypairParse(format!(
+                    "invalid byte array: {}",
+                    e
+                )));
+            }
+        };
+        return Ok(KeypairFile::Plain(keypair_from_bytes(&bytes)?));
+    }
+
+    let bytes = match bs58::decode(contents).into_vec() {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(CypherInteractiveError::KeypairParse(format!(
+                "neither a byte array, base58 nor a keystore: {}",
+                e
+            )));
+        }
+    };
+
+    Ok(KeypairFile::Plain(keypair_from_bytes(&bytes)?))
+}
+
+/// Reads the passphrase from `PASSPHRASE_ENV_VAR` or, if it is not set, prompts for it without echoing.
+pub fn read_passphrase(prompt: &str) -> Result<String, CypherInteractiveError> {
+    if let Ok(p) = std::env::var(PASSPHRASE_ENV_VAR) {
+        return Ok(p);
+    }
+
+    match rpassword::prompt_password(prompt) {
+        Ok(p) => Ok(p),
+        Err(e) => {
+            println!("Failed to read passphrase: {}", e);
+            Err(CypherInteractiveError::PassphraseRead)
+        }
+    }
+}
+
+/// Loads the keypair at the given path, prompting for the passphrase if it is a keystore.
+pub fn load_keypair(path: &Path) -> Result<Keypair, CypherInteractiveError> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("Failed to open keypair file {}: {}", path.display(), e);
+            return Err(CypherInteractiveError::KeypairFileOpen);
+        }
+    };
+    let mut contents = String::new();
+    if let Err(e) = file.read_to_string(&mut contents) {
+        println!("Failed to read keypair file {}: {}", path.display(), e);
+        return Err(CypherInteractiveError::KeypairFileRead);
+    }
+
+    match parse_keypair_file(&contents)? {
+        KeypairFile::Plain(kp) => Ok(kp),
+        KeypairFile::Encrypted(keystore) => {
+            let passphrase = read_passphrase(&format!(
+                "Passphrase for {} ({}): ",
+                path.display(),
+                keystore.pubkey
+            ))?;
+            keystore.decrypt(&passphrase)
+        }
+    }
+}
+
+/// Writes the keystore to a new file, an existing file is never overwritten.
+pub fn write_keystore(path: &Path, keystore: &Keystore) -> Result<(), CypherInteractiveError> {
+    let json = match serde_json::to_string_pretty(keystore) {
+        Ok(j) => j,
+        Err(_) => {
+            return Err(CypherInteractiveError::KeypairFileWrite);
+        }
+    };
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = match options.open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("Failed to create keystore file {}: {}", path.display(), e);
+            return Err(CypherInteractiveError::KeypairFileWrite);
+        }
+    };
+    if writeln!(file, "{}", json).is_err() {
+        return Err(CypherInteractiveError::KeypairFileWrite);
+    }
+
+    Ok(())
+}
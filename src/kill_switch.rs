@@ -0,0 +1,155 @@
+use {
+    crate::{
+        cypher_context::CypherContext,
+        market_handler::{Handler, HandlerContext},
+        services::ChainMetaService,
+        utils::get_open_orders,
+        CypherInteractiveError,
+    },
+    std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    tokio::sync::RwLock,
+};
+
+/// Cancels every open order on every market and keeps new orders from being placed until it is unlocked.
+///
+/// The kill switch is shared with `main` so it can be engaged from a signal handler,
+/// the handlers it acts on are registered once the session's services have been started.
+pub struct KillSwitch {
+    locked: AtomicBool,
+    handlers: RwLock<Vec<Arc<Handler>>>,
+    /// The markets in the group without a handler, orders resting on them can not be cancelled.
+    unloaded_markets: RwLock<Vec<String>>,
+    cypher_context: RwLock<Arc<CypherContext>>,
+    cm_service: RwLock<Arc<ChainMetaService>>,
+}
+
+impl KillSwitch {
+    pub fn default() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            handlers: RwLock::new(Vec::new()),
+            unloaded_markets: RwLock::new(Vec::new()),
+            cypher_context: RwLock::new(Arc::new(CypherContext::default())),
+            cm_service: RwLock::new(Arc::new(ChainMetaService::default())),
+        }
+    }
+
+    pub async fn register(
+        &self,
+        handlers: Vec<Arc<Handler>>,
+        unloaded_markets: Vec<String>,
+        cypher_context: Arc<CypherContext>,
+        cm_service: Arc<ChainMetaService>,
+    ) {
+        *self.handlers.write().await = handlers;
+        *self.unloaded_markets.write().await = unloaded_markets;
+        *self.cypher_context.write().await = cypher_context;
+        *self.cm_service.write().await = cm_service;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+
+    pub fn unlock(&self) {
+        self.locked.store(false, Ordering::SeqCst);
+    }
+
+    /// Locks the session, then cancels every open order on every loaded market and optionally settles funds.
+    ///
+    /// Markets which are not loaded are listed, so their orders can be cancelled once they are. Failures are reported but do not stop the remaining orders from being cancelled.
+    pub async fn engage(&self, settle: bool) {
+        self.locked.store(true, Ordering::SeqCst);
+        println!("[KS] Kill switch engaged, new orders are blocked until 'unlock'.");
+
+        let handlers = self.handlers.read().await.clone();
+        let mut cancelled: usize = 0;
+        let mut failed: usize = 0;
+
+        for handler in &handlers {
+            let name = &handler.market_context.name;
+            let open_orders = match handler.get_open_orders().await {
+                Ok(oo) => get_open_orders(&oo),
+                Err(_) => {
+                    println!("[KS] Open orders for market {} not available.", name);
+                    continue;
+                }
+            };
+
+            for order in open_orders {
+                let ctx = match self.get_handler_context().await {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        println!("[KS] Could not prepare cancel for market {}: {:?}", name, e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                match handler.cancel_order(ctx, order.order_id).await {
+                    Ok(s) => {
+                        println!(
+                            "[KS] Cancelled order {} on market {}. Signature: {}",
+                            order.order_id, name, s
+                        );
+                        cancelled += 1;
+                    }
+                    Err(e) => {
+                        println!(
+                            "[KS] Failed to cancel order {} on market {}: {:?}",
+                            order.order_id, name, e
+                        );
+                        failed += 1;
+                    }
+                }
+            }
+
+            if settle {
+                let ctx = match self.get_handler_context().await {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        println!("[KS] Could not prepare settle for market {}: {:?}", name, e);
+                        continue;
+                    }
+                };
+                match handler.settle_funds(ctx).await {
+                    Ok(s) => {
+                        println!("[KS] Settled funds on market {}. Signature: {}", name, s);
+                    }
+                    Err(e) => {
+                        println!("[KS] Failed to settle funds on market {}: {:?}", name, e);
+                    }
+                }
+            }
+        }
+
+        println!(
+            "[KS] Cancelled {} orders, {} cancels failed. The session stays locked.",
+            cancelled, failed
+        );
+
+        let unloaded_markets = self.unloaded_markets.read().await;
+        if !unloaded_markets.is_empty() {
+            println!(
+                "[KS] Markets {} are not loaded, orders resting on them were not cancelled. Load them with 'load {{symbol}}' and engage the kill switch again.",
+                unloaded_markets.join(", ")
+            );
+        }
+    }
+
+    async fn get_handler_context(&self) -> Result<HandlerContext, CypherInteractiveError> {
+        let cypher_context = Arc::clone(&*self.cypher_context.read().await);
+        let cm_service = Arc::clone(&*self.cm_service.read().await);
+        let group = cypher_context.get_group().await?;
+        let user = cypher_context.get_user().await?;
+        let hash = cm_service.get_latest_blockhash().await;
+
+        Ok(HandlerContext {
+            user: Box::new(user),
+            group: Box::new(group),
+            hash: Box::new(hash),
+        })
+    }
+}
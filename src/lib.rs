@@ -0,0 +1,87 @@
+pub mod accounts_cache;
+pub mod config;
+pub mod cypher_context;
+pub mod fast_tx_builder;
+pub mod interactive_cli;
+pub mod keystore;
+pub mod kill_switch;
+pub mod market_handler;
+pub mod pnl;
+pub mod providers;
+#[cfg(unix)]
+pub mod remote_signer;
+pub mod risk;
+pub mod rpc;
+pub mod serum_event_queue;
+pub mod serum_slab;
+pub mod services;
+pub mod trade_log;
+pub mod utils;
+
+use {serum_slab::SlabTreeError, solana_client::client_error::ClientError, std::path::PathBuf};
+
+pub const CYPHER_CONFIG_PATH: &str = "./cfg/group.json";
+
+#[derive(Debug)]
+pub enum CypherInteractiveError {
+    KeypairFileOpen,
+    KeypairFileRead,
+    KeypairLoad,
+    KeypairParse(String),
+    KeypairEncrypt,
+    KeypairDecrypt,
+    KeypairFileWrite,
+    KeystoreUnsupported(String),
+    PassphraseRead,
+    RemoteSigner,
+    Input,
+    Airdrop,
+    Deposit,
+    SetDelegate,
+    CouldNotFetchOpenOrders(ClientError),
+    CouldNotCreateOpenOrders(ClientError),
+    CouldNotCloseOpenOrders(ClientError),
+    OpenOrdersNotFound,
+    CouldNotFetchCypherUser(ClientError),
+    OwnerKeypairRequired,
+    CouldNotCreateCypherUser(ClientError),
+    CypherUserNotFound,
+    CouldNotFetchCypherGroup(ClientError),
+    CypherGroupNotFound,
+    ChannelSend,
+    CouldNotFindHandler,
+    UserNotAvailable,
+    GroupNotAvailable,
+    OpenOrdersNotAvailable,
+    OrderBookNotAvailable,
+    InvalidOrderId(u128),
+    TransactionSubmission(ClientError),
+    TradeLogWrite,
+    TradeLogRead,
+    PnlStateRead,
+    PnlStateWrite,
+    InvalidMarketUnits,
+    InvalidPrice(f64),
+    InvalidQuantity(f64),
+    PriceBelowTickSize(f64),
+    QuantityBelowLotSize(f64),
+    CRatioBelowMinimum(f64),
+    InsufficientLiquidity(f64),
+    RiskConfigRead,
+    RiskLogWrite,
+    RiskMaxPosition(f64),
+    RiskMaxOrderNotional(f64),
+    RiskMaxOpenOrders(usize),
+    RiskMaxDailyNotional(f64),
+    RiskPriceBand(f64),
+    InvalidSlab(SlabTreeError),
+    RecordingRead,
+    RecordingWrite,
+    FixturesLoad,
+    ConfigNotFound(Vec<PathBuf>),
+    ConfigRead,
+    ConfigParse(String),
+    ConfigInvalid(Vec<String>),
+    UnknownCluster(String),
+    ConfigSync(String),
+}
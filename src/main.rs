@@ -1,199 +1,520 @@
-mod accounts_cache;
-mod config;
-mod cypher_context;
-mod fast_tx_builder;
-mod interactive_cli;
-mod market_handler;
-mod providers;
-mod serum_slab;
-mod services;
-mod utils;
-
-use config::*;
-
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use cypher::utils::derive_cypher_user_address;
-use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient};
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Keypair, signer::Signer,
 };
-use std::{fs::File, io::Read, str::FromStr, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::sync::broadcast::channel;
+#[cfg(unix)]
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::broadcast::Sender,
+};
 
-use crate::{interactive_cli::InteractiveCli, utils::get_or_init_cypher_user};
-
-pub const CYPHER_CONFIG_PATH: &str = "./cfg/group.json";
+#[cfg(unix)]
+use cypher_interactive::remote_signer::RemoteSigner;
+use cypher_interactive::{
+    config::*,
+    interactive_cli::InteractiveCli,
+    keystore::{load_keypair, read_passphrase, write_keystore, Keystore, PASSPHRASE_ENV_VAR},
+    kill_switch::KillSwitch,
+    risk::{get_risk_config_path, load_risk_config, RiskEngine},
+    rpc::{FixturesRpcBackend, RpcBackend},
+    services::ReplayConfig,
+    trade_log::TRADE_LOG_DIR,
+    utils::{get_or_init_cypher_user, MarginCheck, MarginCheckMode},
+    CypherInteractiveError,
+};
 
 #[derive(Parser)]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Cli {
-    #[clap(short = 'u', long = "user-keypair", parse(from_os_str))]
-    user_keypair: std::path::PathBuf,
+    #[clap(subcommand)]
+    command: Option<KeyCommand>,
+
+    /// The owner of the cypher account, only needed for owner-only commands like deposits and delegation.
+    #[clap(
+        short = 'u',
+        long = "user-keypair",
+        parse(from_os_str),
+        required_unless_present = "owner"
+    )]
+    user_keypair: Option<std::path::PathBuf>,
+
+    /// The owner's public key, for sessions which only hold a delegate key.
+    #[clap(long = "owner", conflicts_with = "user_keypair")]
+    owner: Option<Pubkey>,
+
+    #[clap(
+        short = 's',
+        long = "signer-keypair",
+        parse(from_os_str),
+        required_unless_present = "signer_socket"
+    )]
+    signer_keypair: Option<std::path::PathBuf>,
+
+    /// The Unix socket of a signing daemon holding the signer key, which then never enters this process.
+    #[clap(
+        long = "signer-socket",
+        parse(from_os_str),
+        conflicts_with = "signer_keypair"
+    )]
+    signer_socket: Option<std::path::PathBuf>,
+
+    /// The group config, otherwise taken from `CYPHER_CONFIG`, `./cfg/group.json` or the user config directory.
+    #[clap(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Overrides RPC URLs and adds groups, otherwise taken from `CYPHER_CONFIG_OVERLAY` or the user config directory.
+    #[clap(long = "config-overlay", parse(from_os_str))]
+    config_overlay: Option<PathBuf>,
+
+    /// A cluster from the config, `localnet`, or the RPC URL of any other cluster.
+    #[clap(short = 'c', long = "cluster", required = true)]
+    cluster: Option<String>,
+
+    #[clap(short = 'g', long = "group", required = true)]
+    group: Option<String>,
+
+    /// Loads the given markets at startup, e.g. `--markets SOL,ETH`, the others are loaded when first used.
+    #[clap(long = "markets", value_delimiter = ',')]
+    markets: Option<Vec<String>>,
+
+    /// Loads every market in the group at startup, creating the open orders accounts which do not exist yet,
+    /// instead of loading each market when it is first used.
+    #[clap(long = "load-all-markets", conflicts_with = "markets")]
+    load_all_markets: bool,
+
+    /// Rebuilds the group's markets and tokens from the group account before starting, so newly listed markets are available.
+    /// The synced config only lasts for the session, the config file is never written.
+    #[clap(long = "sync-config")]
+    sync_config: bool,
 
-    #[clap(short = 's', long = "signer-keypair", parse(from_os_str))]
-    signer_keypair: std::path::PathBuf,
+    #[clap(long = "record", parse(from_os_str))]
+    record: Option<std::path::PathBuf>,
 
-    #[clap(short = 'c', long = "cluster")]
-    cluster: String,
+    #[clap(long = "replay", parse(from_os_str), conflicts_with = "record")]
+    replay: Option<std::path::PathBuf>,
 
-    #[clap(short = 'g', long = "group")]
-    group: String,
+    #[clap(long = "replay-speed", default_value = "1.0")]
+    replay_speed: f64,
+
+    #[clap(long = "fixtures", parse(from_os_str))]
+    fixtures: Option<std::path::PathBuf>,
+
+    #[clap(long = "min-c-ratio")]
+    min_c_ratio: Option<f64>,
+
+    #[clap(long = "margin-check", default_value = "block")]
+    margin_check: MarginCheckMode,
 }
 
-#[derive(Debug)]
-pub enum CypherInteractiveError {
-    KeypairFileOpen,
-    KeypairFileRead,
-    KeypairLoad,
-    Input,
-    Airdrop,
-    Deposit,
-    SetDelegate,
-    CouldNotFetchOpenOrders(ClientError),
-    CouldNotCreateOpenOrders(ClientError),
-    OpenOrdersNotFound,
-    CouldNotFetchCypherUser(ClientError),
-    CouldNotCreateCypherUser(ClientError),
-    CypherUserNotFound,
-    ChannelSend,
-    CouldNotFindHandler,
-    UserNotAvailable,
-    GroupNotAvailable,
-    OpenOrdersNotAvailable,
-    OrderBookNotAvailable,
-    InvalidOrderId(u128),
-    TransactionSubmission(ClientError),
+/// Key management, which runs on its own before anything needs a cluster or a group.
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Generates a new keypair and writes it to a passphrase-encrypted keystore.
+    Keygen {
+        #[clap(short = 'o', long = "outfile", parse(from_os_str))]
+        outfile: PathBuf,
+    },
+    /// Encrypts an existing keypair file, either a JSON byte array or a base58 private key, into a keystore.
+    EncryptKey {
+        #[clap(short = 'k', long = "keypair", parse(from_os_str))]
+        keypair: PathBuf,
+
+        #[clap(short = 'o', long = "outfile", parse(from_os_str))]
+        outfile: PathBuf,
+    },
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Cli::parse();
 
-    // load keypairs
-    let user_keypair_path = args.user_keypair.as_path().to_str().unwrap();
-    println!("Loading user keypair from: {}", user_keypair_path);
+    match &args.command {
+        Some(KeyCommand::Keygen { outfile }) => {
+            return report_key_command(encrypt_to_keystore(Keypair::new(), outfile));
+        }
+        Some(KeyCommand::EncryptKey { keypair, outfile }) => {
+            let keypair = match load_keypair(keypair) {
+                Ok(kp) => kp,
+                Err(e) => {
+                    println!("There was an error loading the keypair. {:?}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            return report_key_command(encrypt_to_keystore(keypair, outfile));
+        }
+        None => (),
+    }
 
-    let user_keypair = load_keypair(user_keypair_path).unwrap();
-    let user_pubkey = user_keypair.pubkey();
-    println!("Loaded user keypair with pubkey: {}", user_pubkey);
+    // load keypairs, without the owner keypair the signer acts as the account's delegate
+    let user_keypair = match &args.user_keypair {
+        Some(path) => {
+            println!("Loading user keypair from: {}", path.display());
 
-    let signer_keypair_path = args.signer_keypair.as_path().to_str().unwrap();
-    println!("Loading signer keypair from: {}", signer_keypair_path);
+            let user_keypair = match load_keypair(path) {
+                Ok(kp) => kp,
+                Err(e) => {
+                    println!("There was an error loading the user keypair. {:?}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            println!("Loaded user keypair with pubkey: {}", user_keypair.pubkey());
+            Some(Arc::new(user_keypair))
+        }
+        None => None,
+    };
+    let user_pubkey = match &user_keypair {
+        Some(kp) => kp.pubkey(),
+        None => args.owner.unwrap(),
+    };
 
-    let signer_keypair = load_keypair(signer_keypair_path).unwrap();
-    let signer_pubkey = signer_keypair.pubkey();
-    println!("Loaded signer keypair with pubkey: {}", signer_pubkey);
+    let signer = match load_signer(&args, user_keypair.as_ref()) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("There was an error loading the signer. {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Loaded signer with pubkey: {}", signer.pubkey());
+    if user_keypair.is_none() {
+        println!(
+            "Running as delegate of owner {}, owner-only commands are not available.",
+            user_pubkey
+        );
+    }
 
-    let cypher_config = Arc::new(load_cypher_config(CYPHER_CONFIG_PATH).unwrap());
+    let config_path = match find_cypher_config(args.config.as_deref()) {
+        Ok(p) => p,
+        Err(e) => {
+            println!("There was an error loading the config. {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut cypher_config =
+        match discover_cypher_config(Some(&config_path), args.config_overlay.as_deref()) {
+            Ok(c) => c,
+            Err(CypherInteractiveError::ConfigInvalid(problems)) => {
+                println!("The config is invalid:");
+                for problem in problems {
+                    println!("\t{}", problem);
+                }
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                println!("There was an error loading the config. {:?}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+    // the risk limits live next to the group config, wherever it was found
+    let risk_config = match load_risk_config(&get_risk_config_path(&config_path)) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("There was an error loading the risk config. {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let risk_engine = Arc::new(RiskEngine::new(risk_config, Path::new(TRADE_LOG_DIR)));
 
-    let cluster = args.cluster;
-    let group_name = args.group;
-    let cluster_config = cypher_config.get_config_for_cluster(&cluster);
-    let rpc_client = Arc::new(RpcClient::new_with_commitment(
-        cluster_config.rpc_url.to_string(),
-        CommitmentConfig::confirmed(),
-    ));
-    println!("Connecting to cluster: {}", cluster);
+    let cluster = match cypher_config.get_cluster(args.cluster.as_ref().unwrap()) {
+        Ok(c) => c,
+        Err(e) => {
+            println!(
+                "There was an error resolving the cluster. {:?} Known clusters: {}, or an RPC URL.",
+                e,
+                cypher_config.get_cluster_names().join(", ")
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let group_name = args.group.unwrap();
+    let rpc_client: Arc<dyn RpcBackend> = match &args.fixtures {
+        Some(dir) => {
+            println!("Serving accounts from fixtures in: {}", dir.display());
+            match FixturesRpcBackend::load(dir) {
+                Ok(b) => Arc::new(b),
+                Err(e) => {
+                    println!("There was an error loading the fixtures. {:?}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => {
+            println!(
+                "Connecting to cluster: {} at {}",
+                cluster.name, cluster.config.rpc_url
+            );
+            Arc::new(RpcClient::new_with_commitment(
+                cluster.config.rpc_url.to_string(),
+                CommitmentConfig::confirmed(),
+            ))
+        }
+    };
     println!("Using group: {}", group_name);
 
-    let group_config = Arc::new(cypher_config.get_group(&group_name).unwrap());
+    let group_config = match cypher_config.get_group(&group_name) {
+        Some(g) => g,
+        None => {
+            println!("Group {} is not in the config.", group_name);
+            return ExitCode::FAILURE;
+        }
+    };
     let cypher_group_pk = Pubkey::from_str(&group_config.address).unwrap();
-    let cypher_user_pk = derive_cypher_user_address(&cypher_group_pk, &user_keypair.pubkey()).0;
+    let cypher_user_pk = derive_cypher_user_address(&cypher_group_pk, &user_pubkey).0;
 
-    let cypher_user_res = get_or_init_cypher_user(
-        &user_keypair,
-        &cypher_group_pk,
-        &cypher_user_pk,
-        Arc::clone(&rpc_client),
-        cluster.to_string(),
-    )
-    .await;
+    if args.sync_config {
+        println!("Syncing group {} from chain.", group_name);
+        let sync_res = match fetch_cypher_group(&cypher_group_pk, Arc::clone(&rpc_client)).await {
+            Ok(group) => sync_group_config(&group, group_config, Arc::clone(&rpc_client)).await,
+            Err(e) => Err(e),
+        };
+        match sync_res {
+            Ok((synced, report)) => {
+                report.print();
+                cypher_config.set_group(synced);
+            }
+            Err(e) => {
+                println!("There was an error syncing the group config. {:?}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        match cypher_config.validate() {
+            Ok(_) => (),
+            Err(CypherInteractiveError::ConfigInvalid(problems)) => {
+                println!("The synced config is invalid:");
+                for problem in problems {
+                    println!("\t{}", problem);
+                }
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                println!("There was an error validating the synced config. {:?}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        println!(
+            "The synced config is only used for this session, the config file is left as it is."
+        );
+    }
 
-    match cypher_user_res {
-        Ok(_) => {
-            println!(
-                "Successfully fetched cypher user account with key: {}",
-                cypher_user_pk
-            );
+    // markets are loaded when first used, so starting a session sends no transactions for markets it never trades,
+    // a replay can not load markets later on so it loads every market in the recording unless told otherwise
+    let markets: Option<Vec<String>> = match args.markets {
+        Some(m) => Some(m.into_iter().filter(|s| !s.is_empty()).collect()),
+        None if args.load_all_markets || args.replay.is_some() => None,
+        None => Some(Vec::new()),
+    };
+    if let Some(markets) = markets.as_ref().filter(|m| !m.is_empty()) {
+        let group_config = cypher_config.get_group(&group_name).unwrap();
+        for market in markets {
+            if group_config.get_market(market).is_none() {
+                println!("Market {} is not in group {}.", market, group_name);
+                return ExitCode::FAILURE;
+            }
         }
-        Err(e) => {
-            println!(
-                "There was an error getting or creating the cypher user account. {:?}",
-                e
-            );
-            return;
+        println!("Loading markets at startup: {}", markets.join(", "));
+    }
+
+    let replay = args.replay.map(|path| ReplayConfig {
+        path,
+        speed: args.replay_speed,
+    });
+
+    // when replaying, the cypher user account comes from the recording
+    if replay.is_none() {
+        // only the owner can create the account, a delegate needs it to exist already
+        let cypher_user_res = match &user_keypair {
+            Some(kp) => get_or_init_cypher_user(
+                kp.as_ref(),
+                &cypher_group_pk,
+                &cypher_user_pk,
+                Arc::clone(&rpc_client),
+                &cluster,
+            )
+            .await
+            .map(|_| ()),
+            None => match rpc_client.get_account(&cypher_user_pk).await {
+                Ok(Some(_)) => Ok(()),
+                Ok(None) => Err(CypherInteractiveError::CypherUserNotFound),
+                Err(e) => Err(CypherInteractiveError::CouldNotFetchCypherUser(e)),
+            },
+        };
+
+        match cypher_user_res {
+            Ok(_) => {
+                println!(
+                    "Successfully fetched cypher user account with key: {}",
+                    cypher_user_pk
+                );
+            }
+            Err(e) => {
+                println!(
+                    "There was an error getting or creating the cypher user account. {:?}",
+                    e
+                );
+                return ExitCode::FAILURE;
+            }
         }
     }
 
     let (shutdown_send, mut _shutdown_recv) = channel::<bool>(1);
-    let arc_kp = Arc::new(signer_keypair);
+    let kill_switch = Arc::new(KillSwitch::default());
+
+    #[cfg(unix)]
+    {
+        let kill_switch = Arc::clone(&kill_switch);
+        let shutdown_send = shutdown_send.clone();
+        tokio::spawn(async move {
+            handle_signals(kill_switch, shutdown_send).await;
+        });
+    }
 
     let interactive = InteractiveCli::new(
-        Arc::clone(&cypher_config),
-        cluster.clone(),
+        Arc::new(cypher_config),
+        cluster,
         group_name.clone(),
         Arc::clone(&rpc_client),
         shutdown_send.clone(),
-        Arc::clone(&arc_kp),
+        signer,
+        user_keypair,
+        user_pubkey,
         cypher_user_pk,
         cypher_group_pk,
+        args.record,
+        replay,
+        MarginCheck {
+            min_c_ratio: args.min_c_ratio,
+            mode: args.margin_check,
+        },
+        risk_engine,
+        Arc::clone(&kill_switch),
+        markets,
     );
 
-    tokio::select! {
-        cli_res = interactive.start() => {
-            match cli_res {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("An error occurred while running the application loop: {:?}", e);
-                }
-            }
-        },
-        _ = tokio::signal::ctrl_c() => {
-            match shutdown_send.send(true) {
+    // the interactive loop stops taking commands once it sees the shutdown signal and then waits for every task to stop
+    let ctrl_c_shutdown_send = shutdown_send.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            match ctrl_c_shutdown_send.send(true) {
                 Ok(_) => {
                     println!("Sucessfully sent shutdown signal. Waiting for tasks to complete...")
-                },
+                }
                 Err(e) => {
                     println!("Failed to send shutdown error: {}", e);
                 }
             };
-        },
+        }
+    });
+
+    match interactive.start().await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!(
+                "An error occurred while running the application loop: {:?}",
+                e
+            );
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn load_keypair(path: &str) -> Result<Keypair, CypherInteractiveError> {
-    let fd = File::open(path);
+/// Engages the kill switch on SIGUSR1, and on SIGTERM before shutting down so nothing is left resting on the book.
+#[cfg(unix)]
+async fn handle_signals(kill_switch: Arc<KillSwitch>, shutdown_send: Sender<bool>) {
+    let mut sigusr1 = signal(SignalKind::user_defined1()).unwrap();
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
 
-    let mut file = match fd {
-        Ok(f) => f,
-        Err(e) => {
-            println!("Failed to load keypair file: {}", e);
-            return Err(CypherInteractiveError::KeypairFileOpen);
+    loop {
+        tokio::select! {
+            _ = sigusr1.recv() => {
+                println!("Received SIGUSR1, engaging the kill switch.");
+                kill_switch.engage(false).await;
+            },
+            _ = sigterm.recv() => {
+                println!("Received SIGTERM, engaging the kill switch before shutting down.");
+                kill_switch.engage(false).await;
+                match shutdown_send.send(true) {
+                    Ok(_) => (),
+                    Err(e) => {
+                        println!("Failed to send shutdown signal: {}", e);
+                    }
+                };
+                break;
+            },
         }
-    };
+    }
+}
 
-    let file_string = &mut String::new();
-    let file_read_res = file.read_to_string(file_string);
+/// Loads the signer from its keypair file, or connects to the signing daemon holding it.
+fn load_signer(
+    args: &Cli,
+    user_keypair: Option<&Arc<Keypair>>,
+) -> Result<Arc<dyn Signer + Send + Sync>, CypherInteractiveError> {
+    if let Some(path) = &args.signer_keypair {
+        // the owner signing for itself, decrypting its keystore again would only ask for the passphrase twice
+        if let (Some(user_path), Some(kp)) = (&args.user_keypair, user_keypair) {
+            if is_same_file(user_path, path) {
+                println!("Signing with the user keypair.");
+                return Ok(Arc::clone(kp) as Arc<dyn Signer + Send + Sync>);
+            }
+        }
+        println!("Loading signer keypair from: {}", path.display());
+        return Ok(Arc::new(load_keypair(path)?));
+    }
 
-    let _ = if let Err(e) = file_read_res {
-        println!("Failed to read keypair bytes from keypair file: {}", e);
-        return Err(CypherInteractiveError::KeypairFileRead);
-    };
+    let socket_path = args.signer_socket.as_ref().unwrap();
+    println!("Connecting to signing daemon at: {}", socket_path.display());
+    #[cfg(unix)]
+    {
+        Ok(Arc::new(RemoteSigner::connect(socket_path)?))
+    }
+    #[cfg(not(unix))]
+    {
+        println!("Signing daemons are only supported on unix.");
+        Err(CypherInteractiveError::RemoteSigner)
+    }
+}
 
-    let keypair_bytes: Vec<u8> = file_string
-        .replace('[', "")
-        .replace(']', "")
-        .replace(',', " ")
-        .split(' ')
-        .map(|x| u8::from_str(x).unwrap())
-        .collect();
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Asks for a new passphrase twice and writes the keypair to a keystore at the given path.
+fn encrypt_to_keystore(keypair: Keypair, path: &Path) -> Result<Pubkey, CypherInteractiveError> {
+    let passphrase = read_passphrase("New passphrase: ")?;
+    // the passphrase from the environment is not typed, so there is nothing to confirm
+    if std::env::var(PASSPHRASE_ENV_VAR).is_err()
+        && read_passphrase("Confirm passphrase: ")? != passphrase
+    {
+        println!("Passphrases do not match.");
+        return Err(CypherInteractiveError::PassphraseRead);
+    }
 
-    let keypair = Keypair::from_bytes(keypair_bytes.as_ref());
+    let keystore = Keystore::encrypt(&keypair, &passphrase)?;
+    write_keystore(path, &keystore)?;
 
-    match keypair {
-        Ok(kp) => Ok(kp),
+    Ok(keypair.pubkey())
+}
+
+fn report_key_command(res: Result<Pubkey, CypherInteractiveError>) -> ExitCode {
+    match res {
+        Ok(pk) => {
+            println!("Wrote encrypted keystore for pubkey: {}", pk);
+            ExitCode::SUCCESS
+        }
         Err(e) => {
-            println!("Failed to load keypair from bytes: {}", e);
-            Err(CypherInteractiveError::KeypairLoad)
+            println!("There was an error writing the keystore. {:?}", e);
+            ExitCode::FAILURE
         }
     }
 }
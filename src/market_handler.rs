@@ -1,21 +1,24 @@
 use crate::{
     fast_tx_builder::FastTxnBuilder,
     providers::{OpenOrdersContext, OrderBook},
-    utils::{get_cancel_order_ix, get_new_order_ix, get_open_orders},
+    risk::{OrderRisk, RiskEngine},
+    rpc::RpcBackend,
+    utils::{
+        get_cancel_order_ix, get_new_order_ix, get_open_orders, get_settle_funds_ix,
+        native_price_to_ui, number_to_f64, MarginCheck, MarginCheckMode, MarginSummary,
+        MarketUnits, TokenBalance,
+    },
     CypherInteractiveError,
 };
-use cypher::{CypherGroup, CypherUser};
+use cypher::{constants::QUOTE_TOKEN_IDX, CypherGroup, CypherUser};
 use serum_dex::{
     instruction::{CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior},
     matching::{OrderType, Side},
     state::{MarketStateV2, OpenOrders},
 };
-use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient};
+use solana_client::client_error::ClientError;
 use solana_sdk::{
-    hash::Hash,
-    instruction::Instruction,
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
+    hash::Hash, instruction::Instruction, pubkey::Pubkey, signature::Signature, signer::Signer,
     transaction::Transaction,
 };
 use std::{num::NonZeroU64, sync::Arc};
@@ -24,6 +27,11 @@ use tokio::{
     sync::{broadcast::Receiver, Mutex, RwLock},
 };
 
+/// How far past the expected fill price, in basis points, a market order may fill before the rest is cancelled.
+pub const MARKET_ORDER_SLIPPAGE_BPS: u64 = 100;
+/// Set on market orders so their fills can be told apart from those of limit orders, which use `1`.
+pub const MARKET_ORDER_CLIENT_ORDER_ID: u64 = 2;
+
 pub struct HandlerContext {
     pub user: Box<CypherUser>,
     pub group: Box<CypherGroup>,
@@ -39,34 +47,41 @@ pub struct CancelOrderInfo {
 #[derive(Debug, PartialEq, Clone)]
 pub struct LimitOrderInfo {
     pub symbol: String,
-    pub price: u64,
-    pub amount: u64,
+    pub price: f64,
+    pub amount: f64,
     pub side: Side,
+    /// Submit the order even if it fails the margin check.
+    pub force: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct MarketOrderInfo {
     pub symbol: String,
-    pub amount: u64,
+    pub amount: f64,
     pub side: Side,
+    /// Submit the order even if it fails the margin check.
+    pub force: bool,
 }
 
 pub struct MarketContext {
     pub name: String,
     pub market_index: usize,
-    pub signer: Arc<Keypair>,
+    pub signer: Arc<dyn Signer + Send + Sync>,
     pub cypher_user_pk: Pubkey,
     pub dex_market_pk: Pubkey,
     pub open_orders_pk: Pubkey,
+    pub units: MarketUnits,
 }
 
 pub struct Handler {
     pub market_context: Box<MarketContext>,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
     shutdown_receiver: Mutex<Receiver<bool>>,
     open_orders_provider: Mutex<Receiver<OpenOrdersContext>>,
     orderbook_provider: Mutex<Receiver<Arc<OrderBook>>>,
     dex_market: Option<MarketStateV2>,
+    margin_check: MarginCheck,
+    risk_engine: Arc<RiskEngine>,
     open_orders: RwLock<Option<OpenOrders>>,
     orderbook: RwLock<Arc<OrderBook>>,
 }
@@ -74,11 +89,13 @@ pub struct Handler {
 impl Handler {
     pub fn new(
         market_context: Box<MarketContext>,
-        rpc_client: Arc<RpcClient>,
+        rpc_client: Arc<dyn RpcBackend>,
         shutdown_receiver: Receiver<bool>,
         open_orders_provider: Receiver<OpenOrdersContext>,
         orderbook_provider: Receiver<Arc<OrderBook>>,
         dex_market: Option<MarketStateV2>,
+        margin_check: MarginCheck,
+        risk_engine: Arc<RiskEngine>,
     ) -> Self {
         Self {
             market_context,
@@ -87,6 +104,8 @@ impl Handler {
             open_orders_provider: Mutex::new(open_orders_provider),
             orderbook_provider: Mutex::new(orderbook_provider),
             dex_market,
+            margin_check,
+            risk_engine,
             open_orders: RwLock::new(None),
             orderbook: RwLock::new(Arc::new(OrderBook::default())),
         }
@@ -146,6 +165,10 @@ impl Handler {
 
     pub async fn get_orderbook(self: &Arc<Self>) -> Result<Arc<OrderBook>, CypherInteractiveError> {
         let ob = self.orderbook.read().await;
+        // the book starts out empty, until the provider sends the first one for our market
+        if ob.market != self.market_context.dex_market_pk {
+            return Err(CypherInteractiveError::OrderBookNotAvailable);
+        }
 
         Ok(Arc::clone(&ob))
     }
@@ -167,8 +190,39 @@ impl Handler {
                 .unwrap(),
         );
 
-        //todo get best price
+        let units = &self.market_context.units;
+        let quantity_lots = units.ui_quantity_to_lots(order_info.amount)?;
+        let quantity = quantity_lots * units.coin_lot_size;
+        let orderbook = self.get_orderbook().await?;
+        // an order the book can not fill would have no price to check the margin and risk limits against
+        let price_lots = match orderbook
+            .get_fill_price_lots(order_info.side, quantity)
+            .await
+        {
+            Some(price_lots) => price_lots,
+            None => {
+                println!(
+                    "[HANDLER-{}] Not enough liquidity on the book to fill {} lots.",
+                    self.market_context.name, quantity_lots
+                );
+                return Err(CypherInteractiveError::InsufficientLiquidity(
+                    order_info.amount,
+                ));
+            }
+        };
+        let price = lots_to_native_price(units, price_lots);
+        self.check_margin(&ctx, order_info.side, quantity, price, order_info.force)?;
+        let order_risk = self
+            .get_order_risk(&ctx, order_info.side, quantity, price)
+            .await;
+        self.risk_engine.check(&order_risk).await?;
 
+        let limit_price = price_lots_with_slippage(order_info.side, price_lots);
+        println!(
+            "[HANDLER-{}] Expecting a fill at {} lots, submitting with a limit price of {} lots.",
+            self.market_context.name, price_lots, limit_price
+        );
+        let max_native_pc_qty = units.max_native_pc_qty(limit_price, quantity_lots)?;
         let order = get_new_order_ix(
             &ctx.group,
             &cypher_market,
@@ -176,24 +230,25 @@ impl Handler {
             &dex_market_state,
             &self.market_context.open_orders_pk,
             &self.market_context.cypher_user_pk,
-            &self.market_context.signer,
+            self.market_context.signer.as_ref(),
             NewOrderInstructionV3 {
                 side: order_info.side,
-                limit_price: todo!(),
-                max_coin_qty: todo!(),
-                max_native_pc_qty_including_fees: todo!(),
-                self_trade_behavior: todo!(),
-                order_type: todo!(),
-                client_order_id: todo!(),
-                limit: todo!(),
-                max_ts: todo!(),
+                limit_price: NonZeroU64::new(limit_price).unwrap(),
+                max_coin_qty: NonZeroU64::new(quantity_lots).unwrap(),
+                max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc_qty).unwrap(),
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                order_type: OrderType::ImmediateOrCancel,
+                client_order_id: MARKET_ORDER_CLIENT_ORDER_ID,
+                limit: u16::MAX,
+                max_ts: i64::MAX,
             },
         );
 
         let res = self
-            .submit_transactions(order, &self.market_context.signer, *ctx.hash)
+            .submit_transactions(order, self.market_context.signer.as_ref(), *ctx.hash)
             .await;
 
+        // the order is booked by the risk engine as it fills, most of it may be cancelled
         match res {
             Ok(s) => Ok(s),
             Err(e) => Err(CypherInteractiveError::TransactionSubmission(e)),
@@ -217,7 +272,17 @@ impl Handler {
                 .unwrap(),
         );
 
-        let max_native_pc_qty = order_info.amount * order_info.price;
+        let units = &self.market_context.units;
+        let limit_price = units.ui_price_to_lots(order_info.price)?;
+        let max_coin_qty = units.ui_quantity_to_lots(order_info.amount)?;
+        let max_native_pc_qty = units.max_native_pc_qty(limit_price, max_coin_qty)?;
+        let quantity = max_coin_qty * units.coin_lot_size;
+        let price = lots_to_native_price(units, limit_price);
+        self.check_margin(&ctx, order_info.side, quantity, price, order_info.force)?;
+        let order_risk = self
+            .get_order_risk(&ctx, order_info.side, quantity, price)
+            .await;
+        self.risk_engine.check(&order_risk).await?;
 
         let order_ix = get_new_order_ix(
             &ctx.group,
@@ -226,11 +291,11 @@ impl Handler {
             &dex_market_state,
             &self.market_context.open_orders_pk,
             &self.market_context.cypher_user_pk,
-            &self.market_context.signer,
+            self.market_context.signer.as_ref(),
             NewOrderInstructionV3 {
                 side: order_info.side,
-                limit_price: NonZeroU64::new(order_info.price).unwrap(),
-                max_coin_qty: NonZeroU64::new(order_info.amount).unwrap(),
+                limit_price: NonZeroU64::new(limit_price).unwrap(),
+                max_coin_qty: NonZeroU64::new(max_coin_qty).unwrap(),
                 max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc_qty).unwrap(),
                 self_trade_behavior: SelfTradeBehavior::DecrementTake,
                 order_type: OrderType::Limit,
@@ -241,11 +306,14 @@ impl Handler {
         );
 
         let res = self
-            .submit_transactions(order_ix, &self.market_context.signer, *ctx.hash)
+            .submit_transactions(order_ix, self.market_context.signer.as_ref(), *ctx.hash)
             .await;
 
         match res {
-            Ok(s) => Ok(s),
+            Ok(s) => {
+                self.risk_engine.record(&order_risk).await;
+                Ok(s)
+            }
             Err(e) => Err(CypherInteractiveError::TransactionSubmission(e)),
         }
     }
@@ -283,7 +351,7 @@ impl Handler {
             &dex_market_state,
             &self.market_context.open_orders_pk,
             &self.market_context.cypher_user_pk,
-            &self.market_context.signer,
+            self.market_context.signer.as_ref(),
             CancelOrderInstructionV2 {
                 order_id,
                 side: order.side,
@@ -291,7 +359,11 @@ impl Handler {
         );
 
         let res = self
-            .submit_transactions(cancel_order_ix, &self.market_context.signer, *ctx.hash)
+            .submit_transactions(
+                cancel_order_ix,
+                self.market_context.signer.as_ref(),
+                *ctx.hash,
+            )
             .await;
 
         match res {
@@ -300,16 +372,166 @@ impl Handler {
         }
     }
 
+    /// Settles the free funds in the market's open orders account back into the cypher account.
+    pub async fn settle_funds(
+        self: &Arc<Self>,
+        ctx: HandlerContext,
+    ) -> Result<Signature, CypherInteractiveError> {
+        let dex_market_state = self.dex_market.unwrap();
+        let cypher_market = Box::new(
+            ctx.group
+                .get_cypher_market(self.market_context.market_index)
+                .unwrap(),
+        );
+        let cypher_token = Box::new(
+            ctx.group
+                .get_cypher_token(self.market_context.market_index)
+                .unwrap(),
+        );
+
+        let settle_funds_ix = get_settle_funds_ix(
+            &ctx.group,
+            &cypher_market,
+            &cypher_token,
+            &dex_market_state,
+            &self.market_context.cypher_user_pk,
+            &self.market_context.open_orders_pk,
+            self.market_context.signer.as_ref(),
+        );
+
+        let res = self
+            .submit_transactions(
+                settle_funds_ix,
+                self.market_context.signer.as_ref(),
+                *ctx.hash,
+            )
+            .await;
+
+        match res {
+            Ok(s) => Ok(s),
+            Err(e) => Err(CypherInteractiveError::TransactionSubmission(e)),
+        }
+    }
+
+    /// Describes an order for `quantity` native base units at a native `price` for the risk engine, in ui units.
+    async fn get_order_risk(
+        &self,
+        ctx: &HandlerContext,
+        side: Side,
+        quantity: u64,
+        price: f64,
+    ) -> OrderRisk {
+        let units = &self.market_context.units;
+        let market_index = self.market_context.market_index;
+        let cypher_market = ctx.group.get_cypher_market(market_index).unwrap();
+        let base = get_token_balance(&ctx.user, market_index);
+        let orderbook = Arc::clone(&*self.orderbook.read().await);
+        let (open_orders, open_quantity) = match &*self.open_orders.read().await {
+            Some(oo) => {
+                let orders = get_open_orders(oo);
+                let resting = match side {
+                    Side::Bid => orderbook.bids.read().await,
+                    Side::Ask => orderbook.asks.read().await,
+                };
+                let open_quantity: u64 = orders
+                    .iter()
+                    .filter(|o| o.side == side)
+                    .filter_map(|o| resting.iter().find(|r| r.order_id == o.order_id))
+                    .map(|r| r.quantity)
+                    .sum();
+                (orders.len(), open_quantity)
+            }
+            None => (0, 0),
+        };
+
+        OrderRisk {
+            market: self.market_context.name.to_string(),
+            side,
+            price: native_price_to_ui(price, units.base_decimals, units.quote_decimals),
+            quantity: units.native_base_to_ui(quantity),
+            oracle_price: native_price_to_ui(
+                cypher_market.oracle_price.price,
+                units.base_decimals,
+                units.quote_decimals,
+            ),
+            position: (base.deposits - base.borrows) / 10_f64.powi(units.base_decimals as i32),
+            open_quantity: units.native_base_to_ui(open_quantity),
+            open_orders,
+        }
+    }
+
+    /// Checks the c-ratio the account would be left with if the order fully filled against the configured minimum.
+    ///
+    /// Orders which leave the account better off than it currently is always pass, so risk can be reduced
+    /// even when the account is already below the minimum.
+    fn check_margin(
+        &self,
+        ctx: &HandlerContext,
+        side: Side,
+        quantity: u64,
+        price: f64,
+        force: bool,
+    ) -> Result<(), CypherInteractiveError> {
+        let market_index = self.market_context.market_index;
+        let cypher_market = ctx.group.get_cypher_market(market_index).unwrap();
+        let margin = MarginSummary::new(&ctx.user, &ctx.group);
+        let projected = margin
+            .with_fill(
+                side,
+                quantity as f64,
+                price,
+                number_to_f64(cypher_market.oracle_price.price),
+                get_token_balance(&ctx.user, market_index),
+                get_token_balance(&ctx.user, QUOTE_TOKEN_IDX),
+            )
+            .c_ratio();
+        let min_c_ratio = self.margin_check.min_c_ratio.unwrap_or(margin.init_ratio);
+
+        if projected >= min_c_ratio || projected >= margin.c_ratio() {
+            return Ok(());
+        }
+
+        if force {
+            println!(
+                "[HANDLER-{}] Projected c-ratio {} is below the minimum of {}, submitting anyway since the order is forced.",
+                self.market_context.name, projected, min_c_ratio
+            );
+            return Ok(());
+        }
+
+        match self.margin_check.mode {
+            MarginCheckMode::Warn => {
+                println!(
+                    "[HANDLER-{}] Warning: projected c-ratio {} is below the minimum of {}.",
+                    self.market_context.name, projected, min_c_ratio
+                );
+                Ok(())
+            }
+            MarginCheckMode::Block => {
+                println!(
+                    "[HANDLER-{}] Projected c-ratio {} is below the minimum of {}, use '--force' to submit anyway.",
+                    self.market_context.name, projected, min_c_ratio
+                );
+                Err(CypherInteractiveError::CRatioBelowMinimum(projected))
+            }
+        }
+    }
+
     async fn submit_transactions(
         self: &Arc<Self>,
         ix: Instruction,
-        signer: &Keypair,
+        signer: &dyn Signer,
         blockhash: Hash,
     ) -> Result<Signature, ClientError> {
         let mut txn_builder: Box<FastTxnBuilder> = Box::new(FastTxnBuilder::new());
         txn_builder.add(ix);
 
-        let tx = txn_builder.build(blockhash, signer, None);
+        let tx = match txn_builder.build(blockhash, signer, None) {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
         let res = self.send_and_confirm_transaction(&tx).await;
         match res {
             Ok(s) => Ok(s),
@@ -329,3 +551,34 @@ impl Handler {
         }
     }
 }
+
+/// Converts a price in lots into native quote units per native base unit.
+fn lots_to_native_price(units: &MarketUnits, price_lots: u64) -> f64 {
+    if units.coin_lot_size == 0 {
+        return 0.0;
+    }
+    price_lots as f64 * units.pc_lot_size as f64 / units.coin_lot_size as f64
+}
+
+/// The limit price of a market order expected to fill at the given price, leaving room for the book to move
+/// by `MARKET_ORDER_SLIPPAGE_BPS` before the order lands. Never below a single tick.
+fn price_lots_with_slippage(side: Side, price_lots: u64) -> u64 {
+    let price_lots = u128::from(price_lots);
+    let slippage = u128::from(MARKET_ORDER_SLIPPAGE_BPS);
+    let limit_price = match side {
+        Side::Bid => (price_lots * (10_000 + slippage) + 9_999) / 10_000,
+        Side::Ask => price_lots * (10_000 - slippage) / 10_000,
+    };
+
+    u64::try_from(limit_price).unwrap_or(u64::MAX).max(1)
+}
+
+fn get_token_balance(user: &CypherUser, token_index: usize) -> TokenBalance {
+    match user.get_position(token_index) {
+        Some(p) => TokenBalance {
+            deposits: number_to_f64(p.base_deposits()),
+            borrows: number_to_f64(p.base_borrows()),
+        },
+        None => TokenBalance::default(),
+    }
+}
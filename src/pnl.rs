@@ -0,0 +1,236 @@
+use {
+    crate::{providers::Fill, CypherInteractiveError},
+    serde::{Deserialize, Serialize},
+    serum_dex::matching::Side,
+    std::{
+        collections::BTreeMap,
+        fs::{create_dir_all, File},
+        io::{BufReader, BufWriter},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::sync::{
+        broadcast::{channel, Receiver},
+        Mutex, RwLock,
+    },
+};
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketPnl {
+    /// The signed position in base token ui units, negative when short.
+    pub position: f64,
+    pub average_entry: f64,
+    pub realized_pnl: f64,
+    pub fees_paid: f64,
+}
+
+impl MarketPnl {
+    pub fn apply_fill(&mut self, side: Side, price: f64, quantity: f64, fee: f64) {
+        let signed_quantity = match side {
+            Side::Bid => quantity,
+            Side::Ask => -quantity,
+        };
+        self.fees_paid += fee;
+
+        if self.position == 0.0 || self.position.signum() == signed_quantity.signum() {
+            let cost = self.average_entry * self.position.abs() + price * quantity;
+            self.average_entry = cost / (self.position.abs() + quantity);
+            self.position += signed_quantity;
+            return;
+        }
+
+        let closed = quantity.min(self.position.abs());
+        self.realized_pnl += closed * (price - self.average_entry) * self.position.signum();
+        self.position += signed_quantity;
+
+        if self.position.abs() < f64::EPSILON {
+            self.position = 0.0;
+            self.average_entry = 0.0;
+        } else if quantity > closed {
+            // the fill flipped the position, the remainder was opened at the fill price
+            self.average_entry = price;
+        }
+    }
+
+    pub fn unrealized_pnl(&self, price: f64) -> f64 {
+        self.position * (price - self.average_entry)
+    }
+}
+
+pub struct PnlTracker {
+    receiver: Mutex<Receiver<Fill>>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+    path: PathBuf,
+    markets: RwLock<BTreeMap<String, MarketPnl>>,
+}
+
+impl PnlTracker {
+    pub fn default() -> Self {
+        Self {
+            receiver: Mutex::new(channel::<Fill>(u16::MAX as usize).1),
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+            path: PathBuf::new(),
+            markets: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Creates a tracker backed by the given file, restoring the state from previous sessions if it exists.
+    pub fn new(
+        receiver: Receiver<Fill>,
+        shutdown_receiver: Receiver<bool>,
+        path: &Path,
+    ) -> Result<Self, CypherInteractiveError> {
+        let markets = if path.exists() {
+            let file = match File::open(path) {
+                Ok(f) => f,
+                Err(_) => {
+                    return Err(CypherInteractiveError::PnlStateRead);
+                }
+            };
+            match serde_json::from_reader(BufReader::new(file)) {
+                Ok(m) => m,
+                Err(_) => {
+                    return Err(CypherInteractiveError::PnlStateRead);
+                }
+            }
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            receiver: Mutex::new(receiver),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+            path: path.to_path_buf(),
+            markets: RwLock::new(markets),
+        })
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let mut receiver = self.receiver.lock().await;
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let mut shutdown_signal: bool = false;
+
+        loop {
+            tokio::select! {
+                fill = receiver.recv() => {
+                    if fill.is_ok() {
+                        let fill = fill.unwrap();
+                        let mut markets = self.markets.write().await;
+                        markets
+                            .entry(fill.market.to_string())
+                            .or_default()
+                            .apply_fill(fill.side, fill.price, fill.quantity, fill.fee);
+                        match self.save(&markets) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("[PNL] Failed to persist pnl state: {:?}", e);
+                            }
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    shutdown_signal = true;
+                }
+            }
+
+            if shutdown_signal {
+                println!("[PNL] Received shutdown signal, stopping.");
+                break;
+            }
+        }
+    }
+
+    fn save(&self, markets: &BTreeMap<String, MarketPnl>) -> Result<(), CypherInteractiveError> {
+        if let Some(dir) = self.path.parent() {
+            if create_dir_all(dir).is_err() {
+                return Err(CypherInteractiveError::PnlStateWrite);
+            }
+        }
+        let file = match File::create(&self.path) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(CypherInteractiveError::PnlStateWrite);
+            }
+        };
+
+        match serde_json::to_writer_pretty(BufWriter::new(file), markets) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(CypherInteractiveError::PnlStateWrite),
+        }
+    }
+
+    pub async fn get_markets(self: &Arc<Self>) -> BTreeMap<String, MarketPnl> {
+        self.markets.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn opening_and_adding_average_the_entry() {
+        let mut pnl = MarketPnl::default();
+        pnl.apply_fill(Side::Bid, 10.0, 2.0, 0.0);
+        assert_close(pnl.position, 2.0);
+        assert_close(pnl.average_entry, 10.0);
+
+        pnl.apply_fill(Side::Bid, 20.0, 2.0, 0.0);
+        assert_close(pnl.position, 4.0);
+        assert_close(pnl.average_entry, 15.0);
+        assert_close(pnl.realized_pnl, 0.0);
+        assert_close(pnl.unrealized_pnl(17.0), 8.0);
+    }
+
+    #[test]
+    fn partial_close_realizes_against_the_average_entry() {
+        let mut pnl = MarketPnl::default();
+        pnl.apply_fill(Side::Bid, 10.0, 2.0, 0.0);
+        pnl.apply_fill(Side::Bid, 20.0, 2.0, 0.0);
+        pnl.apply_fill(Side::Ask, 25.0, 1.0, 0.0);
+
+        assert_close(pnl.position, 3.0);
+        assert_close(pnl.average_entry, 15.0);
+        assert_close(pnl.realized_pnl, 10.0);
+    }
+
+    #[test]
+    fn flipping_the_position_opens_the_remainder_at_the_fill_price() {
+        let mut pnl = MarketPnl::default();
+        pnl.apply_fill(Side::Bid, 15.0, 3.0, 0.0);
+        pnl.apply_fill(Side::Ask, 5.0, 5.0, 0.0);
+
+        assert_close(pnl.realized_pnl, -30.0);
+        assert_close(pnl.position, -2.0);
+        assert_close(pnl.average_entry, 5.0);
+        // short, so a lower price is a gain
+        assert_close(pnl.unrealized_pnl(4.0), 2.0);
+
+        pnl.apply_fill(Side::Bid, 4.0, 2.0, 0.0);
+        assert_close(pnl.realized_pnl, -28.0);
+        assert_close(pnl.position, 0.0);
+        assert_close(pnl.average_entry, 0.0);
+        assert_close(pnl.unrealized_pnl(100.0), 0.0);
+    }
+
+    #[test]
+    fn fees_are_tracked_apart_from_realized_pnl() {
+        let mut pnl = MarketPnl::default();
+        pnl.apply_fill(Side::Bid, 10.0, 1.0, 0.25);
+        pnl.apply_fill(Side::Ask, 12.0, 1.0, 0.5);
+
+        assert_close(pnl.fees_paid, 0.75);
+        assert_close(pnl.realized_pnl, 2.0);
+        assert_close(pnl.position, 0.0);
+    }
+}
@@ -0,0 +1,188 @@
+use {
+    crate::{accounts_cache::AccountsCache, serum_event_queue::EventQueue, CypherInteractiveError},
+    chrono::Utc,
+    serum_dex::matching::Side,
+    solana_sdk::pubkey::Pubkey,
+    std::{collections::HashMap, sync::Arc},
+    tokio::sync::{
+        broadcast::{channel, Receiver, Sender},
+        Mutex, RwLock,
+    },
+};
+
+#[derive(Default, Clone)]
+pub struct FillsContext {
+    pub market_name: String,
+    pub event_queue: Pubkey,
+    pub open_orders: Pubkey,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub market: String,
+    pub side: Side,
+    pub maker: bool,
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64,
+    pub native_base_quantity: u64,
+    pub native_quote_quantity: u64,
+    pub native_fee: i64,
+    pub order_id: u128,
+    pub client_order_id: u64,
+    pub slot: u64,
+    pub timestamp: String,
+}
+
+pub struct FillsProvider {
+    cache: Arc<AccountsCache>,
+    sender: Arc<Sender<Fill>>,
+    receiver: Mutex<Receiver<Pubkey>>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+    fills_ctxs: RwLock<Vec<FillsContext>>,
+    seq_nums: RwLock<HashMap<Pubkey, u64>>,
+}
+
+impl FillsProvider {
+    pub fn default() -> Self {
+        Self {
+            cache: Arc::new(AccountsCache::default()),
+            sender: Arc::new(channel::<Fill>(u16::MAX as usize).0),
+            receiver: Mutex::new(channel::<Pubkey>(u16::MAX as usize).1),
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+            fills_ctxs: RwLock::new(Vec::new()),
+            seq_nums: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn new(
+        cache: Arc<AccountsCache>,
+        sender: Arc<Sender<Fill>>,
+        receiver: Receiver<Pubkey>,
+        shutdown_receiver: Receiver<bool>,
+        fills_ctxs: Vec<FillsContext>,
+    ) -> Self {
+        Self {
+            cache,
+            sender,
+            receiver: Mutex::new(receiver),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+            fills_ctxs: RwLock::new(fills_ctxs),
+            seq_nums: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let mut receiver = self.receiver.lock().await;
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let mut shutdown_signal: bool = false;
+
+        loop {
+            tokio::select! {
+                key = receiver.recv() => {
+                    if key.is_err() {
+                        continue;
+                    } else {
+                        _ = self.process_updates(key.unwrap()).await;
+                    }
+                },
+                _ = shutdown.recv() => {
+                    shutdown_signal = true;
+                }
+            }
+
+            if shutdown_signal {
+                break;
+            }
+        }
+    }
+
+    /// Starts publishing the fills of another market.
+    pub async fn add_fills_context(&self, fills_ctx: FillsContext) {
+        self.fills_ctxs.write().await.push(fills_ctx);
+    }
+
+    async fn process_updates(self: &Arc<Self>, key: Pubkey) -> Result<(), CypherInteractiveError> {
+        let maybe_fills_ctx = self
+            .fills_ctxs
+            .read()
+            .await
+            .iter()
+            .find(|ctx| ctx.event_queue == key)
+            .cloned();
+        let fills_ctx = match maybe_fills_ctx {
+            Some(ctx) => ctx,
+            None => {
+                return Ok(());
+            }
+        };
+
+        let ai = self.cache.get(&key).unwrap();
+        let data = ai.account.data.to_vec();
+        let slot = ai.slot;
+        drop(ai);
+
+        let event_queue = match EventQueue::new(&data) {
+            Some(eq) => eq,
+            None => {
+                return Ok(());
+            }
+        };
+
+        let seq_num = event_queue.seq_num();
+        let maybe_last_seq_num = self.seq_nums.write().await.insert(key, seq_num);
+
+        // the first time we see the queue we only take note of the sequence number,
+        // otherwise every fill still sitting in the queue would be reported again
+        let last_seq_num = match maybe_last_seq_num {
+            Some(s) => s,
+            None => {
+                return Ok(());
+            }
+        };
+
+        let base_divisor = 10_f64.powi(fills_ctx.base_decimals as i32);
+        let quote_divisor = 10_f64.powi(fills_ctx.quote_decimals as i32);
+        let timestamp = Utc::now().to_rfc3339();
+
+        for fill in event_queue.fills_since(last_seq_num) {
+            if fill.owner != fills_ctx.open_orders {
+                continue;
+            }
+
+            let base_quantity = fill.base_quantity();
+            let quote_quantity = fill.quote_quantity();
+            let quantity = base_quantity as f64 / base_divisor;
+            let price = if base_quantity == 0 {
+                0.0
+            } else {
+                (quote_quantity as f64 / quote_divisor) / quantity
+            };
+
+            let native_fee = fill.fee();
+            let res = self.sender.send(Fill {
+                market: fills_ctx.market_name.to_string(),
+                side: fill.side,
+                maker: fill.maker,
+                price,
+                quantity,
+                fee: native_fee as f64 / quote_divisor,
+                native_base_quantity: base_quantity,
+                native_quote_quantity: quote_quantity,
+                native_fee,
+                order_id: fill.order_id,
+                client_order_id: fill.client_order_id,
+                slot,
+                timestamp: timestamp.to_string(),
+            });
+
+            if res.is_err() {
+                return Err(CypherInteractiveError::ChannelSend);
+            }
+        }
+
+        Ok(())
+    }
+}
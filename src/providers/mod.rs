@@ -1,9 +1,11 @@
 pub mod cypher_account_provider;
 pub mod cypher_group_provider;
+pub mod fills_provider;
 pub mod open_orders_provider;
 pub mod orderbook_provider;
 
 pub use cypher_account_provider::*;
 pub use cypher_group_provider::*;
+pub use fills_provider::*;
 pub use open_orders_provider::*;
 pub use orderbook_provider::*;
@@ -6,7 +6,7 @@ use {
     std::sync::Arc,
     tokio::sync::{
         broadcast::{channel, Receiver, Sender},
-        Mutex,
+        Mutex, RwLock,
     },
 };
 
@@ -21,7 +21,7 @@ pub struct OpenOrdersProvider {
     sender: Arc<Sender<OpenOrdersContext>>,
     receiver: Mutex<Receiver<Pubkey>>,
     shutdown_receiver: Mutex<Receiver<bool>>,
-    open_orders_pks: Vec<Pubkey>,
+    open_orders_pks: RwLock<Vec<Pubkey>>,
 }
 
 impl OpenOrdersProvider {
@@ -31,7 +31,7 @@ impl OpenOrdersProvider {
             sender: Arc::new(channel::<OpenOrdersContext>(u16::MAX as usize).0),
             receiver: Mutex::new(channel::<Pubkey>(u16::MAX as usize).1),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
-            open_orders_pks: Vec::new(),
+            open_orders_pks: RwLock::new(Vec::new()),
         }
     }
 
@@ -47,7 +47,7 @@ impl OpenOrdersProvider {
             sender,
             receiver: Mutex::new(receiver),
             shutdown_receiver: Mutex::new(shutdown_receiver),
-            open_orders_pks,
+            open_orders_pks: RwLock::new(open_orders_pks),
         }
     }
 
@@ -76,8 +76,13 @@ impl OpenOrdersProvider {
         }
     }
 
+    /// Starts publishing the updates of another open orders account.
+    pub async fn add_open_orders(&self, open_orders_pk: Pubkey) {
+        self.open_orders_pks.write().await.push(open_orders_pk);
+    }
+
     async fn process_updates(&self, key: Pubkey) -> Result<(), CypherInteractiveError> {
-        for oo_pk in &self.open_orders_pks {
+        for oo_pk in self.open_orders_pks.read().await.iter() {
             if key == *oo_pk {
                 let ai = self.cache.get(&key).unwrap();
 
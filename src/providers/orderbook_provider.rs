@@ -4,16 +4,16 @@ use {
         serum_slab::{OrderBookOrder, Slab},
         CypherInteractiveError,
     },
-    arrayref::array_refs,
+    serum_dex::matching::Side,
     solana_sdk::pubkey::Pubkey,
-    std::sync::Arc,
+    std::{collections::HashSet, sync::Arc},
     tokio::sync::{
         broadcast::{channel, Receiver, Sender},
         Mutex, RwLock,
     },
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct OrderBookContext {
     pub market: Pubkey,
     pub bids: Pubkey,
@@ -22,21 +22,221 @@ pub struct OrderBookContext {
     pub pc_lot_size: u64,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OrderBookLevel {
+    pub price: u64,
+    pub price_lots: u64,
+    pub quantity: u64,
+    pub orders: usize,
+}
+
+/// The position of one of our orders in the queue of its price level.
+#[derive(Debug, Clone, Copy)]
+pub struct QueuePosition {
+    pub side: Side,
+    pub order: OrderBookOrder,
+    /// The one-based position of the order among the orders resting at the same price.
+    pub position: usize,
+    pub orders_at_level: usize,
+    /// The quantity, in native base units, resting at the same price ahead of the order.
+    pub size_ahead: u64,
+}
+
+/// Holds both the L3 view of the book, one entry per resting order in price-time priority,
+/// and the L2 view, where orders resting at the same price are aggregated into a single level.
 #[derive(Default)]
 pub struct OrderBook {
     pub market: Pubkey,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
     pub bids: RwLock<Vec<OrderBookOrder>>,
     pub asks: RwLock<Vec<OrderBookOrder>>,
+    pub bids_l2: RwLock<Vec<OrderBookLevel>>,
+    pub asks_l2: RwLock<Vec<OrderBookLevel>>,
 }
 
 impl OrderBook {
-    pub fn new(market: Pubkey) -> Self {
+    pub fn new(market: Pubkey, coin_lot_size: u64, pc_lot_size: u64) -> Self {
         Self {
             market,
+            coin_lot_size,
+            pc_lot_size,
             bids: RwLock::new(Vec::new()),
             asks: RwLock::new(Vec::new()),
+            bids_l2: RwLock::new(Vec::new()),
+            asks_l2: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Returns the price levels for the given side with prices grouped into buckets of `tick_lots`.
+    ///
+    /// Bid prices are rounded down and ask prices are rounded up, so a grouped level
+    /// is never shown at a better price than the orders it contains.
+    pub async fn get_levels(&self, side: Side, tick_lots: u64) -> Vec<OrderBookLevel> {
+        let levels = match side {
+            Side::Bid => self.bids_l2.read().await.clone(),
+            Side::Ask => self.asks_l2.read().await.clone(),
+        };
+
+        if tick_lots <= 1 {
+            return levels;
+        }
+
+        let mut grouped: Vec<OrderBookLevel> = Vec::new();
+        for level in levels {
+            let price_lots = group_price_lots(side, level.price_lots, tick_lots);
+
+            match grouped.last_mut() {
+                Some(g) if g.price_lots == price_lots => {
+                    g.quantity += level.quantity;
+                    g.orders += level.orders;
+                }
+                _ => {
+                    grouped.push(OrderBookLevel {
+                        price: self.lots_to_native_price(price_lots),
+                        price_lots,
+                        quantity: level.quantity,
+                        orders: level.orders,
+                    });
+                }
+            }
+        }
+
+        grouped
+    }
+
+    /// Returns the prices, grouped into buckets of `tick_lots`, at which any of the given owners has orders resting.
+    pub async fn get_owned_levels(
+        &self,
+        side: Side,
+        tick_lots: u64,
+        owners: &HashSet<Pubkey>,
+    ) -> HashSet<u64> {
+        let orders = match side {
+            Side::Bid => self.bids.read().await,
+            Side::Ask => self.asks.read().await,
+        };
+
+        orders
+            .iter()
+            .filter(|o| owners.contains(&o.owner))
+            .map(|o| group_price_lots(side, o.price_lots, tick_lots))
+            .collect()
+    }
+
+    /// Returns the queue position of every order in the book which belongs to any of the given owners.
+    pub async fn get_queue_positions(&self, owners: &HashSet<Pubkey>) -> Vec<QueuePosition> {
+        let mut positions: Vec<QueuePosition> = Vec::new();
+
+        for side in [Side::Bid, Side::Ask] {
+            let orders = match side {
+                Side::Bid => self.bids.read().await,
+                Side::Ask => self.asks.read().await,
+            };
+
+            // orders are in price-time priority, so everything before ours at the same price is ahead of us
+            for (idx, order) in orders.iter().enumerate() {
+                if !owners.contains(&order.owner) {
+                    continue;
+                }
+                let level: Vec<&OrderBookOrder> = orders
+                    .iter()
+                    .filter(|o| o.price_lots == order.price_lots)
+                    .collect();
+                let ahead: Vec<&OrderBookOrder> = orders[..idx]
+                    .iter()
+                    .filter(|o| o.price_lots == order.price_lots)
+                    .collect();
+
+                positions.push(QueuePosition {
+                    side,
+                    order: *order,
+                    position: ahead.len() + 1,
+                    orders_at_level: level.len(),
+                    size_ahead: ahead.iter().map(|o| o.quantity).sum(),
+                });
+            }
         }
+
+        positions
     }
+
+    /// The worst price, in lots, at which a taker order for the given native base quantity would fill,
+    /// or `None` if the book is not deep enough to fill it.
+    pub async fn get_fill_price_lots(&self, side: Side, quantity: u64) -> Option<u64> {
+        // a taker order fills against the opposite side of the book
+        let levels = match side {
+            Side::Bid => self.asks_l2.read().await,
+            Side::Ask => self.bids_l2.read().await,
+        };
+
+        let mut remaining = quantity;
+        for level in levels.iter() {
+            if level.quantity >= remaining {
+                return Some(level.price_lots);
+            }
+            remaining -= level.quantity;
+        }
+
+        None
+    }
+
+    /// The value of the given level in native quote units.
+    pub fn notional(&self, level: &OrderBookLevel) -> u64 {
+        if self.coin_lot_size == 0 {
+            return 0;
+        }
+        let quantity_lots = u128::from(level.quantity) / u128::from(self.coin_lot_size);
+        let notional = u128::from(level.price_lots) * quantity_lots * u128::from(self.pc_lot_size);
+
+        u64::try_from(notional).unwrap_or(u64::MAX)
+    }
+
+    fn lots_to_native_price(&self, price_lots: u64) -> u64 {
+        if self.coin_lot_size == 0 {
+            return 0;
+        }
+        let price =
+            u128::from(price_lots) * u128::from(self.pc_lot_size) / u128::from(self.coin_lot_size);
+
+        u64::try_from(price).unwrap_or(u64::MAX)
+    }
+}
+
+/// Groups a price into a bucket of `tick_lots`, rounding bids down and asks up.
+fn group_price_lots(side: Side, price_lots: u64, tick_lots: u64) -> u64 {
+    if tick_lots <= 1 {
+        return price_lots;
+    }
+
+    match side {
+        Side::Bid => price_lots / tick_lots * tick_lots,
+        Side::Ask => (price_lots + tick_lots - 1) / tick_lots * tick_lots,
+    }
+}
+
+/// Aggregates orders, which must already be sorted by price, into price levels.
+pub fn aggregate_levels(orders: &[OrderBookOrder]) -> Vec<OrderBookLevel> {
+    let mut levels: Vec<OrderBookLevel> = Vec::new();
+
+    for order in orders {
+        match levels.last_mut() {
+            Some(level) if level.price_lots == order.price_lots => {
+                level.quantity += order.quantity;
+                level.orders += 1;
+            }
+            _ => {
+                levels.push(OrderBookLevel {
+                    price: order.price,
+                    price_lots: order.price_lots,
+                    quantity: order.quantity,
+                    orders: 1,
+                });
+            }
+        }
+    }
+
+    levels
 }
 
 pub struct OrderBookProvider {
@@ -44,7 +244,7 @@ pub struct OrderBookProvider {
     sender: Arc<Sender<Arc<OrderBook>>>,
     receiver: Mutex<Receiver<Pubkey>>,
     shutdown_receiver: Mutex<Receiver<bool>>,
-    books_keys: Vec<OrderBookContext>,
+    books_keys: RwLock<Vec<OrderBookContext>>,
     books: RwLock<Vec<Arc<OrderBook>>>,
 }
 
@@ -55,12 +255,11 @@ impl OrderBookProvider {
             sender: Arc::new(channel::<Arc<OrderBook>>(u16::MAX as usize).0),
             receiver: Mutex::new(channel::<Pubkey>(u16::MAX as usize).1),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
-            books_keys: Vec::new(),
+            books_keys: RwLock::new(Vec::new()),
             books: RwLock::new(Vec::new()),
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
     pub fn new(
         cache: Arc<AccountsCache>,
         sender: Arc<Sender<Arc<OrderBook>>>,
@@ -74,7 +273,7 @@ impl OrderBookProvider {
             receiver: Mutex::new(receiver),
             shutdown_receiver: Mutex::new(shutdown_receiver),
             books: RwLock::new(Vec::new()),
-            books_keys: books,
+            books_keys: RwLock::new(books),
         }
     }
 
@@ -103,25 +302,36 @@ impl OrderBookProvider {
         }
     }
 
-    #[allow(clippy::ptr_offset_with_cast)]
-    async fn process_updates(self: &Arc<Self>, key: Pubkey) -> Result<(), CypherInteractiveError> {
-        let mut updated: bool = false;
+    /// Starts building the book of another market.
+    pub async fn add_book(&self, book: OrderBookContext) {
+        self.books_keys.write().await.push(book);
+    }
 
+    async fn process_updates(self: &Arc<Self>, key: Pubkey) -> Result<(), CypherInteractiveError> {
         let maybe_ob_ctx = self
             .books_keys
+            .read()
+            .await
             .iter()
-            .find(|ctx| ctx.bids == key || ctx.asks == key);
-        if maybe_ob_ctx.is_none() {
-            return Ok(());
-        }
-        let ob_ctx = maybe_ob_ctx.unwrap();
+            .find(|ctx| ctx.bids == key || ctx.asks == key)
+            .cloned();
+        let ob_ctx = match maybe_ob_ctx {
+            Some(ctx) => ctx,
+            None => {
+                return Ok(());
+            }
+        };
 
         let rb = self.books.read().await;
         let maybe_ob = rb.iter().find(|ob| ob.market == ob_ctx.market);
 
         if maybe_ob.is_none() {
             drop(rb);
-            let ob = Arc::new(OrderBook::new(ob_ctx.market));
+            let ob = Arc::new(OrderBook::new(
+                ob_ctx.market,
+                ob_ctx.coin_lot_size,
+                ob_ctx.pc_lot_size,
+            ));
             let mut wb = self.books.write().await;
             wb.push(ob);
             drop(wb);
@@ -135,40 +345,42 @@ impl OrderBookProvider {
             }
         };
 
-        if key == ob_ctx.bids {
-            let bid_ai = self.cache.get(&key).unwrap();
-
-            let (_bid_head, bid_data, _bid_tail) = array_refs![&bid_ai.account.data, 5; ..; 7];
-            let bid_data = &mut bid_data[8..].to_vec().clone();
-            let bids = Slab::new(bid_data);
-
-            let obl = bids.get_depth(25, ob_ctx.pc_lot_size, ob_ctx.coin_lot_size, false);
-
-            *ob.bids.write().await = obl;
-            updated = true;
-        } else if key == ob_ctx.asks {
-            let ask_ai = self.cache.get(&key).unwrap();
-
-            let (_ask_head, ask_data, _ask_tail) = array_refs![&ask_ai.account.data, 5; ..; 7];
-            let ask_data = &mut ask_data[8..].to_vec().clone();
-            let asks = Slab::new(ask_data);
+        let ai = match self.cache.get(&key) {
+            Some(ai) => ai,
+            None => {
+                return Ok(());
+            }
+        };
+        let data = ai.account.data.to_vec();
+        drop(ai);
 
-            let obl = asks.get_depth(25, ob_ctx.pc_lot_size, ob_ctx.coin_lot_size, true);
+        let is_asks = key == ob_ctx.asks;
+        let maybe_orders = Slab::from_account_data(&data)
+            .and_then(|slab| slab.get_orders(ob_ctx.pc_lot_size, ob_ctx.coin_lot_size, is_asks));
+        // the account may have been caught mid-update, keep the last good book until the next one
+        let obl = match maybe_orders {
+            Ok(o) => o,
+            Err(e) => {
+                return Err(CypherInteractiveError::InvalidSlab(e));
+            }
+        };
 
+        if is_asks {
+            *ob.asks_l2.write().await = aggregate_levels(&obl);
             *ob.asks.write().await = obl;
-            updated = true;
+        } else {
+            *ob.bids_l2.write().await = aggregate_levels(&obl);
+            *ob.bids.write().await = obl;
         }
 
-        if updated {
-            let res = self.sender.send(Arc::clone(ob));
+        let res = self.sender.send(Arc::clone(ob));
 
-            match res {
-                Ok(_) => {}
-                Err(_) => {
-                    return Err(CypherInteractiveError::ChannelSend);
-                }
-            };
-        }
+        match res {
+            Ok(_) => {}
+            Err(_) => {
+                return Err(CypherInteractiveError::ChannelSend);
+            }
+        };
         drop(rb);
         Ok(())
     }
@@ -0,0 +1,157 @@
+use {
+    crate::CypherInteractiveError,
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::Signature,
+        signer::{Signer, SignerError},
+    },
+    std::{
+        convert::TryFrom,
+        io::{self, Read, Write},
+        os::unix::net::UnixStream,
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+};
+
+/// Asks the daemon for the public key of the key it signs with.
+pub const REQUEST_PUBKEY: u8 = 0;
+/// Asks the daemon to sign the payload, which is a serialized transaction message.
+pub const REQUEST_SIGN: u8 = 1;
+pub const RESPONSE_OK: u8 = 0;
+/// The request was refused, the payload is a utf-8 reason.
+pub const RESPONSE_ERR: u8 = 1;
+
+/// Transaction messages are at most a packet in size, anything much larger is not a valid frame.
+const MAX_FRAME_LEN: usize = 64 * 1024;
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Writes a frame, which is a tag byte followed by the payload length as a little endian `u32` and the payload.
+pub fn write_frame(stream: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    let len = match u32::try_from(payload.len()) {
+        Ok(l) if payload.len() <= MAX_FRAME_LEN => l,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame payload is too large",
+            ));
+        }
+    };
+    stream.write_all(&[tag])?;
+    stream.write_all(&len.to_le_bytes())?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+/// Reads a frame written by [`write_frame`], returning its tag and payload.
+pub fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0_u8; 5];
+    stream.read_exact(&mut header)?;
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame payload is too large",
+        ));
+    }
+    let mut payload = vec![0_u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok((header[0], payload))
+}
+
+/// Signs with a key held by a signing daemon listening on a Unix socket, so the key never enters this process.
+///
+/// Every request opens its own connection, the daemon is free to prompt or apply its own policy before answering.
+pub struct RemoteSigner {
+    socket_path: PathBuf,
+    pubkey: Pubkey,
+}
+
+impl RemoteSigner {
+    /// Connects to the daemon and fetches the public key it signs for.
+    pub fn connect(socket_path: &Path) -> Result<Self, CypherInteractiveError> {
+        let mut signer = Self {
+            socket_path: socket_path.to_path_buf(),
+            pubkey: Pubkey::default(),
+        };
+        let payload = match signer.request(REQUEST_PUBKEY, &[]) {
+            Ok(p) => p,
+            Err(e) => {
+                println!(
+                    "Failed to reach signing daemon at {}: {}",
+                    socket_path.display(),
+                    e
+                );
+                return Err(CypherInteractiveError::RemoteSigner);
+            }
+        };
+        signer.pubkey = match <[u8; 32]>::try_from(payload.as_slice()) {
+            Ok(bytes) => Pubkey::new_from_array(bytes),
+            Err(_) => {
+                println!("Signing daemon returned an invalid public key.");
+                return Err(CypherInteractiveError::RemoteSigner);
+            }
+        };
+
+        Ok(signer)
+    }
+
+    fn request(&self, tag: u8, payload: &[u8]) -> Result<Vec<u8>, SignerError> {
+        let mut stream = match UnixStream::connect(&self.socket_path) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(SignerError::Connection(e.to_string()));
+            }
+        };
+        let timeouts = stream
+            .set_read_timeout(Some(SOCKET_TIMEOUT))
+            .and(stream.set_write_timeout(Some(SOCKET_TIMEOUT)));
+        if let Err(e) = timeouts {
+            return Err(SignerError::Connection(e.to_string()));
+        }
+
+        if let Err(e) = write_frame(&mut stream, tag, payload) {
+            return Err(SignerError::Connection(e.to_string()));
+        }
+        match read_frame(&mut stream) {
+            Ok((RESPONSE_OK, p)) => Ok(p),
+            Ok((RESPONSE_ERR, p)) => Err(SignerError::Custom(format!(
+                "signing daemon refused the request: {}",
+                String::from_utf8_lossy(&p)
+            ))),
+            Ok((t, _)) => Err(SignerError::Protocol(format!(
+                "unexpected response tag {}",
+                t
+            ))),
+            Err(e) => Err(SignerError::Connection(e.to_string())),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let payload = self.request(REQUEST_SIGN, message)?;
+        if payload.len() != 64 {
+            return Err(SignerError::Protocol(
+                "signing daemon returned an invalid signature".to_string(),
+            ));
+        }
+        let signature = Signature::new(&payload);
+        // a daemon signing with another key would only get the transaction rejected later on
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Protocol(
+                "signing daemon returned a signature which does not verify".to_string(),
+            ));
+        }
+
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,604 @@
+use {
+    crate::{
+        market_handler::MARKET_ORDER_CLIENT_ORDER_ID, providers::Fill, CypherInteractiveError,
+    },
+    chrono::{DateTime, NaiveDate, Utc},
+    serde::{Deserialize, Serialize},
+    serum_dex::matching::Side,
+    std::{
+        collections::HashMap,
+        fs::{create_dir_all, File, OpenOptions},
+        io::{BufRead, BufReader, Write},
+        path::{Path, PathBuf},
+    },
+    tokio::sync::{broadcast::Receiver, Mutex},
+};
+
+/// The risk config is read from this file next to the group config.
+pub const RISK_CONFIG_FILE_NAME: &str = "risk.json";
+const RISK_LOG_JSONL: &str = "risk.jsonl";
+const SUBMITTED_NOTIONAL_JSONL: &str = "submitted_notional.jsonl";
+
+/// Limits applied to every order before it is signed, a limit which is not set is not enforced.
+///
+/// Quantities are in base token ui units and notionals in quote token ui units.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskLimits {
+    /// The largest absolute position the account may hold in the market once the order fills.
+    pub max_position: Option<f64>,
+    pub max_order_notional: Option<f64>,
+    pub max_open_orders: Option<usize>,
+    /// The total notional of orders which may be submitted on the market per UTC day.
+    pub max_daily_notional: Option<f64>,
+    /// How far the order's price may be from the oracle price, as a fraction of the oracle price.
+    pub price_band: Option<f64>,
+}
+
+impl RiskLimits {
+    /// Returns these limits with any limit which is not set taken from `defaults`.
+    fn or(&self, defaults: &RiskLimits) -> Self {
+        Self {
+            max_position: self.max_position.or(defaults.max_position),
+            max_order_notional: self.max_order_notional.or(defaults.max_order_notional),
+            max_open_orders: self.max_open_orders.or(defaults.max_open_orders),
+            max_daily_notional: self.max_daily_notional.or(defaults.max_daily_notional),
+            price_band: self.price_band.or(defaults.price_band),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskConfig {
+    /// The limits for every market without its own entry in `markets`.
+    #[serde(default)]
+    pub defaults: RiskLimits,
+    /// Per market overrides of the default limits, keyed by market name.
+    #[serde(default)]
+    pub markets: HashMap<String, RiskLimits>,
+}
+
+impl RiskConfig {
+    pub fn get_limits(&self, market: &str) -> RiskLimits {
+        match self.markets.get(market) {
+            Some(l) => l.or(&self.defaults),
+            None => self.defaults,
+        }
+    }
+}
+
+/// The path of the risk config which goes with the group config at the given path.
+pub fn get_risk_config_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(RISK_CONFIG_FILE_NAME)
+}
+
+/// Loads the risk config at the given path, no limits are enforced if the file does not exist.
+pub fn load_risk_config(path: &Path) -> Result<RiskConfig, CypherInteractiveError> {
+    if !path.exists() {
+        return Ok(RiskConfig::default());
+    }
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => {
+            return Err(CypherInteractiveError::RiskConfigRead);
+        }
+    };
+
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(c) => Ok(c),
+        Err(e) => {
+            println!("Failed to parse risk config {}: {}", path.display(), e);
+            Err(CypherInteractiveError::RiskConfigRead)
+        }
+    }
+}
+
+/// An order about to be signed, with the state of the account it is checked against.
+#[derive(Debug, Clone)]
+pub struct OrderRisk {
+    pub market: String,
+    pub side: Side,
+    pub price: f64,
+    pub quantity: f64,
+    pub oracle_price: f64,
+    /// The signed position in the market before the order, negative when short.
+    pub position: f64,
+    /// The quantity of our orders already resting on the same side of the book.
+    pub open_quantity: f64,
+    pub open_orders: usize,
+}
+
+impl OrderRisk {
+    fn notional(&self) -> f64 {
+        self.price * self.quantity
+    }
+
+    /// The position if this order and every resting order on the same side filled.
+    fn projected_position(&self) -> f64 {
+        match self.side {
+            Side::Bid => self.position + self.open_quantity + self.quantity,
+            Side::Ask => self.position - self.open_quantity - self.quantity,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RiskBreach<'a> {
+    timestamp: String,
+    market: &'a str,
+    side: String,
+    price: f64,
+    quantity: f64,
+    oracle_price: f64,
+    limit: &'a str,
+    limit_value: f64,
+    value: f64,
+}
+
+/// The notional of a submitted order, kept so the daily notional survives a restart.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmittedNotional {
+    timestamp: String,
+    market: String,
+    notional: f64,
+}
+
+/// Enforces the session's risk limits and keeps track of the notional submitted per market and day.
+///
+/// Every breach is written to `risk.jsonl` in the log directory, and every submitted order to `submitted_notional.jsonl`,
+/// which the daily notional is rebuilt from when the engine is created.
+pub struct RiskEngine {
+    config: RiskConfig,
+    log_dir: PathBuf,
+    daily_notional: Mutex<HashMap<String, (NaiveDate, f64)>>,
+}
+
+impl RiskEngine {
+    pub fn default() -> Self {
+        Self {
+            config: RiskConfig::default(),
+            log_dir: PathBuf::new(),
+            daily_notional: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn new(config: RiskConfig, log_dir: &Path) -> Self {
+        Self {
+            config,
+            log_dir: log_dir.to_path_buf(),
+            daily_notional: Mutex::new(load_daily_notional(log_dir)),
+        }
+    }
+
+    /// Checks the order against the limits for its market.
+    pub async fn check(&self, order: &OrderRisk) -> Result<(), CypherInteractiveError> {
+        let limits = self.config.get_limits(&order.market);
+
+        if let Some(max) = limits.max_order_notional {
+            let notional = order.notional();
+            if notional > max {
+                self.log_breach(order, "maxOrderNotional", max, notional);
+                return Err(CypherInteractiveError::RiskMaxOrderNotional(notional));
+            }
+        }
+
+        if let Some(max) = limits.max_position {
+            let projected = order.projected_position();
+            // orders which reduce the position are always allowed
+            if projected.abs() > max && projected.abs() > order.position.abs() {
+                self.log_breach(order, "maxPosition", max, projected);
+                return Err(CypherInteractiveError::RiskMaxPosition(projected));
+            }
+        }
+
+        if let Some(max) = limits.max_open_orders {
+            if order.open_orders >= max {
+                self.log_breach(
+                    order,
+                    "maxOpenOrders",
+                    max as f64,
+                    (order.open_orders + 1) as f64,
+                );
+                return Err(CypherInteractiveError::RiskMaxOpenOrders(
+                    order.open_orders + 1,
+                ));
+            }
+        }
+
+        if let Some(band) = limits.price_band {
+            if order.oracle_price > 0.0 {
+                let deviation = (order.price - order.oracle_price).abs() / order.oracle_price;
+                if deviation > band {
+                    self.log_breach(order, "priceBand", band, deviation);
+                    return Err(CypherInteractiveError::RiskPriceBand(deviation));
+                }
+            }
+        }
+
+        if let Some(max) = limits.max_daily_notional {
+            let daily = self.get_daily_notional(&order.market).await + order.notional();
+            if daily > max {
+                self.log_breach(order, "maxDailyNotional", max, daily);
+                return Err(CypherInteractiveError::RiskMaxDailyNotional(daily));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a submitted order to its market's daily notional.
+    ///
+    /// Market orders are immediate or cancel, they are booked by [`RiskEngine::watch_fills`] as they fill instead.
+    pub async fn record(&self, order: &OrderRisk) {
+        self.add_notional(&order.market, order.notional(), Utc::today().naive_utc())
+            .await;
+    }
+
+    /// Adds what our market orders filled to the daily notional until the services are shut down.
+    pub async fn watch_fills(&self, mut receiver: Receiver<Fill>, mut shutdown: Receiver<bool>) {
+        loop {
+            tokio::select! {
+                fill = receiver.recv() => {
+                    if let Ok(fill) = fill {
+                        self.record_fill(&fill).await;
+                    }
+                },
+                _ = shutdown.recv() => {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Adds the filled part of a market order to its market's daily notional, other fills were booked when submitted.
+    pub async fn record_fill(&self, fill: &Fill) {
+        if fill.client_order_id != MARKET_ORDER_CLIENT_ORDER_ID {
+            return;
+        }
+        self.add_notional(
+            &fill.market,
+            fill.price * fill.quantity,
+            Utc::today().naive_utc(),
+        )
+        .await;
+    }
+
+    /// The notional submitted on the given market today.
+    pub async fn get_daily_notional(&self, market: &str) -> f64 {
+        self.get_notional_on(market, Utc::today().naive_utc()).await
+    }
+
+    async fn add_notional(&self, market: &str, notional: f64, today: NaiveDate) {
+        let mut daily_notional = self.daily_notional.lock().await;
+        let entry = daily_notional
+            .entry(market.to_string())
+            .or_insert((today, 0.0));
+        if entry.0 != today {
+            *entry = (today, 0.0);
+        }
+        entry.1 += notional;
+
+        // the default engine has nowhere to keep it
+        if self.log_dir.as_os_str().is_empty() {
+            return;
+        }
+        let submitted = SubmittedNotional {
+            timestamp: Utc::now().to_rfc3339(),
+            market: market.to_string(),
+            notional,
+        };
+        match self.append(SUBMITTED_NOTIONAL_JSONL, &submitted) {
+            Ok(_) => (),
+            Err(e) => {
+                println!(
+                    "[RISK] Failed to write the order to the submitted notional log: {:?}",
+                    e
+                );
+            }
+        }
+    }
+
+    async fn get_notional_on(&self, market: &str, today: NaiveDate) -> f64 {
+        match self.daily_notional.lock().await.get(market) {
+            Some((day, notional)) if *day == today => *notional,
+            _ => 0.0,
+        }
+    }
+
+    fn log_breach(&self, order: &OrderRisk, limit: &str, limit_value: f64, value: f64) {
+        println!(
+            "[RISK] Order on {} breaches {}: {} exceeds the limit of {}.",
+            order.market, limit, value, limit_value
+        );
+
+        if self.log_dir.as_os_str().is_empty() {
+            return;
+        }
+        let breach = RiskBreach {
+            timestamp: Utc::now().to_rfc3339(),
+            market: &order.market,
+            side: format!("{:?}", order.side),
+            price: order.price,
+            quantity: order.quantity,
+            oracle_price: order.oracle_price,
+            limit,
+            limit_value,
+            value,
+        };
+        match self.append(RISK_LOG_JSONL, &breach) {
+            Ok(_) => (),
+            Err(e) => {
+                println!("[RISK] Failed to write breach to the risk log: {:?}", e);
+            }
+        }
+    }
+
+    fn append<T: Serialize>(
+        &self,
+        file_name: &str,
+        record: &T,
+    ) -> Result<(), CypherInteractiveError> {
+        if create_dir_all(&self.log_dir).is_err() {
+            return Err(CypherInteractiveError::RiskLogWrite);
+        }
+        let json = match serde_json::to_string(record) {
+            Ok(j) => j,
+            Err(_) => {
+                return Err(CypherInteractiveError::RiskLogWrite);
+            }
+        };
+        let mut file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_dir.join(file_name))
+        {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(CypherInteractiveError::RiskLogWrite);
+            }
+        };
+        if writeln!(file, "{}", json).is_err() {
+            return Err(CypherInteractiveError::RiskLogWrite);
+        }
+
+        Ok(())
+    }
+}
+
+/// Sums today's submitted notional per market from the log directory, a missing log means nothing was submitted yet.
+fn load_daily_notional(log_dir: &Path) -> HashMap<String, (NaiveDate, f64)> {
+    let mut daily_notional = HashMap::new();
+    let file = match File::open(log_dir.join(SUBMITTED_NOTIONAL_JSONL)) {
+        Ok(f) => f,
+        Err(_) => {
+            return daily_notional;
+        }
+    };
+
+    let today = Utc::today().naive_utc();
+    for line in BufReader::new(file).lines().flatten() {
+        let submitted: SubmittedNotional = match serde_json::from_str(&line) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        let day = match DateTime::parse_from_rfc3339(&submitted.timestamp) {
+            Ok(t) => t.with_timezone(&Utc).date().naive_utc(),
+            Err(_) => continue,
+        };
+        if day == today {
+            daily_notional
+                .entry(submitted.market)
+                .or_insert((today, 0.0))
+                .1 += submitted.notional;
+        }
+    }
+
+    daily_notional
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::Duration, std::fs::remove_dir_all};
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cypher-interactive-risk-{}-{}",
+            name,
+            std::process::id()
+        ));
+        _ = remove_dir_all(&dir);
+        dir
+    }
+
+    fn engine(name: &str, limits: RiskLimits) -> RiskEngine {
+        let config = RiskConfig {
+            defaults: limits,
+            markets: HashMap::new(),
+        };
+        RiskEngine::new(config, &log_dir(name))
+    }
+
+    fn order(side: Side, price: f64, quantity: f64) -> OrderRisk {
+        OrderRisk {
+            market: "SOL".to_string(),
+            side,
+            price,
+            quantity,
+            oracle_price: price,
+            position: 0.0,
+            open_quantity: 0.0,
+            open_orders: 0,
+        }
+    }
+
+    fn fill(client_order_id: u64, price: f64, quantity: f64) -> Fill {
+        Fill {
+            market: "SOL".to_string(),
+            side: Side::Bid,
+            maker: false,
+            price,
+            quantity,
+            fee: 0.0,
+            native_base_quantity: 0,
+            native_quote_quantity: 0,
+            native_fee: 0,
+            order_id: 0,
+            client_order_id,
+            slot: 0,
+            timestamp: Utc::now().to_rfc3339(),
+        }
+    }
+
+    #[tokio::test]
+    async fn max_position_counts_resting_orders_and_allows_reducing() {
+        let engine = engine(
+            "max-position",
+            RiskLimits {
+                max_position: Some(10.0),
+                ..RiskLimits::default()
+            },
+        );
+
+        let mut bid = order(Side::Bid, 30.0, 2.0);
+        bid.position = 8.0;
+        bid.open_quantity = 1.0;
+        assert!(matches!(
+            engine.check(&bid).await,
+            Err(CypherInteractiveError::RiskMaxPosition(p)) if p == 11.0
+        ));
+
+        bid.quantity = 1.0;
+        assert!(engine.check(&bid).await.is_ok());
+
+        // already past the limit, selling still brings the position down
+        let mut ask = order(Side::Ask, 30.0, 1.0);
+        ask.position = 12.0;
+        assert!(engine.check(&ask).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn price_band_rejects_prices_too_far_from_the_oracle() {
+        let engine = engine(
+            "price-band",
+            RiskLimits {
+                price_band: Some(0.05),
+                ..RiskLimits::default()
+            },
+        );
+
+        let mut bid = order(Side::Bid, 106.0, 1.0);
+        bid.oracle_price = 100.0;
+        assert!(matches!(
+            engine.check(&bid).await,
+            Err(CypherInteractiveError::RiskPriceBand(_))
+        ));
+
+        bid.price = 104.0;
+        assert!(engine.check(&bid).await.is_ok());
+
+        // without an oracle price there is nothing to compare against
+        bid.price = 1_000.0;
+        bid.oracle_price = 0.0;
+        assert!(engine.check(&bid).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn max_open_orders_counts_the_new_order() {
+        let engine = engine(
+            "max-open-orders",
+            RiskLimits {
+                max_open_orders: Some(2),
+                ..RiskLimits::default()
+            },
+        );
+
+        let mut bid = order(Side::Bid, 30.0, 1.0);
+        bid.open_orders = 1;
+        assert!(engine.check(&bid).await.is_ok());
+
+        bid.open_orders = 2;
+        assert!(matches!(
+            engine.check(&bid).await,
+            Err(CypherInteractiveError::RiskMaxOpenOrders(3))
+        ));
+        let log = std::fs::read_to_string(engine.log_dir.join(RISK_LOG_JSONL)).unwrap();
+        assert!(log.contains("\"limit\":\"maxOpenOrders\""), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn daily_notional_is_rebuilt_from_todays_submissions_only() {
+        let dir = log_dir("rebuild");
+        create_dir_all(&dir).unwrap();
+        let now = Utc::now();
+        let lines = [
+            serde_json::to_string(&SubmittedNotional {
+                timestamp: now.to_rfc3339(),
+                market: "SOL".to_string(),
+                notional: 30.0,
+            })
+            .unwrap(),
+            serde_json::to_string(&SubmittedNotional {
+                timestamp: (now - Duration::days(1)).to_rfc3339(),
+                market: "SOL".to_string(),
+                notional: 500.0,
+            })
+            .unwrap(),
+            serde_json::to_string(&SubmittedNotional {
+                timestamp: now.to_rfc3339(),
+                market: "ETH".to_string(),
+                notional: 7.0,
+            })
+            .unwrap(),
+            "not json".to_string(),
+            serde_json::to_string(&SubmittedNotional {
+                timestamp: now.to_rfc3339(),
+                market: "SOL".to_string(),
+                notional: 12.5,
+            })
+            .unwrap(),
+        ];
+        std::fs::write(dir.join(SUBMITTED_NOTIONAL_JSONL), lines.join("\n")).unwrap();
+
+        let engine = RiskEngine::new(RiskConfig::default(), &dir);
+        assert_eq!(engine.get_daily_notional("SOL").await, 42.5);
+        assert_eq!(engine.get_daily_notional("ETH").await, 7.0);
+
+        // what the engine records is rebuilt by the next one
+        engine.record(&order(Side::Bid, 10.0, 1.0)).await;
+        let restarted = RiskEngine::new(RiskConfig::default(), &dir);
+        assert_eq!(restarted.get_daily_notional("SOL").await, 52.5);
+    }
+
+    #[tokio::test]
+    async fn daily_notional_starts_over_on_a_new_day() {
+        let engine = engine("rollover", RiskLimits::default());
+        let day = NaiveDate::from_ymd(2022, 6, 1);
+        let next_day = day.succ();
+
+        engine.add_notional("SOL", 50.0, day).await;
+        engine.add_notional("SOL", 25.0, day).await;
+        assert_eq!(engine.get_notional_on("SOL", day).await, 75.0);
+        assert_eq!(engine.get_notional_on("SOL", next_day).await, 0.0);
+
+        engine.add_notional("SOL", 10.0, next_day).await;
+        assert_eq!(engine.get_notional_on("SOL", next_day).await, 10.0);
+        assert_eq!(engine.get_notional_on("SOL", day).await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn market_orders_are_booked_as_they_fill() {
+        let engine = engine("market-fills", RiskLimits::default());
+
+        engine
+            .record_fill(&fill(MARKET_ORDER_CLIENT_ORDER_ID, 30.0, 0.5))
+            .await;
+        assert_eq!(engine.get_daily_notional("SOL").await, 15.0);
+
+        // limit orders were booked in full when they were submitted
+        engine.record_fill(&fill(1, 30.0, 2.0)).await;
+        assert_eq!(engine.get_daily_notional("SOL").await, 15.0);
+    }
+}
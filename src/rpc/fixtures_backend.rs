@@ -0,0 +1,191 @@
+use {
+    super::{RpcBackend, SimulationResult},
+    crate::CypherInteractiveError,
+    async_trait::async_trait,
+    serde::Deserialize,
+    solana_account_decoder::{
+        parse_token::{token_amount_to_ui_amount, UiTokenAmount},
+        UiAccount,
+    },
+    solana_client::client_error::{ClientError, ClientErrorKind},
+    solana_sdk::{
+        account::Account, hash::Hash, program_pack::Pack, pubkey::Pubkey, signature::Signature,
+        transaction::Transaction,
+    },
+    std::{
+        collections::HashMap,
+        fs::{read_dir, File},
+        io::BufReader,
+        path::Path,
+        str::FromStr,
+    },
+};
+
+/// An account fixture in the format written by `solana account --output json`.
+#[derive(Debug, Deserialize)]
+struct AccountFixture {
+    pubkey: String,
+    account: UiAccount,
+}
+
+/// An in-memory rpc backend serving accounts loaded from json fixtures.
+///
+/// Transactions are checked for valid signatures and acknowledged but never executed,
+/// so the accounts stay exactly as they were loaded.
+pub struct FixturesRpcBackend {
+    slot: u64,
+    accounts: HashMap<Pubkey, Account>,
+}
+
+impl FixturesRpcBackend {
+    /// Loads every `.json` file in the given directory as an account fixture.
+    pub fn load(dir: &Path) -> Result<Self, CypherInteractiveError> {
+        let entries = match read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => {
+                return Err(CypherInteractiveError::FixturesLoad);
+            }
+        };
+
+        let mut accounts: HashMap<Pubkey, Account> = HashMap::new();
+        for entry in entries {
+            let path = match entry {
+                Ok(e) => e.path(),
+                Err(_) => {
+                    return Err(CypherInteractiveError::FixturesLoad);
+                }
+            };
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let file = match File::open(&path) {
+                Ok(f) => f,
+                Err(_) => {
+                    return Err(CypherInteractiveError::FixturesLoad);
+                }
+            };
+            let fixture: AccountFixture = match serde_json::from_reader(BufReader::new(file)) {
+                Ok(f) => f,
+                Err(e) => {
+                    println!("Failed to parse fixture {}: {}", path.display(), e);
+                    return Err(CypherInteractiveError::FixturesLoad);
+                }
+            };
+            let pubkey = match Pubkey::from_str(&fixture.pubkey) {
+                Ok(pk) => pk,
+                Err(_) => {
+                    println!("Invalid pubkey in fixture {}.", path.display());
+                    return Err(CypherInteractiveError::FixturesLoad);
+                }
+            };
+            let account: Account = match fixture.account.decode() {
+                Some(a) => a,
+                None => {
+                    println!("Invalid account data in fixture {}.", path.display());
+                    return Err(CypherInteractiveError::FixturesLoad);
+                }
+            };
+            accounts.insert(pubkey, account);
+        }
+
+        Ok(Self { slot: 1, accounts })
+    }
+}
+
+fn custom_error(message: String) -> ClientError {
+    ClientErrorKind::Custom(message).into()
+}
+
+#[async_trait]
+impl RpcBackend for FixturesRpcBackend {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError> {
+        Ok(self.accounts.get(pubkey).cloned())
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<(u64, Vec<Option<Account>>), ClientError> {
+        Ok((
+            self.slot,
+            pubkeys
+                .iter()
+                .map(|pk| self.accounts.get(pk).cloned())
+                .collect(),
+        ))
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, ClientError> {
+        Ok(Hash::default())
+    }
+
+    async fn get_slot(&self) -> Result<u64, ClientError> {
+        Ok(self.slot)
+    }
+
+    async fn get_token_account_balance(
+        &self,
+        pubkey: &Pubkey,
+    ) -> Result<UiTokenAmount, ClientError> {
+        let token_account = match self.accounts.get(pubkey) {
+            Some(a) => match spl_token::state::Account::unpack(&a.data) {
+                Ok(ta) => ta,
+                Err(_) => {
+                    return Err(custom_error(format!("{} is not a token account", pubkey)));
+                }
+            },
+            None => {
+                return Err(custom_error(format!("could not find account {}", pubkey)));
+            }
+        };
+        let mint = match self.accounts.get(&token_account.mint) {
+            Some(a) => match spl_token::state::Mint::unpack(&a.data) {
+                Ok(m) => m,
+                Err(_) => {
+                    return Err(custom_error(format!(
+                        "{} is not a mint",
+                        token_account.mint
+                    )));
+                }
+            },
+            None => {
+                return Err(custom_error(format!(
+                    "could not find mint {}",
+                    token_account.mint
+                )));
+            }
+        };
+
+        Ok(token_amount_to_ui_amount(
+            token_account.amount,
+            mint.decimals,
+        ))
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, ClientError> {
+        if transaction.verify().is_err() {
+            return Err(custom_error(
+                "transaction signature verification failed".to_string(),
+            ));
+        }
+
+        Ok(transaction.signatures.first().copied().unwrap_or_default())
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ClientError> {
+        if transaction.verify().is_err() {
+            return Err(custom_error(
+                "transaction signature verification failed".to_string(),
+            ));
+        }
+
+        Ok(SimulationResult::default())
+    }
+}
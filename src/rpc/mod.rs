@@ -0,0 +1,141 @@
+pub mod fixtures_backend;
+
+pub use fixtures_backend::*;
+
+use {
+    async_trait::async_trait,
+    solana_account_decoder::parse_token::UiTokenAmount,
+    solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
+    solana_sdk::{
+        account::Account, commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey,
+        signature::Signature, transaction::Transaction, transaction::TransactionError,
+    },
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct SimulationResult {
+    pub err: Option<TransactionError>,
+    pub logs: Vec<String>,
+}
+
+/// The subset of the rpc api the application relies on, so services, handlers and utilities
+/// can be driven by something other than a live cluster.
+///
+/// Every call uses the `confirmed` commitment.
+#[async_trait]
+pub trait RpcBackend: Send + Sync {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError>;
+
+    /// Returns the accounts along with the slot at which they were read.
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<(u64, Vec<Option<Account>>), ClientError>;
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, ClientError>;
+
+    async fn get_slot(&self) -> Result<u64, ClientError>;
+
+    async fn get_token_account_balance(
+        &self,
+        pubkey: &Pubkey,
+    ) -> Result<UiTokenAmount, ClientError>;
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, ClientError>;
+
+    async fn send_and_confirm_transaction_with_spinner(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, ClientError> {
+        self.send_and_confirm_transaction(transaction).await
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ClientError>;
+}
+
+#[async_trait]
+impl RpcBackend for RpcClient {
+    async fn get_account(&self, pubkey: &Pubkey) -> Result<Option<Account>, ClientError> {
+        match self
+            .get_account_with_commitment(pubkey, CommitmentConfig::confirmed())
+            .await
+        {
+            Ok(r) => Ok(r.value),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> Result<(u64, Vec<Option<Account>>), ClientError> {
+        match self
+            .get_multiple_accounts_with_commitment(pubkeys, CommitmentConfig::confirmed())
+            .await
+        {
+            Ok(r) => Ok((r.context.slot, r.value)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, ClientError> {
+        match self
+            .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            .await
+        {
+            Ok(r) => Ok(r.0),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_slot(&self) -> Result<u64, ClientError> {
+        self.get_slot_with_commitment(CommitmentConfig::confirmed())
+            .await
+    }
+
+    async fn get_token_account_balance(
+        &self,
+        pubkey: &Pubkey,
+    ) -> Result<UiTokenAmount, ClientError> {
+        match self
+            .get_token_account_balance_with_commitment(pubkey, CommitmentConfig::confirmed())
+            .await
+        {
+            Ok(r) => Ok(r.value),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn send_and_confirm_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, ClientError> {
+        RpcClient::send_and_confirm_transaction(self, transaction).await
+    }
+
+    async fn send_and_confirm_transaction_with_spinner(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, ClientError> {
+        RpcClient::send_and_confirm_transaction_with_spinner(self, transaction).await
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ClientError> {
+        match RpcClient::simulate_transaction(self, transaction).await {
+            Ok(r) => Ok(SimulationResult {
+                err: r.value.err,
+                logs: r.value.logs.unwrap_or_default(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+}
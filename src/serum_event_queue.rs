@@ -0,0 +1,227 @@
+#![allow(dead_code)]
+use bitflags::bitflags;
+use bytemuck::{cast, try_cast_slice, try_from_bytes, Pod, Zeroable};
+use serum_dex::matching::Side;
+use solana_sdk::pubkey::Pubkey;
+use static_assertions::const_assert_eq;
+use std::mem::size_of;
+
+const ACCOUNT_HEAD_PADDING: usize = 5;
+const ACCOUNT_TAIL_PADDING: usize = 7;
+
+bitflags! {
+    struct EventFlag: u8 {
+        const FILL = 0x1;
+        const OUT = 0x2;
+        const BID = 0x4;
+        const MAKER = 0x8;
+        const RELEASE_FUNDS = 0x10;
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(packed)]
+struct EventQueueHeader {
+    account_flags: u64,
+    head: u64,
+    count: u64,
+    seq_num: u64,
+}
+unsafe impl Zeroable for EventQueueHeader {}
+unsafe impl Pod for EventQueueHeader {}
+
+#[derive(Copy, Clone)]
+#[repr(packed)]
+struct Event {
+    event_flags: u8,           // 1
+    owner_slot: u8,            // 2
+    fee_tier: u8,              // 3
+    _padding: [u8; 5],         // 8
+    native_qty_released: u64,  // 16
+    native_qty_paid: u64,      // 24
+    native_fee_or_rebate: u64, // 32
+    order_id: u128,            // 48
+    owner: [u64; 4],           // 80
+    client_order_id: u64,      // 88
+}
+unsafe impl Zeroable for Event {}
+unsafe impl Pod for Event {}
+
+const EVENT_QUEUE_HEADER_LEN: usize = size_of::<EventQueueHeader>();
+const EVENT_LEN: usize = size_of::<Event>();
+
+const_assert_eq!(EVENT_QUEUE_HEADER_LEN, 32);
+const_assert_eq!(EVENT_LEN, 88);
+
+#[derive(Debug, Clone, Copy)]
+pub struct FillEvent {
+    pub seq_num: u64,
+    pub side: Side,
+    pub maker: bool,
+    pub native_qty_paid: u64,
+    pub native_qty_released: u64,
+    pub native_fee_or_rebate: u64,
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub owner_slot: u8,
+    pub client_order_id: u64,
+}
+
+impl FillEvent {
+    /// The amount of base token exchanged, in native units.
+    pub fn base_quantity(&self) -> u64 {
+        match self.side {
+            Side::Bid => self.native_qty_released,
+            Side::Ask => self.native_qty_paid,
+        }
+    }
+
+    /// The amount of quote token exchanged before fees, in native units.
+    pub fn quote_quantity(&self) -> u64 {
+        match (self.side, self.maker) {
+            (Side::Bid, true) => self.native_qty_paid + self.native_fee_or_rebate,
+            (Side::Bid, false) => self
+                .native_qty_paid
+                .saturating_sub(self.native_fee_or_rebate),
+            (Side::Ask, true) => self
+                .native_qty_released
+                .saturating_sub(self.native_fee_or_rebate),
+            (Side::Ask, false) => self.native_qty_released + self.native_fee_or_rebate,
+        }
+    }
+
+    /// The fee paid for this fill in native quote units, negative when it is a maker rebate.
+    pub fn fee(&self) -> i64 {
+        if self.maker {
+            -(self.native_fee_or_rebate as i64)
+        } else {
+            self.native_fee_or_rebate as i64
+        }
+    }
+}
+
+pub struct EventQueue<'a> {
+    header: &'a EventQueueHeader,
+    events: &'a [Event],
+}
+
+impl<'a> EventQueue<'a> {
+    /// Creates a view over the raw event queue account data, including the serum account paddings.
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < ACCOUNT_HEAD_PADDING + EVENT_QUEUE_HEADER_LEN + ACCOUNT_TAIL_PADDING {
+            return None;
+        }
+        let data = &data[ACCOUNT_HEAD_PADDING..data.len() - ACCOUNT_TAIL_PADDING];
+        let (header_bytes, events_bytes) = data.split_at(EVENT_QUEUE_HEADER_LEN);
+        let events_len = events_bytes.len() - events_bytes.len() % EVENT_LEN;
+
+        let header: &EventQueueHeader = try_from_bytes(header_bytes).ok()?;
+        let events: &[Event] = try_cast_slice(&events_bytes[..events_len]).ok()?;
+
+        if events.is_empty() {
+            return None;
+        }
+
+        Some(Self { header, events })
+    }
+
+    /// The sequence number that will be assigned to the next event pushed to the queue.
+    pub fn seq_num(&self) -> u64 {
+        self.header.seq_num
+    }
+
+    /// Returns every fill event with a sequence number greater or equal to `from_seq_num`.
+    ///
+    /// Events which have already been consumed by the crank remain readable until
+    /// their slot is overwritten, so fills are only lost if more than a full queue
+    /// worth of events happened since `from_seq_num`.
+    pub fn fills_since(&self, from_seq_num: u64) -> Vec<FillEvent> {
+        let capacity = self.events.len() as u64;
+        let seq_num = self.seq_num();
+        let start = from_seq_num.max(seq_num.saturating_sub(capacity));
+        let mut fills = Vec::new();
+
+        for s in start..seq_num {
+            let event = self.events[(s % capacity) as usize];
+            let flags = match EventFlag::from_bits(event.event_flags) {
+                Some(f) => f,
+                None => {
+                    continue;
+                }
+            };
+            if !flags.contains(EventFlag::FILL) {
+                continue;
+            }
+
+            let side = if flags.contains(EventFlag::BID) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let owner: [u64; 4] = event.owner;
+
+            fills.push(FillEvent {
+                seq_num: s,
+                side,
+                maker: flags.contains(EventFlag::MAKER),
+                native_qty_paid: event.native_qty_paid,
+                native_qty_released: event.native_qty_released,
+                native_fee_or_rebate: event.native_fee_or_rebate,
+                order_id: event.order_id,
+                owner: Pubkey::new_from_array(cast(owner)),
+                owner_slot: event.owner_slot,
+                client_order_id: event.client_order_id,
+            });
+        }
+
+        fills
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fill of 1 SOL at 32 USDC with a 0.0096 USDC fee or rebate.
+    fn fill(side: Side, maker: bool) -> FillEvent {
+        let (native_qty_paid, native_qty_released) = match (side, maker) {
+            // the maker rebate lowers what a bid pays and raises what an ask receives
+            (Side::Bid, true) => (32_000_000 - 9_600, 1_000_000_000),
+            (Side::Bid, false) => (32_000_000 + 9_600, 1_000_000_000),
+            (Side::Ask, true) => (1_000_000_000, 32_000_000 + 9_600),
+            (Side::Ask, false) => (1_000_000_000, 32_000_000 - 9_600),
+        };
+        FillEvent {
+            seq_num: 0,
+            side,
+            maker,
+            native_qty_paid,
+            native_qty_released,
+            native_fee_or_rebate: 9_600,
+            order_id: 0,
+            owner: Pubkey::default(),
+            owner_slot: 0,
+            client_order_id: 0,
+        }
+    }
+
+    #[test]
+    fn quantities_exclude_the_fee_or_rebate() {
+        for (side, maker) in [
+            (Side::Bid, true),
+            (Side::Bid, false),
+            (Side::Ask, true),
+            (Side::Ask, false),
+        ] {
+            let fill = fill(side, maker);
+            assert_eq!(fill.base_quantity(), 1_000_000_000);
+            assert_eq!(fill.quote_quantity(), 32_000_000);
+        }
+    }
+
+    #[test]
+    fn maker_fees_are_negative() {
+        assert_eq!(fill(Side::Bid, true).fee(), -9_600);
+        assert_eq!(fill(Side::Ask, false).fee(), 9_600);
+    }
+}
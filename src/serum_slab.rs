@@ -1,8 +1,8 @@
 #![allow(dead_code, clippy::upper_case_acronyms)]
-use arrayref::array_refs;
-use bytemuck::{cast_mut, cast_ref, cast_slice, Pod, Zeroable};
+use bytemuck::{cast, cast_mut, cast_ref, try_cast_slice, try_from_bytes, Pod, Zeroable};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use solana_sdk::pubkey::Pubkey;
 use static_assertions::const_assert_eq;
 use std::{
     convert::TryFrom,
@@ -105,8 +105,8 @@ impl LeafNode {
     }
 
     #[inline]
-    pub fn price(&self) -> NonZeroU64 {
-        NonZeroU64::new((self.key >> 64) as u64).unwrap()
+    pub fn price(&self) -> Option<NonZeroU64> {
+        NonZeroU64::new((self.key >> 64) as u64)
     }
 
     #[inline]
@@ -203,7 +203,7 @@ impl AnyNode {
     }
 
     fn children(&self) -> Option<[u32; 2]> {
-        match self.case().unwrap() {
+        match self.case()? {
             NodeRef::Inner(&InnerNode { children, .. }) => Some(children),
             NodeRef::Leaf(_) => None,
         }
@@ -273,50 +273,76 @@ unsafe impl Pod for SlabHeader {}
 
 const SLAB_HEADER_LEN: usize = size_of::<SlabHeader>();
 
-#[cfg(debug_assertions)]
-unsafe fn invariant(check: bool) {
-    if check {
-        unreachable!();
-    }
-}
-
-#[cfg(not(debug_assertions))]
-#[inline(always)]
-unsafe fn invariant(check: bool) {
-    if check {
-        std::hint::unreachable_unchecked();
-    }
-}
+/// The `serum` padding at the start of every dex account.
+const ACCOUNT_HEAD_PADDING: usize = 5;
+/// The `padding` at the end of every dex account.
+const ACCOUNT_TAIL_PADDING: usize = 7;
+/// The account flags which precede the slab in the bids and asks accounts.
+const ACCOUNT_FLAGS_LEN: usize = size_of::<OrderBookStateHeader>();
 
 #[derive(Debug, Clone, Copy)]
 pub struct OrderBookOrder {
     pub price: u64,
+    pub price_lots: u64,
     pub quantity: u64,
     pub order_id: u128,
     pub client_order_id: u64,
+    pub owner: Pubkey,
+    pub owner_slot: u8,
 }
 
-#[repr(transparent)]
-pub struct Slab([u8]);
-
-impl Slab {
-    /// Creates a slab that holds and references the bytes
-    ///
-    /// ```compile_fail
-    /// let slab = {
-    ///     let mut bytes = [10; 100];
-    ///     serum_dex::critbit::Slab::new(&mut bytes)
-    /// };
-    /// ```
-    #[inline]
-    pub fn new(bytes: &mut [u8]) -> &mut Self {
-        let len_without_header = bytes.len().checked_sub(SLAB_HEADER_LEN).unwrap();
-        let slop = len_without_header % size_of::<AnyNode>();
-        let truncated_len = bytes.len() - slop;
-        let bytes = &mut bytes[..truncated_len];
-        let slab: &mut Self = unsafe { &mut *(bytes as *mut [u8] as *mut Slab) };
-        slab.check_size_align(); // check alignment
-        slab
+/// A read-only view over the critbit tree stored in a bids or asks account.
+///
+/// Decoding never trusts the account data, malformed or mid-update slabs produce a [`SlabTreeError`].
+pub struct Slab<'a> {
+    header: SlabHeader,
+    nodes: &'a [AnyNode],
+}
+
+impl<'a> Slab<'a> {
+    /// Creates a slab that references the given bytes, which must start with the slab header.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SlabTreeError> {
+        if bytes.len() < SLAB_HEADER_LEN {
+            return Err(SlabTreeError::InvalidLength(bytes.len()));
+        }
+        let (header_bytes, nodes_bytes) = bytes.split_at(SLAB_HEADER_LEN);
+        let slop = nodes_bytes.len() % size_of::<AnyNode>();
+        let nodes_bytes = &nodes_bytes[..nodes_bytes.len() - slop];
+
+        let header: SlabHeader = match try_from_bytes(header_bytes) {
+            Ok(h) => *h,
+            Err(_) => {
+                return Err(SlabTreeError::InvalidLength(bytes.len()));
+            }
+        };
+        let nodes: &[AnyNode] = match try_cast_slice(nodes_bytes) {
+            Ok(n) => n,
+            Err(_) => {
+                return Err(SlabTreeError::InvalidLength(bytes.len()));
+            }
+        };
+
+        Ok(Self { header, nodes })
+    }
+
+    /// Creates a slab from the raw data of a bids or asks account, skipping the account padding and flags.
+    pub fn from_account_data(data: &'a [u8]) -> Result<Self, SlabTreeError> {
+        let start = ACCOUNT_HEAD_PADDING + ACCOUNT_FLAGS_LEN;
+        if data.len() < start + ACCOUNT_TAIL_PADDING {
+            return Err(SlabTreeError::InvalidLength(data.len()));
+        }
+
+        Self::new(&data[start..data.len() - ACCOUNT_TAIL_PADDING])
+    }
+
+    /// Decodes every order in the slab, in price-time priority.
+    pub fn get_orders(
+        &self,
+        pc_lot_size: u64,
+        coin_lot_size: u64,
+        is_asks: bool,
+    ) -> Result<Vec<OrderBookOrder>, SlabTreeError> {
+        self.get_depth(u64::MAX, pc_lot_size, coin_lot_size, is_asks)
     }
 
     //Each one of these does a preorder traversal
@@ -326,64 +352,108 @@ impl Slab {
         pc_lot_size: u64,
         coin_lot_size: u64,
         is_asks: bool,
-    ) -> Vec<OrderBookOrder> {
-        let (header, _nodes) = self.parts();
-        let depth_to_get: usize = std::cmp::min(depth, header.leaf_count) as usize;
-        let mut res: Vec<OrderBookOrder> = Vec::with_capacity(depth_to_get);
-        let maybe_leafs = self.get_leaf_depth(depth_to_get, is_asks);
-
-        let leafs = match maybe_leafs {
-            Some(l) => l,
-            _ => {
-                return res;
-            }
-        };
+    ) -> Result<Vec<OrderBookOrder>, SlabTreeError> {
+        if coin_lot_size == 0 {
+            return Err(SlabTreeError::InvalidLotSize);
+        }
+
+        let depth_to_get = std::cmp::min(depth, self.header.leaf_count);
+        let depth_to_get = usize::try_from(depth_to_get).unwrap_or(usize::MAX);
+        let leafs = self.get_leaf_depth(depth_to_get, is_asks)?;
+
+        let mut res: Vec<OrderBookOrder> = Vec::with_capacity(leafs.len());
         for leaf in leafs {
-            let leaf_price = u64::from(leaf.price());
+            let leaf_price = match leaf.price() {
+                Some(p) => u64::from(p),
+                None => {
+                    return Err(SlabTreeError::InvalidPrice(leaf.order_id()));
+                }
+            };
             let token_price =
                 u128::from(leaf_price) * u128::from(pc_lot_size) / u128::from(coin_lot_size);
-            let token_quantity = leaf.quantity() * coin_lot_size;
+            let owner: [u64; 4] = leaf.owner();
             let line = OrderBookOrder {
-                price: u64::try_from(token_price).unwrap(),
-                quantity: token_quantity,
+                price: u64::try_from(token_price).unwrap_or(u64::MAX),
+                price_lots: leaf_price,
+                quantity: leaf.quantity().saturating_mul(coin_lot_size),
                 order_id: leaf.order_id(),
-                client_order_id: leaf.client_order_id,
+                client_order_id: leaf.client_order_id(),
+                owner: Pubkey::new_from_array(cast(owner)),
+                owner_slot: leaf.owner_slot(),
             };
             res.push(line);
         }
 
-        res
-    }
-
-    #[allow(clippy::ptr_offset_with_cast)]
-    fn check_size_align(&self) {
-        let (header_bytes, nodes_bytes) = array_refs![&self.0, SLAB_HEADER_LEN; .. ;];
-        let _header: &SlabHeader = cast_ref(header_bytes);
-        let _nodes: &[AnyNode] = cast_slice(nodes_bytes);
+        Ok(res)
     }
 
-    #[allow(clippy::ptr_offset_with_cast)]
-    fn parts(&self) -> (&SlabHeader, &[AnyNode]) {
-        unsafe {
-            invariant(self.0.len() < size_of::<SlabHeader>());
-            invariant((self.0.as_ptr() as usize) % align_of::<SlabHeader>() != 0);
-            invariant(
-                ((self.0.as_ptr() as usize) + size_of::<SlabHeader>()) % align_of::<AnyNode>() != 0,
-            );
+    fn root(&self) -> Option<NodeHandle> {
+        if self.header.leaf_count == 0 {
+            return None;
         }
 
-        let (header_bytes, nodes_bytes) = array_refs![&self.0, SLAB_HEADER_LEN; .. ;];
-        let header = cast_ref(header_bytes);
-        let nodes = cast_slice(nodes_bytes);
-        (header, nodes)
+        Some(self.header.root_node)
     }
 
-    fn header(&self) -> &SlabHeader {
-        self.parts().0
-    }
+    fn get_leaf_depth(&self, depth: usize, asc: bool) -> Result<Vec<&'a LeafNode>, SlabTreeError> {
+        let nodes = self.nodes;
+        let mut res: Vec<&'a LeafNode> = Vec::with_capacity(depth.min(nodes.len()));
+        let root = match self.root() {
+            Some(r) => r,
+            None => {
+                return Ok(res);
+            }
+        };
+
+        // every node of a well formed tree is reachable through exactly one path,
+        // seeing a node twice means the handles form a cycle or a shared subtree
+        let mut visited = vec![false; nodes.len()];
+        let mut stack: Vec<NodeHandle> = vec![root];
+
+        while let Some(handle) = stack.pop() {
+            if res.len() == depth {
+                break;
+            }
+
+            let node: &'a AnyNode = match nodes.get(handle as usize) {
+                Some(n) => n,
+                None => {
+                    return Err(SlabTreeError::HandleOutOfRange(handle));
+                }
+            };
+            if visited[handle as usize] {
+                return Err(SlabTreeError::Cycle(handle));
+            }
+            visited[handle as usize] = true;
+
+            match node.case() {
+                Some(NodeRef::Inner(&InnerNode { children, .. })) => {
+                    if asc {
+                        stack.push(children[1]);
+                        stack.push(children[0]);
+                    } else {
+                        stack.push(children[0]);
+                        stack.push(children[1]);
+                    }
+                }
+                Some(NodeRef::Leaf(leaf)) => {
+                    res.push(leaf);
+                }
+                None => {
+                    let tag = node.tag;
+                    return Err(SlabTreeError::InvalidTag { handle, tag });
+                }
+            }
+        }
+
+        if res.len() < depth {
+            return Err(SlabTreeError::LeafCountMismatch {
+                expected: self.header.leaf_count,
+                found: res.len(),
+            });
+        }
 
-    fn nodes(&self) -> &[AnyNode] {
-        self.parts().1
+        Ok(res)
     }
 }
 
@@ -391,9 +461,9 @@ pub trait SlabView<T> {
     fn get(&self, h: NodeHandle) -> Option<&T>;
 }
 
-impl SlabView<AnyNode> for Slab {
+impl<'a> SlabView<AnyNode> for Slab<'a> {
     fn get(&self, key: u32) -> Option<&AnyNode> {
-        let node = self.nodes().get(key as usize)?;
+        let node = self.nodes.get(key as usize)?;
         let tag = NodeTag::try_from(node.tag);
         match tag {
             Ok(NodeTag::InnerNode) | Ok(NodeTag::LeafNode) => Some(node),
@@ -402,50 +472,276 @@ impl SlabView<AnyNode> for Slab {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlabTreeError {
-    OutOfSpace,
+    /// The data is too short to hold a slab.
+    InvalidLength(usize),
+    /// A node reachable from the root is neither an inner node nor a leaf.
+    InvalidTag {
+        handle: NodeHandle,
+        tag: u32,
+    },
+    /// A node references a child outside of the slab.
+    HandleOutOfRange(NodeHandle),
+    /// A node is reachable more than once from the root.
+    Cycle(NodeHandle),
+    /// The tree holds fewer leaves than the header claims.
+    LeafCountMismatch {
+        expected: u64,
+        found: usize,
+    },
+    /// A leaf has a zero price.
+    InvalidPrice(u128),
+    InvalidLotSize,
 }
 
-impl Slab {
-    fn root(&self) -> Option<NodeHandle> {
-        if self.header().leaf_count == 0 {
-            return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// The key the dex gives an ask, asks with the same price are filled in the order they were placed.
+    fn ask_key(price_lots: u64, seq_num: u64) -> u128 {
+        (u128::from(price_lots) << 64) | u128::from(seq_num)
+    }
+
+    /// The key the dex gives a bid, the sequence number is inverted so earlier bids sort higher.
+    fn bid_key(price_lots: u64, seq_num: u64) -> u128 {
+        (u128::from(price_lots) << 64) | u128::from(!seq_num)
+    }
+
+    fn leaf(key: u128) -> AnyNode {
+        cast(LeafNode::new(0, key, [0; 4], 1, FeeTier::Base, key as u64))
+    }
+
+    fn inner(prefix_len: u32, key: u128, children: [NodeHandle; 2]) -> AnyNode {
+        cast(InnerNode {
+            tag: NodeTag::InnerNode.into(),
+            prefix_len,
+            key,
+            children,
+            _padding: [0; 5],
+        })
+    }
+
+    fn slab_bytes(root_node: NodeHandle, leaf_count: u64, nodes: &[AnyNode]) -> Vec<u8> {
+        let header = SlabHeader {
+            bump_index: nodes.len() as u64,
+            free_list_len: 0,
+            free_list_head: 0,
+            root_node,
+            leaf_count,
+        };
+        let mut bytes = bytemuck::bytes_of(&header).to_vec();
+        for node in nodes {
+            bytes.extend_from_slice(bytemuck::bytes_of(node));
+        }
+        bytes
+    }
+
+    /// Builds a critbit tree the way the dex does, one leaf per distinct key, and returns the handle of its root.
+    fn build_tree(keys: &[u128], nodes: &mut Vec<AnyNode>) -> NodeHandle {
+        if keys.len() == 1 {
+            nodes.push(leaf(keys[0]));
+            return (nodes.len() - 1) as NodeHandle;
         }
 
-        Some(self.header().root_node)
+        // the keys are sorted, so the highest bit in which any of them differ is the one where the first and last differ
+        let prefix_len = (keys[0] ^ keys[keys.len() - 1]).leading_zeros();
+        let crit_bit_mask = (1u128 << 127) >> prefix_len;
+        let split = keys.iter().position(|k| k & crit_bit_mask != 0).unwrap();
+
+        let left = build_tree(&keys[..split], nodes);
+        let right = build_tree(&keys[split..], nodes);
+        nodes.push(inner(prefix_len, keys[0], [left, right]));
+        (nodes.len() - 1) as NodeHandle
     }
 
-    fn get_leaf_depth(&self, depth: usize, asc: bool) -> Option<Vec<&LeafNode>> {
-        let root: NodeHandle = self.root()?;
-        //println!("root {}", root);
-        let mut stack: Vec<NodeHandle> = Vec::with_capacity(self.header().leaf_count as usize);
-        let mut res: Vec<&LeafNode> = Vec::with_capacity(depth);
-        stack.push(root);
-        loop {
-            if stack.is_empty() {
-                break;
-            }
-            let node_contents = self.get(stack.pop().unwrap()).unwrap();
-            match node_contents.case().unwrap() {
-                NodeRef::Inner(&InnerNode { children, .. }) => {
-                    if asc {
-                        stack.push(children[1]);
-                        stack.push(children[0]);
-                    } else {
-                        stack.push(children[0]);
-                        stack.push(children[1]);
-                    }
-                    continue;
-                }
-                NodeRef::Leaf(leaf) => {
-                    res.push(leaf);
-                }
-            }
-            if res.len() == depth {
-                break;
+    fn order_ids(orders: &[OrderBookOrder]) -> Vec<u128> {
+        orders.iter().map(|o| o.order_id).collect()
+    }
+
+    /// Two price levels under the root, each holding two orders placed one after the other.
+    fn two_level_slab(keys: [u128; 4]) -> Vec<u8> {
+        let mut sorted = keys;
+        sorted.sort_unstable();
+        let mut nodes = Vec::new();
+        let root = build_tree(&sorted, &mut nodes);
+        slab_bytes(root, 4, &nodes)
+    }
+
+    #[test]
+    fn asks_are_decoded_lowest_price_and_earliest_order_first() {
+        let keys = [
+            ask_key(3_300, 2),
+            ask_key(3_200, 4),
+            ask_key(3_300, 1),
+            ask_key(3_200, 3),
+        ];
+        let bytes = two_level_slab(keys);
+        let slab = Slab::new(&bytes).unwrap();
+
+        let asks = slab.get_orders(100, 1_000_000, true).unwrap();
+        assert_eq!(
+            order_ids(&asks),
+            vec![
+                ask_key(3_200, 3),
+                ask_key(3_200, 4),
+                ask_key(3_300, 1),
+                ask_key(3_300, 2)
+            ]
+        );
+        assert_eq!(asks[0].price_lots, 3_200);
+        assert_eq!(asks[0].quantity, 1_000_000);
+
+        let top = slab.get_depth(1, 100, 1_000_000, true).unwrap();
+        assert_eq!(order_ids(&top), vec![ask_key(3_200, 3)]);
+    }
+
+    #[test]
+    fn bids_are_decoded_highest_price_and_earliest_order_first() {
+        let keys = [
+            bid_key(3_100, 1),
+            bid_key(3_200, 4),
+            bid_key(3_100, 2),
+            bid_key(3_200, 3),
+        ];
+        let bytes = two_level_slab(keys);
+        let slab = Slab::new(&bytes).unwrap();
+
+        let bids = slab.get_orders(100, 1_000_000, false).unwrap();
+        assert_eq!(
+            order_ids(&bids),
+            vec![
+                bid_key(3_200, 3),
+                bid_key(3_200, 4),
+                bid_key(3_100, 1),
+                bid_key(3_100, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn an_empty_slab_has_no_orders() {
+        let bytes = slab_bytes(0, 0, &[]);
+        let slab = Slab::new(&bytes).unwrap();
+        assert!(slab.get_orders(1, 1, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let bytes = slab_bytes(0, 1, &[leaf(ask_key(1, 1))]);
+        assert_eq!(
+            Slab::new(&bytes[..SLAB_HEADER_LEN - 1]).err(),
+            Some(SlabTreeError::InvalidLength(SLAB_HEADER_LEN - 1))
+        );
+        assert_eq!(
+            Slab::from_account_data(&[0; 12]).err(),
+            Some(SlabTreeError::InvalidLength(12))
+        );
+
+        // a partial node is dropped, so the leaf the header promises is nowhere to be found
+        let slab = Slab::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::HandleOutOfRange(0))
+        );
+    }
+
+    #[test]
+    fn a_cycle_in_the_tree_is_rejected() {
+        let nodes = [inner(127, ask_key(1, 0), [1, 0]), leaf(ask_key(1, 0))];
+        let bytes = slab_bytes(0, 3, &nodes);
+        let slab = Slab::new(&bytes).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::Cycle(0))
+        );
+    }
+
+    #[test]
+    fn a_child_outside_of_the_slab_is_rejected() {
+        let nodes = [inner(127, ask_key(1, 0), [1, 7]), leaf(ask_key(1, 0))];
+        let bytes = slab_bytes(0, 2, &nodes);
+        let slab = Slab::new(&bytes).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::HandleOutOfRange(7))
+        );
+    }
+
+    #[test]
+    fn an_unknown_node_tag_is_rejected() {
+        let mut free: AnyNode = leaf(ask_key(1, 1));
+        free.tag = NodeTag::FreeNode.into();
+        let nodes = [inner(127, ask_key(1, 0), [1, 2]), leaf(ask_key(1, 0)), free];
+        let bytes = slab_bytes(0, 2, &nodes);
+        let slab = Slab::new(&bytes).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::InvalidTag { handle: 2, tag: 3 })
+        );
+    }
+
+    #[test]
+    fn a_leaf_count_larger_than_the_tree_is_rejected() {
+        let bytes = slab_bytes(0, 2, &[leaf(ask_key(1, 1))]);
+        let slab = Slab::new(&bytes).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::LeafCountMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn a_leaf_without_a_price_is_rejected() {
+        let bytes = slab_bytes(0, 1, &[leaf(ask_key(0, 1))]);
+        let slab = Slab::new(&bytes).unwrap();
+        assert_eq!(
+            slab.get_orders(1, 1, true).err(),
+            Some(SlabTreeError::InvalidPrice(ask_key(0, 1)))
+        );
+        assert_eq!(
+            slab.get_orders(1, 0, true).err(),
+            Some(SlabTreeError::InvalidLotSize)
+        );
+    }
+
+    #[test]
+    fn random_trees_decode_in_the_same_order_as_sorting_their_keys() {
+        let mut rng = StdRng::seed_from_u64(32);
+        for _ in 0..500 {
+            let len = rng.gen_range(1, 64);
+            // few distinct prices so price levels hold more than one order
+            let mut keys: Vec<u128> = (0..len)
+                .map(|_| ask_key(rng.gen_range(1, 16), rng.gen()))
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+
+            let mut nodes = Vec::new();
+            let root = build_tree(&keys, &mut nodes);
+            let bytes = slab_bytes(root, keys.len() as u64, &nodes);
+            let slab = Slab::new(&bytes).unwrap();
+
+            let asks = slab.get_orders(1, 1, true).unwrap();
+            assert_eq!(order_ids(&asks), keys);
+
+            let bids = slab.get_orders(1, 1, false).unwrap();
+            let mut reversed = keys.clone();
+            reversed.reverse();
+            assert_eq!(order_ids(&bids), reversed);
+
+            let depth = rng.gen_range(0, keys.len() + 1);
+            let top = slab.get_depth(depth as u64, 1, 1, true).unwrap();
+            assert_eq!(order_ids(&top), keys[..depth]);
+
+            for order in asks {
+                assert_eq!(u128::from(order.price_lots), order.order_id >> 64);
             }
         }
-        Some(res)
     }
 }
@@ -1,12 +1,15 @@
 use {
-    crate::accounts_cache::{AccountState, AccountsCache},
+    crate::{
+        accounts_cache::{AccountState, AccountsCache},
+        rpc::RpcBackend,
+    },
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
-    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
+    solana_sdk::pubkey::Pubkey,
     std::{sync::Arc, time::Duration},
     tokio::{
         sync::{
             broadcast::{channel, Receiver},
-            Mutex,
+            Mutex, RwLock,
         },
         time::sleep,
     },
@@ -14,8 +17,8 @@ use {
 
 pub struct AccountInfoService {
     cache: Arc<AccountsCache>,
-    client: Arc<RpcClient>,
-    keys: Vec<Pubkey>,
+    client: Arc<dyn RpcBackend>,
+    keys: RwLock<Vec<Pubkey>>,
     shutdown_receiver: Mutex<Receiver<bool>>,
 }
 
@@ -24,21 +27,21 @@ impl AccountInfoService {
         Self {
             cache: Arc::new(AccountsCache::default()),
             client: Arc::new(RpcClient::new("http://localhost:8899".to_string())),
-            keys: Vec::new(),
+            keys: RwLock::new(Vec::new()),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
         }
     }
 
     pub fn new(
         cache: Arc<AccountsCache>,
-        client: Arc<RpcClient>,
+        client: Arc<dyn RpcBackend>,
         keys: &[Pubkey],
         shutdown_receiver: Receiver<bool>,
     ) -> AccountInfoService {
         AccountInfoService {
             cache,
             client,
-            keys: Vec::from(keys),
+            keys: RwLock::new(Vec::from(keys)),
             shutdown_receiver: Mutex::new(shutdown_receiver),
         }
     }
@@ -46,11 +49,9 @@ impl AccountInfoService {
     pub async fn start_service(self: &Arc<Self>) {
         let rpc_cloned_self = self.clone();
 
-        for i in (0..self.keys.len()).step_by(100) {
-            rpc_cloned_self
-                .update_infos(i, self.keys.len().min(i + 100))
-                .await
-                .unwrap();
+        let keys = self.keys.read().await.clone();
+        for chunk in keys.chunks(100) {
+            rpc_cloned_self.update_infos(chunk).await.unwrap();
         }
 
         let cself = Arc::clone(&rpc_cloned_self);
@@ -63,23 +64,29 @@ impl AccountInfoService {
         }
     }
 
+    /// Starts polling the given accounts along with the ones the service was created with,
+    /// fetching them right away rather than on the next poll.
+    pub async fn add_keys(self: &Arc<Self>, keys: &[Pubkey]) -> Result<(), ClientError> {
+        self.keys.write().await.extend_from_slice(keys);
+
+        for chunk in keys.chunks(100) {
+            self.update_infos(chunk).await?;
+        }
+
+        Ok(())
+    }
+
     #[inline(always)]
-    async fn update_infos(self: &Arc<Self>, from: usize, to: usize) -> Result<(), ClientError> {
-        let account_keys = &self.keys[from..to];
-        let rpc_result = self
-            .client
-            .get_multiple_accounts_with_commitment(account_keys, CommitmentConfig::confirmed())
-            .await;
-
-        let res = match rpc_result {
+    async fn update_infos(self: &Arc<Self>, account_keys: &[Pubkey]) -> Result<(), ClientError> {
+        let rpc_result = self.client.get_multiple_accounts(account_keys).await;
+
+        let (slot, mut infos) = match rpc_result {
             Ok(r) => r,
             Err(e) => {
                 return Err(e);
             }
         };
 
-        let mut infos = res.value;
-
         while !infos.is_empty() {
             let next = infos.pop().unwrap();
             let i = infos.len();
@@ -96,7 +103,7 @@ impl AccountInfoService {
                 key,
                 AccountState {
                     account: println,
-                    slot: res.context.slot,
+                    slot,
                 },
             );
         }
@@ -109,8 +116,9 @@ impl AccountInfoService {
         loop {
             let aself = self.clone();
 
-            for i in (0..self.keys.len()).step_by(100) {
-                _ = aself.update_infos(i, self.keys.len().min(i + 100)).await;
+            let keys = self.keys.read().await.clone();
+            for chunk in keys.chunks(100) {
+                _ = aself.update_infos(chunk).await;
             }
 
             sleep(Duration::from_millis(500)).await;
@@ -0,0 +1,301 @@
+use {
+    crate::{accounts_cache::AccountsCache, CypherInteractiveError},
+    chrono::Utc,
+    solana_sdk::{account::Account, pubkey::Pubkey},
+    std::{
+        collections::{HashMap, HashSet},
+        fs::{create_dir_all, File, OpenOptions},
+        io::{self, BufReader, BufWriter, Read, Write},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::sync::{
+        broadcast::{channel, Receiver},
+        Mutex, RwLock,
+    },
+};
+
+/// Written at the start of every recording so we never try to replay an unrelated file.
+pub const RECORDING_MAGIC: &[u8; 8] = b"CYIREC01";
+
+/// A single account update as it was inserted into the accounts cache.
+///
+/// Records are stored back to back in little endian as
+/// `pubkey | slot | timestamp | lamports | owner | executable | rent_epoch | data_len | data`.
+#[derive(Debug, Clone)]
+pub struct AccountRecord {
+    pub pubkey: Pubkey,
+    pub slot: u64,
+    /// Unix timestamp in milliseconds at which the update was seen.
+    pub timestamp: i64,
+    pub account: Account,
+}
+
+impl AccountRecord {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.pubkey.as_ref())?;
+        writer.write_all(&self.slot.to_le_bytes())?;
+        writer.write_all(&self.timestamp.to_le_bytes())?;
+        writer.write_all(&self.account.lamports.to_le_bytes())?;
+        writer.write_all(self.account.owner.as_ref())?;
+        writer.write_all(&[self.account.executable as u8])?;
+        writer.write_all(&self.account.rent_epoch.to_le_bytes())?;
+        writer.write_all(&(self.account.data.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.account.data)
+    }
+
+    /// Reads the next record, returning `None` once the end of the recording is reached.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut pubkey = [0_u8; 32];
+        match reader.read(&mut pubkey[..1]) {
+            Ok(0) => {
+                return Ok(None);
+            }
+            Ok(_) => (),
+            Err(e) => {
+                return Err(e);
+            }
+        };
+        reader.read_exact(&mut pubkey[1..])?;
+
+        let slot = u64::from_le_bytes(read_array(reader)?);
+        let timestamp = i64::from_le_bytes(read_array(reader)?);
+        let lamports = u64::from_le_bytes(read_array(reader)?);
+        let owner: [u8; 32] = read_array(reader)?;
+        let executable: [u8; 1] = read_array(reader)?;
+        let rent_epoch = u64::from_le_bytes(read_array(reader)?);
+        let data_len = u32::from_le_bytes(read_array(reader)?);
+        let mut data = vec![0_u8; data_len as usize];
+        reader.read_exact(&mut data)?;
+
+        Ok(Some(Self {
+            pubkey: Pubkey::new_from_array(pubkey),
+            slot,
+            timestamp,
+            account: Account {
+                lamports,
+                data,
+                owner: Pubkey::new_from_array(owner),
+                executable: executable[0] != 0,
+                rent_epoch,
+            },
+        }))
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0_u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads every record in the recording at the given path.
+///
+/// A record cut short at the end of the file, e.g. because the recorder was killed mid-write, ends the recording.
+pub fn read_recording(path: &Path) -> Result<Vec<AccountRecord>, CypherInteractiveError> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => {
+            return Err(CypherInteractiveError::RecordingRead);
+        }
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0_u8; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != RECORDING_MAGIC {
+        return Err(CypherInteractiveError::RecordingRead);
+    }
+
+    let mut records: Vec<AccountRecord> = Vec::new();
+    loop {
+        match AccountRecord::read_from(&mut reader) {
+            Ok(Some(r)) => records.push(r),
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!(
+                    "[ARPS] Recording ends with a partial record, ignoring it. Read {} records.",
+                    records.len()
+                );
+                break;
+            }
+            Err(_) => {
+                return Err(CypherInteractiveError::RecordingRead);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Empties the recording at the given path, done once when a session starts so it does not
+/// carry on the recording of a previous session.
+pub fn reset_recording(path: &Path) -> Result<(), CypherInteractiveError> {
+    if let Some(dir) = path.parent() {
+        if create_dir_all(dir).is_err() {
+            return Err(CypherInteractiveError::RecordingWrite);
+        }
+    }
+    match File::create(path) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(CypherInteractiveError::RecordingWrite),
+    }
+}
+
+/// Writes every update to the given accounts seen by the accounts cache to an append-only log,
+/// which can later be fed back into the cache by the [`super::AccountReplayService`].
+pub struct AccountRecorderService {
+    cache: Arc<AccountsCache>,
+    receiver: Mutex<Receiver<Pubkey>>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+    keys: RwLock<HashSet<Pubkey>>,
+    path: PathBuf,
+    last_data: Mutex<HashMap<Pubkey, Vec<u8>>>,
+}
+
+impl AccountRecorderService {
+    pub fn default() -> Self {
+        Self {
+            cache: Arc::new(AccountsCache::default()),
+            receiver: Mutex::new(channel::<Pubkey>(u16::MAX as usize).1),
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+            keys: RwLock::new(HashSet::new()),
+            path: PathBuf::new(),
+            last_data: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn new(
+        cache: Arc<AccountsCache>,
+        receiver: Receiver<Pubkey>,
+        shutdown_receiver: Receiver<bool>,
+        keys: &[Pubkey],
+        path: &Path,
+    ) -> Self {
+        Self {
+            cache,
+            receiver: Mutex::new(receiver),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+            keys: RwLock::new(keys.iter().copied().collect()),
+            path: path.to_path_buf(),
+            last_data: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        let mut writer = match self.open() {
+            Ok(w) => w,
+            Err(e) => {
+                println!(
+                    "[ARS] Failed to open recording at {}: {:?}",
+                    self.path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        println!("[ARS] Recording account updates to {}", self.path.display());
+
+        let mut receiver = self.receiver.lock().await;
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let mut shutdown_signal: bool = false;
+
+        loop {
+            tokio::select! {
+                key = receiver.recv() => {
+                    if key.is_err() {
+                        continue;
+                    } else {
+                        match self.record(&mut writer, key.unwrap()).await {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("[ARS] Failed to record account update: {:?}", e);
+                            }
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    shutdown_signal = true;
+                }
+            }
+
+            if shutdown_signal {
+                println!("[ARS] Received shutdown signal, stopping.");
+                break;
+            }
+        }
+    }
+
+    /// Starts recording the given accounts along with the ones the service was created with.
+    pub async fn add_keys(&self, keys: &[Pubkey]) {
+        self.keys.write().await.extend(keys.iter().copied());
+    }
+
+    /// Opens the recording for appending, so restarting the services within a session keeps what was recorded so far.
+    fn open(&self) -> Result<BufWriter<File>, CypherInteractiveError> {
+        if let Some(dir) = self.path.parent() {
+            if create_dir_all(dir).is_err() {
+                return Err(CypherInteractiveError::RecordingWrite);
+            }
+        }
+        let file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+        {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(CypherInteractiveError::RecordingWrite);
+            }
+        };
+        let is_new = match file.metadata() {
+            Ok(m) => m.len() == 0,
+            Err(_) => {
+                return Err(CypherInteractiveError::RecordingWrite);
+            }
+        };
+
+        let mut writer = BufWriter::new(file);
+        if is_new && writer.write_all(RECORDING_MAGIC).is_err() {
+            return Err(CypherInteractiveError::RecordingWrite);
+        }
+
+        Ok(writer)
+    }
+
+    async fn record(
+        &self,
+        writer: &mut BufWriter<File>,
+        key: Pubkey,
+    ) -> Result<(), CypherInteractiveError> {
+        if !self.keys.read().await.contains(&key) {
+            return Ok(());
+        }
+
+        let ai = match self.cache.get(&key) {
+            Some(ai) => ai,
+            None => {
+                return Ok(());
+            }
+        };
+        let record = AccountRecord {
+            pubkey: key,
+            slot: ai.slot,
+            timestamp: Utc::now().timestamp_millis(),
+            account: ai.account.clone(),
+        };
+        drop(ai);
+
+        // the account info service polls, so most updates carry the exact same data as the previous one
+        let mut last_data = self.last_data.lock().await;
+        if last_data.get(&key) == Some(&record.account.data) {
+            return Ok(());
+        }
+
+        if record.write_to(writer).is_err() || writer.flush().is_err() {
+            return Err(CypherInteractiveError::RecordingWrite);
+        }
+        last_data.insert(key, record.account.data);
+
+        Ok(())
+    }
+}
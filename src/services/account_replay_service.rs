@@ -0,0 +1,102 @@
+use {
+    super::AccountRecord,
+    crate::accounts_cache::{AccountState, AccountsCache},
+    solana_sdk::{account::Account, pubkey::Pubkey},
+    std::{path::PathBuf, sync::Arc},
+    tokio::{
+        sync::{
+            broadcast::{channel, Receiver},
+            Mutex,
+        },
+        task::yield_now,
+        time::{sleep, Duration},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    pub path: PathBuf,
+    /// How fast the recording is played back relative to how it was recorded, zero replays it as fast as possible.
+    pub speed: f64,
+}
+
+/// Feeds a recording made by the [`super::AccountRecorderService`] back into the accounts cache,
+/// taking the place of the account info service so everything downstream runs without a network.
+pub struct AccountReplayService {
+    cache: Arc<AccountsCache>,
+    records: Vec<AccountRecord>,
+    speed: f64,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl AccountReplayService {
+    pub fn default() -> Self {
+        Self {
+            cache: Arc::new(AccountsCache::default()),
+            records: Vec::new(),
+            speed: 1.0,
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+        }
+    }
+
+    pub fn new(
+        cache: Arc<AccountsCache>,
+        records: Vec<AccountRecord>,
+        speed: f64,
+        shutdown_receiver: Receiver<bool>,
+    ) -> Self {
+        Self {
+            cache,
+            records,
+            speed,
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    /// The first recorded state of the given account.
+    pub fn get_account(&self, key: &Pubkey) -> Option<&Account> {
+        self.records
+            .iter()
+            .find(|r| r.pubkey == *key)
+            .map(|r| &r.account)
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        let mut shutdown = self.shutdown_receiver.lock().await;
+
+        tokio::select! {
+            _ = self.replay() => {},
+            _ = shutdown.recv() => {
+                println!("[ARPS] Received shutdown signal, stopping.");
+            }
+        }
+    }
+
+    async fn replay(self: &Arc<Self>) {
+        println!("[ARPS] Replaying {} account updates.", self.records.len());
+        let mut last_timestamp: Option<i64> = None;
+
+        for record in &self.records {
+            if self.speed > 0.0 {
+                if let Some(last) = last_timestamp {
+                    let delay = (record.timestamp - last).max(0) as f64 / self.speed;
+                    sleep(Duration::from_millis(delay as u64)).await;
+                }
+            } else {
+                // let the providers keep up so they don't lag behind the cache's channel
+                yield_now().await;
+            }
+            last_timestamp = Some(record.timestamp);
+
+            _ = self.cache.insert(
+                record.pubkey,
+                AccountState {
+                    account: record.account.clone(),
+                    slot: record.slot,
+                },
+            );
+        }
+
+        println!("[ARPS] Replay finished.");
+    }
+}
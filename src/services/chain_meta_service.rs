@@ -1,6 +1,6 @@
 use {
+    crate::rpc::RpcBackend,
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
-    solana_sdk::commitment_config::CommitmentConfig,
     solana_sdk::hash::Hash,
     std::sync::Arc,
     tokio::sync::broadcast::{channel, Receiver},
@@ -11,7 +11,7 @@ use {
     },
 };
 pub struct ChainMetaService {
-    client: Arc<RpcClient>,
+    client: Arc<dyn RpcBackend>,
     recent_blockhash: RwLock<Hash>,
     slot: RwLock<u64>,
     shutdown_receiver: Mutex<Receiver<bool>>,
@@ -27,7 +27,7 @@ impl ChainMetaService {
         }
     }
 
-    pub fn new(client: Arc<RpcClient>, shutdown_receiver: Receiver<bool>) -> ChainMetaService {
+    pub fn new(client: Arc<dyn RpcBackend>, shutdown_receiver: Receiver<bool>) -> ChainMetaService {
         ChainMetaService {
             client,
             shutdown_receiver: Mutex::new(shutdown_receiver),
@@ -37,17 +37,14 @@ impl ChainMetaService {
 
     #[inline(always)]
     async fn update_chain_meta(self: &Arc<Self>) -> Result<(), ClientError> {
-        let hash_res = self
-            .client
-            .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
-            .await;
+        let hash_res = self.client.get_latest_blockhash().await;
         let hash = match hash_res {
             Ok(hash) => hash,
             Err(e) => {
                 return Err(e);
             }
         };
-        *self.recent_blockhash.write().await = hash.0;
+        *self.recent_blockhash.write().await = hash;
 
         let slot_res = self.client.get_slot().await;
         let slot = match slot_res {
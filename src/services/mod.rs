@@ -1,5 +1,9 @@
 pub mod account_info_service;
+pub mod account_recorder_service;
+pub mod account_replay_service;
 pub mod chain_meta_service;
 
 pub use account_info_service::*;
+pub use account_recorder_service::*;
+pub use account_replay_service::*;
 pub use chain_meta_service::*;
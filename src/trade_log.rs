@@ -0,0 +1,233 @@
+use {
+    crate::{providers::Fill, CypherInteractiveError},
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::{create_dir_all, File, OpenOptions},
+        io::{self, BufRead, BufReader, Write},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::sync::{
+        broadcast::{channel, Receiver},
+        Mutex,
+    },
+};
+
+pub const TRADE_LOG_DIR: &str = "./logs";
+const TRADE_LOG_CSV: &str = "trades.csv";
+const TRADE_LOG_JSONL: &str = "trades.jsonl";
+const TRADE_LOG_CSV_HEADER: &str = "timestamp,slot,market,side,maker,price,quantity,native_base_quantity,native_quote_quantity,native_fee,order_id,client_order_id";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeRecord {
+    pub timestamp: String,
+    pub slot: u64,
+    pub market: String,
+    pub side: String,
+    pub maker: bool,
+    pub price: f64,
+    pub quantity: f64,
+    pub native_base_quantity: u64,
+    pub native_quote_quantity: u64,
+    pub native_fee: i64,
+    pub order_id: String,
+    pub client_order_id: u64,
+}
+
+impl From<&Fill> for TradeRecord {
+    fn from(fill: &Fill) -> Self {
+        Self {
+            timestamp: fill.timestamp.to_string(),
+            slot: fill.slot,
+            market: fill.market.to_string(),
+            side: format!("{:?}", fill.side),
+            maker: fill.maker,
+            price: fill.price,
+            quantity: fill.quantity,
+            native_base_quantity: fill.native_base_quantity,
+            native_quote_quantity: fill.native_quote_quantity,
+            native_fee: fill.native_fee,
+            order_id: fill.order_id.to_string(),
+            client_order_id: fill.client_order_id,
+        }
+    }
+}
+
+impl TradeRecord {
+    fn to_csv_line(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.timestamp,
+            self.slot,
+            self.market,
+            self.side,
+            self.maker,
+            self.price,
+            self.quantity,
+            self.native_base_quantity,
+            self.native_quote_quantity,
+            self.native_fee,
+            self.order_id,
+            self.client_order_id
+        )
+    }
+}
+
+pub struct TradeLog {
+    receiver: Mutex<Receiver<Fill>>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+    dir: PathBuf,
+}
+
+impl TradeLog {
+    pub fn default() -> Self {
+        Self {
+            receiver: Mutex::new(channel::<Fill>(u16::MAX as usize).1),
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+            dir: PathBuf::from(TRADE_LOG_DIR),
+        }
+    }
+
+    pub fn new(receiver: Receiver<Fill>, shutdown_receiver: Receiver<bool>, dir: &Path) -> Self {
+        Self {
+            receiver: Mutex::new(receiver),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let mut receiver = self.receiver.lock().await;
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let mut shutdown_signal: bool = false;
+
+        loop {
+            tokio::select! {
+                fill = receiver.recv() => {
+                    if fill.is_ok() {
+                        let fill = fill.unwrap();
+                        notify_fill(&fill);
+                        match self.append(&TradeRecord::from(&fill)) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("[TL] Failed to write fill to the trade log: {:?}", e);
+                            }
+                        }
+                    }
+                },
+                _ = shutdown.recv() => {
+                    shutdown_signal = true;
+                }
+            }
+
+            if shutdown_signal {
+                println!("[TL] Received shutdown signal, stopping.");
+                break;
+            }
+        }
+    }
+
+    fn append(&self, record: &TradeRecord) -> Result<(), CypherInteractiveError> {
+        if create_dir_all(&self.dir).is_err() {
+            return Err(CypherInteractiveError::TradeLogWrite);
+        }
+
+        let csv_path = self.dir.join(TRADE_LOG_CSV);
+        let write_header = !csv_path.exists();
+        let mut csv_file = match open_for_append(&csv_path) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(CypherInteractiveError::TradeLogWrite);
+            }
+        };
+        if write_header && writeln!(csv_file, "{}", TRADE_LOG_CSV_HEADER).is_err() {
+            return Err(CypherInteractiveError::TradeLogWrite);
+        }
+        if writeln!(csv_file, "{}", record.to_csv_line()).is_err() {
+            return Err(CypherInteractiveError::TradeLogWrite);
+        }
+
+        let json = match serde_json::to_string(record) {
+            Ok(j) => j,
+            Err(_) => {
+                return Err(CypherInteractiveError::TradeLogWrite);
+            }
+        };
+        let mut jsonl_file = match open_for_append(&self.dir.join(TRADE_LOG_JSONL)) {
+            Ok(f) => f,
+            Err(_) => {
+                return Err(CypherInteractiveError::TradeLogWrite);
+            }
+        };
+        if writeln!(jsonl_file, "{}", json).is_err() {
+            return Err(CypherInteractiveError::TradeLogWrite);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the last `count` trades from the log, optionally only for the given market.
+    pub fn read(
+        &self,
+        market: Option<&str>,
+        count: usize,
+    ) -> Result<Vec<TradeRecord>, CypherInteractiveError> {
+        let file = match File::open(self.dir.join(TRADE_LOG_JSONL)) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new());
+            }
+            Err(_) => {
+                return Err(CypherInteractiveError::TradeLogRead);
+            }
+        };
+
+        let mut records: Vec<TradeRecord> = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => {
+                    return Err(CypherInteractiveError::TradeLogRead);
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let record: TradeRecord = match serde_json::from_str(&line) {
+                Ok(r) => r,
+                Err(_) => {
+                    return Err(CypherInteractiveError::TradeLogRead);
+                }
+            };
+            if let Some(m) = market {
+                if m != record.market {
+                    continue;
+                }
+            }
+            records.push(record);
+        }
+
+        let skip = records.len().saturating_sub(count);
+        Ok(records.split_off(skip))
+    }
+}
+
+fn open_for_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn notify_fill(fill: &Fill) {
+    println!(
+        "\n[FILL] {} {:?} {} @ {} ({}) - Order ID: {} - Client ID: {}",
+        fill.market,
+        fill.side,
+        fill.quantity,
+        fill.price,
+        if fill.maker { "maker" } else { "taker" },
+        fill.order_id,
+        fill.client_order_id
+    );
+    print!(">");
+    _ = io::stdout().flush();
+}
@@ -11,19 +11,34 @@ use cypher::{
 use faucet::request_airdrop_ix;
 use serum_dex::state::{MarketStateV2, OpenOrders};
 use solana_account_decoder::parse_token::UiTokenAmount;
-use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient};
-use solana_sdk::{
-    commitment_config::CommitmentConfig,
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
-    signer::Signer,
-};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::Signer};
 use spl_associated_token_account::instruction::create_associated_token_account;
 use std::sync::Arc;
 
-use crate::{fast_tx_builder::FastTxnBuilder, CypherInteractiveError};
+use crate::{
+    config::Cluster, fast_tx_builder::FastTxnBuilder, rpc::RpcBackend, CypherInteractiveError,
+};
+
+use super::{get_close_open_orders_ix, get_deposit_collateral_ix, get_init_open_orders_ix};
+
+/// The number of numbered sub-accounts we look for when deriving an owner's cypher user accounts.
+pub const MAX_SUB_ACCOUNTS: u64 = 16;
+
+/// Derives the addresses of the owner's default cypher user account and all of its numbered sub-accounts.
+pub fn derive_cypher_user_addresses(cypher_group_pubkey: &Pubkey, owner: &Pubkey) -> Vec<Pubkey> {
+    let mut addresses = vec![derive_cypher_user_address(cypher_group_pubkey, owner).0];
 
-use super::{get_deposit_collateral_ix, get_init_open_orders_ix};
+    for account_number in 0..MAX_SUB_ACCOUNTS {
+        let address =
+            derive_cypher_user_address_with_number(cypher_group_pubkey, owner, account_number).0;
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    addresses
+}
 
 pub fn derive_quote_token_address(wallet_address: Pubkey) -> Pubkey {
     Pubkey::find_program_address(
@@ -38,15 +53,13 @@ pub fn derive_quote_token_address(wallet_address: Pubkey) -> Pubkey {
 }
 
 pub async fn get_token_account(
-    client: Arc<RpcClient>,
+    client: Arc<dyn RpcBackend>,
     token_account: &Pubkey,
 ) -> Result<UiTokenAmount, ClientError> {
-    let ta_res = client
-        .get_token_account_balance_with_commitment(token_account, CommitmentConfig::confirmed())
-        .await;
+    let ta_res = client.get_token_account_balance(token_account).await;
 
     let ta = match ta_res {
-        Ok(ta) => ta.value,
+        Ok(ta) => ta,
         Err(e) => {
             return Err(e);
         }
@@ -56,15 +69,20 @@ pub async fn get_token_account(
 }
 
 pub async fn get_serum_market(
-    client: Arc<RpcClient>,
+    client: Arc<dyn RpcBackend>,
     market: Pubkey,
 ) -> Result<MarketStateV2, ClientError> {
-    let ai_res = client
-        .get_account_with_commitment(&market, CommitmentConfig::confirmed())
-        .await;
+    let ai_res = client.get_account(&market).await;
 
     let ai = match ai_res {
-        Ok(ai) => ai.value.unwrap(),
+        Ok(Some(ai)) => ai,
+        Ok(None) => {
+            return Err(ClientErrorKind::Custom(format!(
+                "Serum market {} does not exist.",
+                market
+            ))
+            .into());
+        }
         Err(e) => {
             println!("There was an error while fetching the serum market: {}", e);
             return Err(e);
@@ -77,11 +95,11 @@ pub async fn get_serum_market(
 }
 
 pub async fn get_or_init_cypher_user(
-    owner: &Keypair,
+    owner: &dyn Signer,
     cypher_group_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
-    rpc_client: Arc<RpcClient>,
-    cluster: String,
+    rpc_client: Arc<dyn RpcBackend>,
+    cluster: &Cluster,
 ) -> Result<Box<CypherUser>, CypherInteractiveError> {
     let account_state = fetch_cypher_user(cypher_user_pubkey, Arc::clone(&rpc_client)).await;
 
@@ -94,7 +112,10 @@ pub async fn get_or_init_cypher_user(
 
         match res {
             Ok(s) => {
-                println!("Successfully created cypher user account: https://explorer.solana.com/tx/{}?cluster={}", s, cluster);
+                println!(
+                    "Successfully created cypher user account: {}",
+                    cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 return Err(e);
@@ -111,11 +132,9 @@ pub async fn get_or_init_cypher_user(
 
 async fn fetch_cypher_user(
     cypher_user_pubkey: &Pubkey,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<Box<CypherUser>, CypherInteractiveError> {
-    let res = rpc_client
-        .get_account_with_commitment(cypher_user_pubkey, CommitmentConfig::confirmed())
-        .await;
+    let res = rpc_client.get_account(cypher_user_pubkey).await;
 
     if res.is_err() {
         return Err(CypherInteractiveError::CouldNotFetchCypherUser(
@@ -123,7 +142,7 @@ async fn fetch_cypher_user(
         ));
     }
 
-    let maybe_account = res.unwrap().value;
+    let maybe_account = res.unwrap();
 
     if maybe_account.is_some() {
         let account_state = get_zero_copy_account::<CypherUser>(&maybe_account.unwrap());
@@ -135,8 +154,8 @@ async fn fetch_cypher_user(
 
 pub async fn init_cypher_user(
     group_address: &Pubkey,
-    owner: &Keypair,
-    rpc: Arc<RpcClient>,
+    owner: &dyn Signer,
+    rpc: Arc<dyn RpcBackend>,
 ) -> Result<Signature, CypherInteractiveError> {
     let (address, bump) = derive_cypher_user_address(group_address, &owner.pubkey());
     let ix = init_cypher_user_ix(group_address, &address, &owner.pubkey(), bump);
@@ -149,7 +168,12 @@ pub async fn init_cypher_user(
             return Err(CypherInteractiveError::CouldNotCreateCypherUser(e));
         }
     };
-    let tx = builder.build(hash, owner, None);
+    let tx = match builder.build(hash, owner, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(CypherInteractiveError::CouldNotCreateCypherUser(e.into()));
+        }
+    };
     let tx_res = rpc.send_and_confirm_transaction_with_spinner(&tx).await;
     let sig = match tx_res {
         Ok(s) => s,
@@ -161,13 +185,13 @@ pub async fn init_cypher_user(
 }
 
 pub async fn get_or_init_open_orders(
-    owner: &Keypair,
+    owner: &dyn Signer,
     cypher_group_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
     cypher_market: &Pubkey,
     open_orders: &Pubkey,
-    rpc_client: Arc<RpcClient>,
-    cluster: String,
+    rpc_client: Arc<dyn RpcBackend>,
+    cluster: &Cluster,
 ) -> Result<OpenOrders, CypherInteractiveError> {
     let account_state = fetch_open_orders(open_orders, Arc::clone(&rpc_client)).await;
 
@@ -193,7 +217,10 @@ pub async fn get_or_init_open_orders(
 
         match res {
             Ok(s) => {
-                println!("Successfully created open orders account: https://explorer.solana.com/tx/{}?cluster={}", s, cluster);
+                println!(
+                    "Successfully created open orders account: {}",
+                    cluster.get_explorer_tx_url(&s)
+                );
             }
             Err(e) => {
                 return Err(e);
@@ -208,11 +235,9 @@ pub async fn get_or_init_open_orders(
 
 async fn fetch_open_orders(
     open_orders: &Pubkey,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<OpenOrders, CypherInteractiveError> {
-    let res = rpc_client
-        .get_account_with_commitment(open_orders, CommitmentConfig::confirmed())
-        .await;
+    let res = rpc_client.get_account(open_orders).await;
 
     if res.is_err() {
         return Err(CypherInteractiveError::CouldNotFetchOpenOrders(
@@ -220,7 +245,7 @@ async fn fetch_open_orders(
         ));
     }
 
-    let maybe_account = res.unwrap().value;
+    let maybe_account = res.unwrap();
 
     if maybe_account.is_some() {
         let ooa: OpenOrders = parse_dex_account(maybe_account.unwrap().data);
@@ -235,8 +260,8 @@ pub async fn init_open_orders(
     cypher_user_pubkey: &Pubkey,
     cypher_market: &Pubkey,
     open_orders: &Pubkey,
-    signer: &Keypair,
-    rpc_client: Arc<RpcClient>,
+    signer: &dyn Signer,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<Signature, CypherInteractiveError> {
     let ix = get_init_open_orders_ix(
         cypher_group_pubkey,
@@ -250,7 +275,12 @@ pub async fn init_open_orders(
     builder.add(ix);
 
     let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, signer, None);
+    let tx = match builder.build(hash, signer, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(CypherInteractiveError::CouldNotCreateOpenOrders(e.into()));
+        }
+    };
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -260,9 +290,50 @@ pub async fn init_open_orders(
     }
 }
 
+/// Closes the open orders account, the rent goes back to the signer.
+pub async fn close_open_orders(
+    cypher_group_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    cypher_market: &Pubkey,
+    open_orders: &Pubkey,
+    signer: &dyn Signer,
+    rpc_client: Arc<dyn RpcBackend>,
+) -> Result<Signature, CypherInteractiveError> {
+    let ix = get_close_open_orders_ix(
+        cypher_group_pubkey,
+        cypher_user_pubkey,
+        cypher_market,
+        open_orders,
+        &signer.pubkey(),
+    );
+
+    let mut builder = FastTxnBuilder::new();
+    builder.add(ix);
+
+    let hash = match rpc_client.get_latest_blockhash().await {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(CypherInteractiveError::CouldNotCloseOpenOrders(e));
+        }
+    };
+    let tx = match builder.build(hash, signer, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(CypherInteractiveError::CouldNotCloseOpenOrders(e.into()));
+        }
+    };
+    let res = rpc_client
+        .send_and_confirm_transaction_with_spinner(&tx)
+        .await;
+    match res {
+        Ok(s) => Ok(s),
+        Err(e) => Err(CypherInteractiveError::CouldNotCloseOpenOrders(e)),
+    }
+}
+
 pub async fn request_airdrop(
-    owner: &Keypair,
-    rpc_client: Arc<RpcClient>,
+    owner: &dyn Signer,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<Signature, CypherInteractiveError> {
     let token_account = derive_quote_token_address(owner.pubkey());
     let airdrop_ix = request_airdrop_ix(&token_account, 10_000_000_000);
@@ -288,7 +359,13 @@ pub async fn request_airdrop(
     builder.add(airdrop_ix);
 
     let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let tx = match builder.build(hash, owner, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            println!("There was an error requesting airdrop: {}", e);
+            return Err(CypherInteractiveError::Airdrop);
+        }
+    };
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -303,9 +380,9 @@ pub async fn request_airdrop(
 
 pub async fn create_cypher_user(
     cypher_group_pubkey: &Pubkey,
-    owner: &Keypair,
+    owner: &dyn Signer,
     account_number: u64,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<Signature, CypherInteractiveError> {
     let (cypher_user_pubkey, bump) = derive_cypher_user_address_with_number(
         cypher_group_pubkey,
@@ -325,7 +402,12 @@ pub async fn create_cypher_user(
     builder.add(create_cypher_user_ix);
 
     let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let tx = match builder.build(hash, owner, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(CypherInteractiveError::CouldNotCreateCypherUser(e.into()));
+        }
+    };
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await
@@ -344,8 +426,8 @@ pub async fn set_delegate(
     cypher_group_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
     delegate: &Pubkey,
-    owner: &Keypair,
-    rpc_client: Arc<RpcClient>,
+    owner: &dyn Signer,
+    rpc_client: Arc<dyn RpcBackend>,
 ) -> Result<Signature, CypherInteractiveError> {
     let delegate_ix = set_delegate_ix(
         cypher_group_pubkey,
@@ -358,7 +440,13 @@ pub async fn set_delegate(
     builder.add(delegate_ix);
 
     let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let tx = match builder.build(hash, owner, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            println!("There was an error setting account delegate: {}", e);
+            return Err(CypherInteractiveError::SetDelegate);
+        }
+    };
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -372,10 +460,10 @@ pub async fn set_delegate(
 }
 
 pub async fn deposit_quote_token(
-    owner: &Keypair,
+    owner: &dyn Signer,
     cypher_user_pubkey: &Pubkey,
     cypher_group: &CypherGroup,
-    rpc_client: Arc<RpcClient>,
+    rpc_client: Arc<dyn RpcBackend>,
     amount: u64,
 ) -> Result<Signature, CypherInteractiveError> {
     let source_ata = derive_quote_token_address(owner.pubkey());
@@ -391,7 +479,16 @@ pub async fn deposit_quote_token(
     let mut builder = FastTxnBuilder::new();
     builder.add(ix);
     let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let tx = match builder.build(hash, owner, None) {
+        Ok(tx) => tx,
+        Err(e) => {
+            println!(
+                "There was an error depositing funds into cypher account: {}",
+                e
+            );
+            return Err(CypherInteractiveError::Deposit);
+        }
+    };
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -1,5 +1,5 @@
 use cypher::{
-    client::{deposit_collateral_ix, init_open_orders_ix},
+    client::{close_open_orders_ix, deposit_collateral_ix, init_open_orders_ix},
     utils::derive_dex_market_authority,
 };
 use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
@@ -39,3 +39,21 @@ pub fn get_init_open_orders_ix(
         &market_authority,
     )
 }
+
+pub fn get_close_open_orders_ix(
+    cypher_group_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    cypher_market: &Pubkey,
+    open_orders: &Pubkey,
+    signer: &Pubkey,
+) -> Instruction {
+    let market_authority = derive_dex_market_authority(cypher_market);
+    close_open_orders_ix(
+        cypher_group_pubkey,
+        cypher_user_pubkey,
+        signer,
+        cypher_market,
+        open_orders,
+        &market_authority,
+    )
+}
@@ -0,0 +1,294 @@
+use cypher::{CypherGroup, CypherUser};
+use serum_dex::matching::Side;
+use std::str::FromStr;
+
+use super::number_to_f64;
+
+/// The collateral of a cypher account and the group's margin thresholds.
+///
+/// Values are in native quote units and prices in native quote units per native base unit,
+/// the same units as `CypherMarket::oracle_price`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MarginSummary {
+    pub assets_value: f64,
+    pub liabs_value: f64,
+    /// The c-ratio the account needs to open new positions or borrow.
+    pub init_ratio: f64,
+    /// The c-ratio below which the account can be liquidated.
+    pub maint_ratio: f64,
+}
+
+impl MarginSummary {
+    pub fn new(user: &CypherUser, group: &CypherGroup) -> Self {
+        let (_, assets_value, liabs_value) = user.get_margin_c_ratio_components(group);
+
+        Self {
+            assets_value: number_to_f64(assets_value),
+            liabs_value: number_to_f64(liabs_value),
+            init_ratio: number_to_f64(group.margin_init_ratio()),
+            maint_ratio: number_to_f64(group.margin_maint_ratio()),
+        }
+    }
+
+    pub fn c_ratio(&self) -> f64 {
+        if self.liabs_value == 0.0 {
+            return f64::INFINITY;
+        }
+        self.assets_value / self.liabs_value
+    }
+
+    /// The value of assets not needed to keep the account above the initial margin ratio.
+    pub fn free_collateral(&self) -> f64 {
+        (self.assets_value - self.liabs_value * self.init_ratio).max(0.0)
+    }
+
+    /// The most of a token with the given price that can be borrowed before dropping below the initial margin ratio.
+    ///
+    /// Borrowed tokens stay in the account, so each borrow adds the same value to both assets and liabilities.
+    pub fn max_borrowable(&self, price: f64) -> f64 {
+        if price <= 0.0 || self.init_ratio <= 1.0 {
+            return 0.0;
+        }
+        self.free_collateral() / ((self.init_ratio - 1.0) * price)
+    }
+
+    /// The price of a token at which the account drops to the maintenance margin ratio,
+    /// assuming every other price stays where it is.
+    ///
+    /// `deposits` and `borrows` are the account's native amounts of the token and `price` its current price.
+    /// Returns `None` if no positive price would make the account liquidatable.
+    pub fn liquidation_price(&self, deposits: f64, borrows: f64, price: f64) -> Option<f64> {
+        let other_assets = self.assets_value - deposits * price;
+        let other_liabs = self.liabs_value - borrows * price;
+
+        // solve (other_assets + deposits * p) / (other_liabs + borrows * p) = maint_ratio for p
+        let exposure = deposits - self.maint_ratio * borrows;
+        if exposure == 0.0 {
+            return None;
+        }
+        let liquidation_price = (self.maint_ratio * other_liabs - other_assets) / exposure;
+        if !liquidation_price.is_finite() || liquidation_price <= 0.0 {
+            return None;
+        }
+
+        Some(liquidation_price)
+    }
+}
+
+/// The native amounts of a token deposited and borrowed by an account.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokenBalance {
+    pub deposits: f64,
+    pub borrows: f64,
+}
+
+impl MarginSummary {
+    /// The account's margin after an order for `quantity` native base units at `price` fully fills.
+    ///
+    /// Tokens given up come out of deposits first and are borrowed beyond that, tokens received
+    /// repay borrows first and are deposited beyond that. Fees are not taken into account.
+    pub fn with_fill(
+        &self,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        oracle_price: f64,
+        base: TokenBalance,
+        quote: TokenBalance,
+    ) -> Self {
+        let mut projected = *self;
+        let quote_quantity = quantity * price;
+
+        match side {
+            Side::Bid => {
+                projected.give(quote_quantity, 1.0, quote);
+                projected.receive(quantity, oracle_price, base);
+            }
+            Side::Ask => {
+                projected.give(quantity, oracle_price, base);
+                projected.receive(quote_quantity, 1.0, quote);
+            }
+        }
+
+        projected
+    }
+
+    fn give(&mut self, amount: f64, price: f64, balance: TokenBalance) {
+        let from_deposits = amount.min(balance.deposits.max(0.0));
+        self.assets_value -= from_deposits * price;
+        self.liabs_value += (amount - from_deposits) * price;
+    }
+
+    fn receive(&mut self, amount: f64, price: f64, balance: TokenBalance) {
+        let repaid = amount.min(balance.borrows.max(0.0));
+        self.liabs_value -= repaid * price;
+        self.assets_value += (amount - repaid) * price;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginCheckMode {
+    /// Print a warning and submit the order anyway.
+    Warn,
+    /// Refuse to submit the order unless it is forced.
+    Block,
+}
+
+impl FromStr for MarginCheckMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(MarginCheckMode::Warn),
+            "block" => Ok(MarginCheckMode::Block),
+            _ => Err(format!(
+                "invalid margin check mode '{}', expected 'warn' or 'block'",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do before submitting an order that would leave the account below a minimum c-ratio once filled.
+#[derive(Debug, Clone, Copy)]
+pub struct MarginCheck {
+    /// The minimum projected c-ratio, the group's initial margin ratio is used when not set.
+    pub min_c_ratio: Option<f64>,
+    pub mode: MarginCheckMode,
+}
+
+impl Default for MarginCheck {
+    fn default() -> Self {
+        Self {
+            min_c_ratio: None,
+            mode: MarginCheckMode::Block,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(assets_value: f64, liabs_value: f64) -> MarginSummary {
+        MarginSummary {
+            assets_value,
+            liabs_value,
+            init_ratio: 1.2,
+            maint_ratio: 1.1,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn free_collateral_and_max_borrowable() {
+        let margin = summary(1_000.0, 400.0);
+        assert_close(margin.c_ratio(), 2.5);
+        assert_close(margin.free_collateral(), 520.0);
+        // borrowing 1300 at 2 adds 2600 to both sides, leaving 3600 / 3000 = 1.2
+        assert_close(margin.max_borrowable(2.0), 1_300.0);
+        assert_close(margin.max_borrowable(0.0), 0.0);
+    }
+
+    #[test]
+    fn without_borrows_everything_is_free() {
+        let margin = summary(1_000.0, 0.0);
+        assert_eq!(margin.c_ratio(), f64::INFINITY);
+        assert_close(margin.free_collateral(), 1_000.0);
+        assert_close(margin.max_borrowable(1.0), 5_000.0);
+
+        let no_leverage = MarginSummary {
+            init_ratio: 1.0,
+            ..margin
+        };
+        assert_close(no_leverage.max_borrowable(1.0), 0.0);
+    }
+
+    #[test]
+    fn below_the_initial_ratio_nothing_can_be_borrowed() {
+        let margin = summary(500.0, 480.0);
+        assert_close(margin.free_collateral(), 0.0);
+        assert_close(margin.max_borrowable(1.0), 0.0);
+    }
+
+    #[test]
+    fn liquidation_price_of_a_long() {
+        // 100 tokens at 5 plus 100 in other assets against 400 of liabilities
+        let margin = summary(600.0, 400.0);
+        assert_close(margin.liquidation_price(100.0, 0.0, 5.0).unwrap(), 3.4);
+
+        // with enough other assets no price makes the account liquidatable
+        let margin = summary(1_000.0, 400.0);
+        assert_eq!(margin.liquidation_price(100.0, 0.0, 5.0), None);
+    }
+
+    #[test]
+    fn liquidation_price_of_a_short() {
+        // 50 tokens borrowed at 4 against 1000 in assets, liquidated once 1000 / (50 * p) = 1.1
+        let margin = summary(1_000.0, 200.0);
+        assert_close(
+            margin.liquidation_price(0.0, 50.0, 4.0).unwrap(),
+            1_000.0 / 55.0,
+        );
+    }
+
+    #[test]
+    fn liquidation_price_without_a_position() {
+        let margin = summary(1_000.0, 400.0);
+        assert_eq!(margin.liquidation_price(0.0, 0.0, 5.0), None);
+    }
+
+    #[test]
+    fn liquidation_price_of_an_account_already_below_maintenance() {
+        let margin = summary(600.0, 600.0);
+        let liquidation_price = margin.liquidation_price(100.0, 0.0, 5.0).unwrap();
+        assert_close(liquidation_price, 5.6);
+        assert!(liquidation_price > 5.0);
+    }
+
+    #[test]
+    fn fills_use_deposits_before_borrowing() {
+        let margin = summary(1_000.0, 400.0);
+
+        // 50 quote given with only 30 deposited borrows 20, the 10 base received are worth 50
+        let bid = margin.with_fill(
+            Side::Bid,
+            10.0,
+            5.0,
+            5.0,
+            TokenBalance::default(),
+            TokenBalance {
+                deposits: 30.0,
+                borrows: 0.0,
+            },
+        );
+        assert_close(bid.assets_value, 1_020.0);
+        assert_close(bid.liabs_value, 420.0);
+
+        // 10 base given with 4 deposited borrows 6, the 60 quote received repay borrows
+        let ask = margin.with_fill(
+            Side::Ask,
+            10.0,
+            6.0,
+            5.0,
+            TokenBalance {
+                deposits: 4.0,
+                borrows: 0.0,
+            },
+            TokenBalance {
+                deposits: 0.0,
+                borrows: 100.0,
+            },
+        );
+        assert_close(ask.assets_value, 980.0);
+        assert_close(ask.liabs_value, 370.0);
+    }
+}
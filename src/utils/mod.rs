@@ -1,7 +1,13 @@
 pub mod accounts;
 pub mod instructions;
+pub mod margin;
 pub mod orders;
+pub mod prices;
+pub mod units;
 
 pub use accounts::*;
 pub use instructions::*;
+pub use margin::*;
 pub use orders::*;
+pub use prices::*;
+pub use units::*;
@@ -10,7 +10,7 @@ use serum_dex::{
     matching::Side,
     state::{MarketStateV2, OpenOrders},
 };
-use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signer::Signer};
 
 use crate::{providers::OrderBook, serum_slab::OrderBookOrder};
 
@@ -86,12 +86,7 @@ async fn get_order_book_line(
     if side == Side::Ask {
         for order in orderbook.asks.read().await.iter() {
             if order.client_order_id == client_order_id {
-                return Some(OrderBookOrder {
-                    order_id: order.order_id,
-                    price: order.price,
-                    quantity: order.quantity,
-                    client_order_id: order.client_order_id,
-                });
+                return Some(*order);
             }
         }
     }
@@ -99,12 +94,7 @@ async fn get_order_book_line(
     if side == Side::Bid {
         for order in orderbook.bids.read().await.iter() {
             if order.client_order_id == client_order_id {
-                return Some(OrderBookOrder {
-                    order_id: order.order_id,
-                    price: order.price,
-                    quantity: order.quantity,
-                    client_order_id: order.client_order_id,
-                });
+                return Some(*order);
             }
         }
     }
@@ -120,7 +110,7 @@ pub fn get_cancel_order_ix(
     dex_market_state: &MarketStateV2,
     open_orders_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn Signer,
     ix_data: CancelOrderInstructionV2,
 ) -> Instruction {
     let prune_authority = derive_dex_market_authority(&cypher_market.dex_market);
@@ -157,7 +147,7 @@ pub fn get_new_order_ix(
     dex_market_state: &MarketStateV2,
     open_orders_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn Signer,
     ix_data: NewOrderInstructionV3,
 ) -> Instruction {
     let vault_signer = gen_dex_vault_signer_key(
@@ -193,7 +183,7 @@ pub fn get_settle_funds_ix(
     dex_market_state: &MarketStateV2,
     cypher_user_pubkey: &Pubkey,
     open_orders_pubkey: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn Signer,
 ) -> Instruction {
     let vault_signer = gen_dex_vault_signer_key(
         dex_market_state.vault_signer_nonce,
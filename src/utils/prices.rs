@@ -0,0 +1,62 @@
+use jet_proto_math::Number;
+
+/// The number of decimals a `jet_proto_math::Number` keeps.
+const NUMBER_DECIMALS: i32 = 15;
+
+/// A number which can be converted into an `f64`, possibly losing precision.
+pub trait ToF64 {
+    fn to_f64(&self) -> f64;
+}
+
+impl ToF64 for Number {
+    fn to_f64(&self) -> f64 {
+        let [low, mid, high] = self.into_bits();
+        let bits = high as f64 * 2_f64.powi(128) + mid as f64 * 2_f64.powi(64) + low as f64;
+        bits / 10_f64.powi(NUMBER_DECIMALS)
+    }
+}
+
+impl ToF64 for u64 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl ToF64 for f64 {
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+
+/// Converts a number, such as a `jet_proto_math::Number`, into an `f64`.
+pub fn number_to_f64<T: ToF64>(number: T) -> f64 {
+    number.to_f64()
+}
+
+/// Converts a price in native quote units per native base unit into a price in ui units.
+pub fn native_price_to_ui<T: ToF64>(price: T, base_decimals: u8, quote_decimals: u8) -> f64 {
+    number_to_f64(price) * 10_f64.powi(base_decimals as i32) / 10_f64.powi(quote_decimals as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_keep_their_fraction() {
+        assert_eq!(number_to_f64(Number::ONE), 1.0);
+        assert_eq!(number_to_f64(Number::ZERO), 0.0);
+        let two: Number = 2_u64.into();
+        assert_eq!(number_to_f64(Number::ONE / two), 0.5);
+        // large enough to need more than the lowest 64 bits
+        let large: Number = 32_000_000_000_u64.into();
+        assert!((number_to_f64(large) - 32_000_000_000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn native_prices_are_scaled_by_the_decimals() {
+        // 0.032 native USDC per lamport is 32 USDC per SOL
+        assert!((native_price_to_ui(0.032, 9, 6) - 32.0).abs() < 1e-9);
+        assert_eq!(native_price_to_ui(32_u64, 9, 6), 32_000.0);
+    }
+}
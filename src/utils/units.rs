@@ -0,0 +1,213 @@
+use crate::CypherInteractiveError;
+
+/// Converts prices and quantities between ui units and the native and lot units used by the dex.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MarketUnits {
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+}
+
+impl MarketUnits {
+    pub fn new(
+        base_decimals: u8,
+        quote_decimals: u8,
+        coin_lot_size: u64,
+        pc_lot_size: u64,
+    ) -> Self {
+        Self {
+            base_decimals,
+            quote_decimals,
+            coin_lot_size,
+            pc_lot_size,
+        }
+    }
+
+    fn base_multiplier(&self) -> f64 {
+        10_f64.powi(self.base_decimals as i32)
+    }
+
+    fn quote_multiplier(&self) -> f64 {
+        10_f64.powi(self.quote_decimals as i32)
+    }
+
+    /// The minimum price increment in ui units.
+    pub fn tick_size(&self) -> f64 {
+        self.price_lots_to_ui(1)
+    }
+
+    /// The minimum quantity increment in ui units.
+    pub fn lot_size(&self) -> f64 {
+        self.native_base_to_ui(self.coin_lot_size)
+    }
+
+    pub fn price_lots_to_ui(&self, price_lots: u64) -> f64 {
+        if self.coin_lot_size == 0 {
+            return 0.0;
+        }
+        (price_lots as f64 * self.pc_lot_size as f64 * self.base_multiplier())
+            / (self.coin_lot_size as f64 * self.quote_multiplier())
+    }
+
+    pub fn native_base_to_ui(&self, quantity: u64) -> f64 {
+        quantity as f64 / self.base_multiplier()
+    }
+
+    pub fn native_quote_to_ui(&self, quantity: u64) -> f64 {
+        quantity as f64 / self.quote_multiplier()
+    }
+
+    /// Converts a ui price into price lots, rounding to the nearest tick.
+    pub fn ui_price_to_lots(&self, price: f64) -> Result<u64, CypherInteractiveError> {
+        if self.coin_lot_size == 0 || self.pc_lot_size == 0 {
+            return Err(CypherInteractiveError::InvalidMarketUnits);
+        }
+        if !price.is_finite() || price <= 0.0 {
+            return Err(CypherInteractiveError::InvalidPrice(price));
+        }
+
+        let price_lots = (price / self.tick_size()).round();
+        if price_lots < 1.0 {
+            return Err(CypherInteractiveError::PriceBelowTickSize(price));
+        }
+        if price_lots > u64::MAX as f64 {
+            return Err(CypherInteractiveError::InvalidPrice(price));
+        }
+
+        Ok(price_lots as u64)
+    }
+
+    /// Converts a ui quantity into base lots, rounding down to a whole lot so we never exceed the requested size.
+    pub fn ui_quantity_to_lots(&self, quantity: f64) -> Result<u64, CypherInteractiveError> {
+        if self.coin_lot_size == 0 {
+            return Err(CypherInteractiveError::InvalidMarketUnits);
+        }
+        if !quantity.is_finite() || quantity <= 0.0 {
+            return Err(CypherInteractiveError::InvalidQuantity(quantity));
+        }
+
+        // nudge by a small epsilon so that sizes which are exact multiples of the lot size survive float error
+        let quantity_lots = (quantity / self.lot_size() + 1e-9).floor();
+        if quantity_lots < 1.0 {
+            return Err(CypherInteractiveError::QuantityBelowLotSize(quantity));
+        }
+        if quantity_lots > u64::MAX as f64 {
+            return Err(CypherInteractiveError::InvalidQuantity(quantity));
+        }
+
+        Ok(quantity_lots as u64)
+    }
+
+    /// The maximum amount of native quote tokens needed for an order of the given size at the given price.
+    pub fn max_native_pc_qty(
+        &self,
+        price_lots: u64,
+        quantity_lots: u64,
+    ) -> Result<u64, CypherInteractiveError> {
+        match price_lots
+            .checked_mul(quantity_lots)
+            .and_then(|q| q.checked_mul(self.pc_lot_size))
+        {
+            Some(q) => Ok(q),
+            None => Err(CypherInteractiveError::InvalidQuantity(
+                self.native_base_to_ui(quantity_lots.saturating_mul(self.coin_lot_size)),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A SOL/USDC market with a tick size of 0.1 and a lot size of 0.001 SOL.
+    fn sol_usdc() -> MarketUnits {
+        MarketUnits::new(9, 6, 1_000_000, 100)
+    }
+
+    #[test]
+    fn tick_and_lot_sizes_follow_the_decimals() {
+        let units = sol_usdc();
+        assert!((units.tick_size() - 0.1).abs() < 1e-12);
+        assert!((units.lot_size() - 0.001).abs() < 1e-12);
+    }
+
+    #[test]
+    fn prices_round_trip_through_lots() {
+        let units = sol_usdc();
+        for price_lots in [1, 325, 1_000_000] {
+            let price = units.price_lots_to_ui(price_lots);
+            assert_eq!(units.ui_price_to_lots(price).unwrap(), price_lots);
+        }
+    }
+
+    #[test]
+    fn prices_round_to_the_nearest_tick() {
+        let units = sol_usdc();
+        assert_eq!(units.ui_price_to_lots(32.54).unwrap(), 325);
+        assert_eq!(units.ui_price_to_lots(32.56).unwrap(), 326);
+    }
+
+    #[test]
+    fn quantities_round_down_to_whole_lots() {
+        let units = sol_usdc();
+        assert_eq!(units.ui_quantity_to_lots(1.5).unwrap(), 1_500);
+        assert_eq!(units.ui_quantity_to_lots(0.0019).unwrap(), 1);
+        // 0.3 / 0.001 is slightly below 300 in floating point
+        assert_eq!(units.ui_quantity_to_lots(0.3).unwrap(), 300);
+        assert_eq!(units.native_base_to_ui(300 * units.coin_lot_size), 0.3);
+    }
+
+    #[test]
+    fn sizes_below_a_tick_or_lot_are_rejected() {
+        let units = sol_usdc();
+        assert!(matches!(
+            units.ui_price_to_lots(0.04),
+            Err(CypherInteractiveError::PriceBelowTickSize(_))
+        ));
+        assert!(matches!(
+            units.ui_quantity_to_lots(0.0005),
+            Err(CypherInteractiveError::QuantityBelowLotSize(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        let units = sol_usdc();
+        for price in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e30] {
+            assert!(matches!(
+                units.ui_price_to_lots(price),
+                Err(CypherInteractiveError::InvalidPrice(_))
+            ));
+        }
+        for quantity in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e30] {
+            assert!(matches!(
+                units.ui_quantity_to_lots(quantity),
+                Err(CypherInteractiveError::InvalidQuantity(_))
+            ));
+        }
+
+        let no_lots = MarketUnits::new(9, 6, 0, 0);
+        assert!(matches!(
+            no_lots.ui_price_to_lots(1.0),
+            Err(CypherInteractiveError::InvalidMarketUnits)
+        ));
+        assert!(matches!(
+            no_lots.ui_quantity_to_lots(1.0),
+            Err(CypherInteractiveError::InvalidMarketUnits)
+        ));
+        assert_eq!(no_lots.price_lots_to_ui(10), 0.0);
+    }
+
+    #[test]
+    fn max_native_pc_qty_checks_for_overflow() {
+        let units = sol_usdc();
+        // 1.5 SOL at 32.5 is 48.75 USDC
+        assert_eq!(units.max_native_pc_qty(325, 1_500).unwrap(), 48_750_000);
+        assert!(matches!(
+            units.max_native_pc_qty(u64::MAX, 2),
+            Err(CypherInteractiveError::InvalidQuantity(_))
+        ));
+    }
+}
@@ -0,0 +1,676 @@
+mod common;
+
+use {
+    common::{TestEnv, ASK_PRICE_LOTS, GROUP_NAME, MARKET_NAME},
+    cypher_interactive::{market_handler::MARKET_ORDER_SLIPPAGE_BPS, services::read_recording},
+    solana_sdk::pubkey::Pubkey,
+};
+
+#[test]
+fn help_lists_every_command() {
+    let env = TestEnv::new("help");
+    let output = env.run(&["help"]);
+
+    for command in [
+        ">>> new",
+        ">>> airdrop",
+        ">>> deposit",
+        ">>> delegate",
+        ">>> undelegate",
+        ">>> delegate status",
+        ">>> status",
+        ">>> markets",
+        ">>> tokens",
+        ">>> orderbook",
+        ">>> limit",
+        ">>> market",
+        ">>> cancel",
+        ">>> trades",
+        ">>> pnl",
+        ">>> panic",
+        ">>> unlock",
+        ">>> accounts",
+        ">>> use",
+        ">>> load",
+        ">>> close-oo",
+        ">>> sync-config",
+        ">>> exit",
+    ] {
+        assert!(
+            output.contains(command),
+            "missing {} in:\n{}",
+            command,
+            output
+        );
+    }
+}
+
+#[test]
+fn unknown_input_keeps_the_cli_running() {
+    let env = TestEnv::new("unknown");
+    let output = env.run(&["not-a-command", "cancel SOL not-a-number", "help"]);
+
+    assert!(output.contains(">>> exit"), "{}", output);
+}
+
+#[test]
+fn exit_stops_every_task_and_reports_what_is_left_open() {
+    let env = TestEnv::new("shutdown");
+    let output = env.run(&[]);
+
+    assert!(output.contains("Shutting down"), "{}", output);
+    assert!(output.contains("tasks."), "{}", output);
+    assert!(!output.contains("were aborted"), "{}", output);
+    assert!(output.contains("----- Left Open -----"), "{}", output);
+    assert!(output.contains("\tMarket: SOL"), "{}", output);
+}
+
+#[test]
+fn orderbook_shows_fixture_levels_and_our_orders() {
+    let env = TestEnv::new("orderbook");
+    let output = env.run(&[
+        &format!("orderbook {} 10", MARKET_NAME),
+        &format!("orderbook {} 10 --l3", MARKET_NAME),
+        &format!("orderbook {} 10 --group 1", MARKET_NAME),
+    ]);
+
+    assert!(
+        output.contains("----- OrderBook Status -----"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("----- OrderBook Status (L3) -----"),
+        "{}",
+        output
+    );
+    assert!(output.contains("----- Our Orders -----"), "{}", output);
+    assert!(output.contains("Bids:   1   Asks:   1"), "{}", output);
+    assert!(output.contains(&env.open_orders.to_string()), "{}", output);
+}
+
+#[test]
+fn orderbook_for_unknown_market_is_reported() {
+    let env = TestEnv::new("orderbook-unknown");
+    let output = env.run(&["orderbook NOPE 10"]);
+
+    assert!(
+        output.contains("Could not find a suitable handler for the market: NOPE."),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn status_commands_print_their_sections() {
+    let env = TestEnv::new("status");
+    let output = env.run(&["markets", "tokens", "status", "pnl"]);
+
+    assert!(output.contains("----- Markets Status -----"), "{}", output);
+    assert!(output.contains("----- Tokens Status -----"), "{}", output);
+    assert!(output.contains("----- Account Status -----"), "{}", output);
+    assert!(output.contains("----- Open Orders -----"), "{}", output);
+    assert!(output.contains("----- PnL -----"), "{}", output);
+}
+
+#[test]
+fn trades_with_an_empty_log() {
+    let env = TestEnv::new("trades");
+    let output = env.run(&["trades", &format!("trades {} 5", MARKET_NAME)]);
+
+    assert!(
+        output.contains("No trades found in the trade log."),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn account_transactions_are_submitted() {
+    let env = TestEnv::new("account-txs");
+    let delegate = Pubkey::new_unique();
+    let output = env.run(&[
+        "airdrop",
+        "deposit 100",
+        &format!("delegate {}", delegate),
+        "new 1",
+    ]);
+
+    assert!(
+        output.contains("Successfully requested airdrop."),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("Successfully deposited USDC."),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!("Successfully delegated account to {}.", delegate)),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("Successfully created new account with number 1."),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn limit_order_is_submitted() {
+    let env = TestEnv::new("limit");
+    let output = env.run(&[&format!("limit buy {} 1 30", MARKET_NAME)]);
+
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn limit_order_below_lot_size_is_rejected() {
+    let env = TestEnv::new("limit-lot");
+    let output = env.run(&[&format!("limit buy {} 0.0000001 30", MARKET_NAME)]);
+
+    assert!(output.contains("QuantityBelowLotSize"), "{}", output);
+}
+
+#[test]
+fn limit_order_below_min_c_ratio_is_blocked_unless_forced() {
+    let env = TestEnv::new("limit-margin");
+    let output = env.run_with_args(
+        &["--min-c-ratio", "1000000"],
+        &[
+            &format!("limit buy {} 1 30", MARKET_NAME),
+            &format!("limit buy {} 1 30 --force", MARKET_NAME),
+        ],
+    );
+
+    assert!(output.contains("CRatioBelowMinimum"), "{}", output);
+    assert!(
+        output.contains("submitting anyway since the order is forced"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn limit_order_below_min_c_ratio_only_warns_in_warn_mode() {
+    let env = TestEnv::new("limit-margin-warn");
+    let output = env.run_with_args(
+        &["--min-c-ratio", "1000000", "--margin-check", "warn"],
+        &[&format!("limit buy {} 1 30", MARKET_NAME)],
+    );
+
+    assert!(output.contains("Warning: projected c-ratio"), "{}", output);
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn limit_order_breaching_a_risk_limit_is_rejected_and_logged() {
+    let env = TestEnv::new("limit-risk");
+    std::fs::write(
+        env.dir.join("cfg").join("risk.json"),
+        r#"{ "defaults": { "maxOrderNotional": 10.0 } }"#,
+    )
+    .unwrap();
+    let output = env.run(&[&format!("limit buy {} 1 30", MARKET_NAME)]);
+
+    assert!(output.contains("RiskMaxOrderNotional("), "{}", output);
+    let log = std::fs::read_to_string(env.dir.join("logs").join("risk.jsonl")).unwrap();
+    assert!(log.contains("\"limit\":\"maxOrderNotional\""), "{}", log);
+}
+
+#[test]
+fn a_malformed_risk_config_stops_the_cli() {
+    let env = TestEnv::new("risk-malformed");
+    std::fs::write(
+        env.dir.join("cfg").join("risk.json"),
+        r#"{ "defaults": { "maxOrderNotional": "ten" } }"#,
+    )
+    .unwrap();
+    let output = env.run_failing(&[]);
+
+    assert!(
+        output.contains("There was an error loading the risk config. RiskConfigRead"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn missing_fixtures_stop_the_cli() {
+    let env = TestEnv::new("fixtures-missing");
+    std::fs::remove_dir_all(env.dir.join("fixtures")).unwrap();
+    let output = env.run_failing(&[]);
+
+    assert!(
+        output.contains("There was an error loading the fixtures. FixturesLoad"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn the_risk_config_is_read_next_to_the_group_config() {
+    let env = TestEnv::new("risk-next-to-config");
+    let config_dir = env.dir.join("elsewhere");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::copy(
+        env.dir.join("cfg").join("group.json"),
+        config_dir.join("group.json"),
+    )
+    .unwrap();
+    std::fs::write(
+        config_dir.join("risk.json"),
+        r#"{ "defaults": { "maxOrderNotional": 10.0 } }"#,
+    )
+    .unwrap();
+    let output = env.run_with_args(
+        &["--config", "elsewhere/group.json"],
+        &[&format!("limit buy {} 1 30", MARKET_NAME)],
+    );
+
+    assert!(output.contains("RiskMaxOrderNotional("), "{}", output);
+}
+
+#[test]
+fn panic_blocks_orders_until_unlocked() {
+    let env = TestEnv::new("panic");
+    let output = env.run(&[
+        "panic settle",
+        &format!("limit buy {} 1 30", MARKET_NAME),
+        "unlock",
+        &format!("limit buy {} 1 30", MARKET_NAME),
+    ]);
+
+    assert!(output.contains("Kill switch engaged"), "{}", output);
+    assert!(
+        output.contains("Orders are blocked by the kill switch"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Kill switch released"), "{}", output);
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn accounts_lists_the_active_account_and_use_rejects_missing_ones() {
+    let env = TestEnv::new("accounts");
+    let output = env.run(&["accounts", "use 3", "use default"]);
+
+    assert!(output.contains("----- Accounts -----"), "{}", output);
+    assert!(output.contains("\t* default"), "{}", output);
+    assert!(output.contains("does not exist"), "{}", output);
+    assert!(output.contains("is already in use."), "{}", output);
+}
+
+#[test]
+fn owner_only_commands_are_unavailable_with_a_delegate_key() {
+    let env = TestEnv::new("delegate-key");
+    let output = env.run_as_delegate(&[
+        "help",
+        "deposit 10",
+        "delegate 11111111111111111111111111111111",
+    ]);
+
+    assert!(
+        output.contains("Running as delegate of owner"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(">>> deposit {amount_ui} (owner only, unavailable)"),
+        "{}",
+        output
+    );
+    assert!(output.contains("OwnerKeypairRequired"), "{}", output);
+    assert!(
+        output.contains("Successfully fetched cypher user account"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn delegate_status_shows_the_signer_role() {
+    let env = TestEnv::new("delegate-status");
+    let output = env.run(&["delegate status"]);
+    assert!(output.contains("----- Delegation -----"), "{}", output);
+    assert!(output.contains("\tDelegate: none"), "{}", output);
+    assert!(
+        output.contains("The signer is the account owner."),
+        "{}",
+        output
+    );
+
+    let output = env.run_as_delegate(&["delegate status", "undelegate"]);
+    assert!(
+        output.contains("neither the owner nor the delegate"),
+        "{}",
+        output
+    );
+    assert!(output.contains("OwnerKeypairRequired"), "{}", output);
+}
+
+#[test]
+fn orders_are_blocked_for_a_signer_which_is_not_the_delegate() {
+    let env = TestEnv::new("unauthorized");
+    let output = env.run_as_delegate(&[
+        &format!("limit buy {} 1 32", MARKET_NAME),
+        &format!("cancel {} 12345", MARKET_NAME),
+    ]);
+
+    assert_eq!(
+        output
+            .matches("is neither the owner nor the delegate of the account")
+            .count(),
+        2,
+        "{}",
+        output
+    );
+    assert!(!output.contains("Successfully placed order."), "{}", output);
+    assert!(!output.contains("InvalidOrderId"), "{}", output);
+}
+
+#[test]
+fn market_order_for_unknown_market_is_reported() {
+    // the fixtures backend accepts any order, so only the market lookup is exercised
+    let env = TestEnv::new("market");
+    let output = env.run(&["market buy NOPE 1"]);
+
+    assert!(
+        output.contains("Could not find an handler for market NOPE."),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn market_order_is_submitted_with_room_for_slippage() {
+    let env = TestEnv::new("market-fill");
+    // 1 SOL is 1000 lots, all of it resting at the ask
+    env.set_ask_quantity(1_000);
+    let output = env.run(&[&format!("market buy {} 1", MARKET_NAME)]);
+
+    let limit_price = (ASK_PRICE_LOTS * (10_000 + MARKET_ORDER_SLIPPAGE_BPS)).div_ceil(10_000);
+    assert!(
+        output.contains(&format!(
+            "Expecting a fill at {} lots, submitting with a limit price of {} lots.",
+            ASK_PRICE_LOTS, limit_price
+        )),
+        "{}",
+        output
+    );
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn market_order_larger_than_the_book_is_rejected() {
+    let env = TestEnv::new("market-thin");
+    let output = env.run(&[&format!("market buy {} 1", MARKET_NAME)]);
+
+    assert!(
+        output.contains("Not enough liquidity on the book to fill 1000 lots."),
+        "{}",
+        output
+    );
+    assert!(output.contains("InsufficientLiquidity(1.0)"), "{}", output);
+    assert!(!output.contains("Successfully placed order."), "{}", output);
+}
+
+#[test]
+fn cancel_of_an_order_we_do_not_have_is_rejected() {
+    let env = TestEnv::new("cancel");
+    let output = env.run(&[&format!("cancel {} 12345", MARKET_NAME)]);
+
+    assert!(output.contains("InvalidOrderId(12345)"), "{}", output);
+}
+
+#[test]
+fn sync_config_drops_markets_the_group_no_longer_lists() {
+    // the fixture group account lists no markets at all
+    let env = TestEnv::new("sync-config");
+    let output = env.run_with_args(&["--sync-config"], &["sync-config"]);
+
+    assert!(
+        output.contains(&format!(
+            "Removed market {}, it is no longer listed in the group.",
+            MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(
+        !output.contains(&format!("Preparing handler for market {}.", MARKET_NAME)),
+        "{}",
+        output
+    );
+    assert!(output.contains("The config is up to date."), "{}", output);
+}
+
+#[test]
+fn markets_left_out_are_loaded_on_demand() {
+    let env = TestEnv::new("load");
+    let output = env.run_with_args(
+        &["--markets="],
+        &[
+            "status",
+            &format!("load {}", MARKET_NAME),
+            &format!("load {}", MARKET_NAME),
+        ],
+    );
+
+    assert!(
+        output.contains(&format!(
+            "Not loaded, use 'load {}' to load it.",
+            MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!("Loaded market {}.", MARKET_NAME)),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!("Market {} is already loaded.", MARKET_NAME)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn markets_left_out_are_loaded_on_first_use() {
+    let env = TestEnv::new("load-on-use");
+    let output = env.run_with_args(&["--markets="], &[&format!("cancel {} 12345", MARKET_NAME)]);
+
+    assert!(
+        output.contains(&format!("Loading market {} on first use.", MARKET_NAME)),
+        "{}",
+        output
+    );
+    assert!(output.contains("InvalidOrderId(12345)"), "{}", output);
+}
+
+#[test]
+fn markets_are_only_loaded_when_first_used_by_default() {
+    let env = TestEnv::new("lazy-default");
+    let output = env.run(&["status"]);
+
+    assert!(
+        output.contains(&format!(
+            "Not loaded, use 'load {}' to load it.",
+            MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(!output.contains("Preparing handler"), "{}", output);
+}
+
+#[test]
+fn every_market_is_loaded_at_startup_when_asked_to() {
+    let env = TestEnv::new("load-all");
+    let output = env.run_with_args(
+        &["--load-all-markets"],
+        &[&format!("cancel {} 12345", MARKET_NAME)],
+    );
+
+    assert!(
+        output.contains(&format!("Preparing handler for market {}.", MARKET_NAME)),
+        "{}",
+        output
+    );
+    assert!(!output.contains("on first use"), "{}", output);
+    assert!(output.contains("InvalidOrderId(12345)"), "{}", output);
+}
+
+#[test]
+fn markets_which_failed_to_load_are_retried_on_first_use() {
+    let env = TestEnv::new("load-retry");
+    std::fs::remove_file(env.dir.join("fixtures").join("market.json")).unwrap();
+    let output = env.run_with_args(
+        &["--load-all-markets"],
+        &[&format!("orderbook {} 10", MARKET_NAME)],
+    );
+
+    assert!(
+        output.contains(&format!(
+            "An error occurred while fetching the serum market account for {}.",
+            MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!("Loading market {} on first use.", MARKET_NAME)),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!("Market {} could not be loaded.", MARKET_NAME)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn close_oo_reclaims_rent_and_unloads_the_market() {
+    let env = TestEnv::new("close-oo");
+    let output = env.run(&[&format!("close-oo {}", MARKET_NAME), "status"]);
+
+    assert!(
+        output.contains(&format!(
+            "Closed open orders account {} for market {}, reclaimed 1 SOL.",
+            env.open_orders, MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!(
+            "Not loaded, use 'load {}' to load it.",
+            MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn close_oo_of_a_market_which_is_not_in_the_group_is_rejected() {
+    let env = TestEnv::new("close-oo-unknown");
+    let output = env.run(&["close-oo NOPE"]);
+
+    assert!(
+        output.contains(&format!("Market NOPE is not in group {}.", GROUP_NAME)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn close_oo_all_closes_the_accounts_of_markets_which_are_not_loaded() {
+    let env = TestEnv::new("close-oo-all-unloaded");
+    let output = env.run_with_args(&["--markets="], &["close-oo all"]);
+
+    assert!(
+        output.contains(&format!(
+            "Closed open orders account {} for market {}, reclaimed 1 SOL.",
+            env.open_orders, MARKET_NAME
+        )),
+        "{}",
+        output
+    );
+    assert!(!output.contains("Unloaded"), "{}", output);
+}
+
+#[test]
+fn close_oo_is_unavailable_with_a_delegate_key() {
+    let env = TestEnv::new("close-oo-delegate");
+    let output = env.run_as_delegate(&[&format!("close-oo {}", MARKET_NAME)]);
+
+    assert!(output.contains("OwnerKeypairRequired"), "{}", output);
+    assert!(!output.contains("Closed open orders account"), "{}", output);
+}
+
+#[test]
+fn recording_keeps_the_updates_from_before_a_service_restart() {
+    let env = TestEnv::new("record-restart");
+    let sub_account = env.add_sub_account(1);
+    let output = env.run_with_args(
+        &["--record", "recording.bin", "--load-all-markets"],
+        &["use 1"],
+    );
+    assert!(
+        output.contains(&format!("Now using account {}.", sub_account)),
+        "{}",
+        output
+    );
+
+    let records = read_recording(&env.dir.join("recording.bin")).unwrap();
+    for pubkey in [env.cypher_user, env.bids, env.asks, sub_account] {
+        assert!(
+            records.iter().any(|r| r.pubkey == pubkey),
+            "the recording has no update for {}",
+            pubkey
+        );
+    }
+
+    let output = env.run_with_args(
+        &["--replay", "recording.bin"],
+        &[&format!("orderbook {}", MARKET_NAME)],
+    );
+    assert!(
+        output.contains("----- OrderBook Status -----"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn replay_rejects_commands_which_send_transactions() {
+    let env = TestEnv::new("replay");
+    env.run_with_args(&["--record", "recording.bin"], &["status"]);
+    let output = env.run_with_args(
+        &["--replay", "recording.bin"],
+        &[
+            &format!("limit buy {} 1 32", MARKET_NAME),
+            &format!("cancel {} 12345", MARKET_NAME),
+            "panic",
+            "deposit 10",
+        ],
+    );
+
+    assert_eq!(
+        output
+            .matches("This command sends transactions, which is not possible while replaying a recording.")
+            .count(),
+        4,
+        "{}",
+        output
+    );
+    assert!(!output.contains("InvalidOrderId"), "{}", output);
+}
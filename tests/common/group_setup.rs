@@ -0,0 +1,291 @@
+//! Creates a cypher group with a single market on a local validator, so the validator tests do not
+//! depend on a group dumped from a live cluster.
+//!
+//! The `cypher` client only exposes the user instructions, the admin ones are encoded here the way
+//! anchor does for the cypher program in the fixtures, an 8 byte sighash of `global:<name>`
+//! followed by the borsh encoded arguments.
+
+use {
+    super::validator::LocalValidator,
+    anchor_lang::AnchorSerialize,
+    cypher::{quote_mint, utils::derive_dex_market_authority, CypherGroup},
+    cypher_interactive::{
+        config::{fetch_cypher_group, sync_group_config, CypherGroupConfig},
+        rpc::RpcBackend,
+    },
+    serum_dex::{instruction::initialize_market, state::gen_vault_signer_key},
+    solana_sdk::{
+        hash::hash,
+        instruction::{AccountMeta, Instruction},
+        program_pack::Pack,
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{Keypair, Signer},
+        system_instruction, system_program, sysvar,
+        transaction::Transaction,
+    },
+    spl_token::state::{Account as TokenAccount, Mint},
+    std::{mem::size_of, sync::Arc},
+};
+
+pub const BASE_DECIMALS: u8 = 9;
+pub const COIN_LOT_SIZE: u64 = 1_000_000;
+pub const PC_LOT_SIZE: u64 = 100;
+const PC_DUST_THRESHOLD: u64 = 100;
+
+// the sizes the serum dex expects, each including its 12 bytes of `serum` and `padding` markers
+const MARKET_SIZE: usize = 388;
+const REQUEST_QUEUE_SIZE: usize = 5_120 + 12;
+const EVENT_QUEUE_SIZE: usize = 262_144 + 12;
+const ORDER_BOOK_SIZE: usize = 65_536 + 12;
+
+/// A group created on the local validator, along with the config the cli would use for it.
+pub struct TestGroup {
+    pub address: Pubkey,
+    pub config: CypherGroupConfig,
+}
+
+impl TestGroup {
+    /// Creates a group with the quote mint from the fixtures as its quote token, and lists a single
+    /// market for a new token on a new serum market, priced by the oracle from the fixtures.
+    pub async fn create(validator: &LocalValidator) -> Self {
+        let rpc = validator.rpc();
+        let authority = Keypair::new();
+        validator.fund(&authority.pubkey(), 100);
+
+        let group = Keypair::new();
+        let (vault_signer, vault_signer_bump) =
+            Pubkey::find_program_address(&[group.pubkey().as_ref()], &cypher::ID);
+        let quote_vault = Keypair::new();
+        let mut ixs = vec![create_account(
+            &authority.pubkey(),
+            &group.pubkey(),
+            8 + size_of::<CypherGroup>(),
+            &cypher::ID,
+        )];
+        ixs.extend(create_token_account(
+            &authority.pubkey(),
+            &quote_vault.pubkey(),
+            &quote_mint::ID,
+            &vault_signer,
+        ));
+        ixs.push(anchor_ix(
+            "init_cypher_group",
+            vault_signer_bump,
+            vec![
+                AccountMeta::new(group.pubkey(), false),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new_readonly(vault_signer, false),
+                AccountMeta::new_readonly(quote_mint::ID, false),
+                AccountMeta::new(quote_vault.pubkey(), false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                AccountMeta::new_readonly(sysvar::rent::ID, false),
+            ],
+        ));
+        send(&rpc, &authority, &[&group, &quote_vault], &ixs).await;
+
+        // the token the market trades, minted by the group like every other c-asset
+        let c_asset_mint = Keypair::new();
+        let c_asset_vault = Keypair::new();
+        let mut ixs = vec![
+            create_account(
+                &authority.pubkey(),
+                &c_asset_mint.pubkey(),
+                Mint::LEN,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::ID,
+                &c_asset_mint.pubkey(),
+                &vault_signer,
+                None,
+                BASE_DECIMALS,
+            )
+            .unwrap(),
+        ];
+        ixs.extend(create_token_account(
+            &authority.pubkey(),
+            &c_asset_vault.pubkey(),
+            &c_asset_mint.pubkey(),
+            &vault_signer,
+        ));
+        send(&rpc, &authority, &[&c_asset_mint, &c_asset_vault], &ixs).await;
+
+        let dex_market = create_dex_market(
+            &rpc,
+            &authority,
+            &validator.dex_program_id,
+            &c_asset_mint.pubkey(),
+        )
+        .await;
+
+        let ix = anchor_ix(
+            "create_cypher_market",
+            (),
+            vec![
+                AccountMeta::new(group.pubkey(), false),
+                AccountMeta::new(authority.pubkey(), true),
+                AccountMeta::new_readonly(vault_signer, false),
+                AccountMeta::new_readonly(validator.oracle, false),
+                AccountMeta::new(c_asset_mint.pubkey(), false),
+                AccountMeta::new(c_asset_vault.pubkey(), false),
+                AccountMeta::new_readonly(dex_market, false),
+                AccountMeta::new_readonly(validator.dex_program_id, false),
+            ],
+        );
+        send(&rpc, &authority, &[], &[ix]).await;
+
+        // names are not on chain, the sync names the market after its index
+        let cypher_group = fetch_cypher_group(&group.pubkey(), Arc::clone(&rpc))
+            .await
+            .unwrap();
+        let empty_config = CypherGroupConfig {
+            cluster: "localnet".to_string(),
+            name: "validator".to_string(),
+            quote_symbol: "USDC".to_string(),
+            address: group.pubkey().to_string(),
+            program_id: cypher::ID.to_string(),
+            ..Default::default()
+        };
+        let (config, report) = sync_group_config(&cypher_group, &empty_config, rpc)
+            .await
+            .unwrap();
+        assert_eq!(
+            report.added.len(),
+            1,
+            "the group should list a single market"
+        );
+
+        Self {
+            address: group.pubkey(),
+            config,
+        }
+    }
+}
+
+/// Creates and initializes a serum market for the given coin against the quote mint,
+/// permissioned to the cypher market authority the way cypher lists its markets.
+async fn create_dex_market(
+    rpc: &Arc<dyn RpcBackend>,
+    payer: &Keypair,
+    dex_program_id: &Pubkey,
+    coin_mint: &Pubkey,
+) -> Pubkey {
+    let market = Keypair::new();
+    let request_queue = Keypair::new();
+    let event_queue = Keypair::new();
+    let bids = Keypair::new();
+    let asks = Keypair::new();
+    let ixs: Vec<Instruction> = [
+        (&market, MARKET_SIZE),
+        (&request_queue, REQUEST_QUEUE_SIZE),
+        (&event_queue, EVENT_QUEUE_SIZE),
+        (&bids, ORDER_BOOK_SIZE),
+        (&asks, ORDER_BOOK_SIZE),
+    ]
+    .iter()
+    .map(|(account, len)| create_account(&payer.pubkey(), &account.pubkey(), *len, dex_program_id))
+    .collect();
+    send(
+        rpc,
+        payer,
+        &[&market, &request_queue, &event_queue, &bids, &asks],
+        &ixs,
+    )
+    .await;
+
+    let mut vault_signer_nonce = 0;
+    let vault_signer = loop {
+        if let Ok(pk) = gen_vault_signer_key(vault_signer_nonce, &market.pubkey(), dex_program_id) {
+            break pk;
+        }
+        vault_signer_nonce += 1;
+    };
+    let coin_vault = Keypair::new();
+    let pc_vault = Keypair::new();
+    let market_authority = derive_dex_market_authority(&market.pubkey());
+    let mut ixs = create_token_account(
+        &payer.pubkey(),
+        &coin_vault.pubkey(),
+        coin_mint,
+        &vault_signer,
+    );
+    ixs.extend(create_token_account(
+        &payer.pubkey(),
+        &pc_vault.pubkey(),
+        &quote_mint::ID,
+        &vault_signer,
+    ));
+    ixs.push(
+        initialize_market(
+            &market.pubkey(),
+            dex_program_id,
+            coin_mint,
+            &quote_mint::ID,
+            &coin_vault.pubkey(),
+            &pc_vault.pubkey(),
+            Some(&market_authority),
+            Some(&market_authority),
+            Some(&market_authority),
+            &bids.pubkey(),
+            &asks.pubkey(),
+            &request_queue.pubkey(),
+            &event_queue.pubkey(),
+            COIN_LOT_SIZE,
+            PC_LOT_SIZE,
+            vault_signer_nonce,
+            PC_DUST_THRESHOLD,
+        )
+        .unwrap(),
+    );
+    send(rpc, payer, &[&coin_vault, &pc_vault], &ixs).await;
+
+    market.pubkey()
+}
+
+/// An instruction for the cypher program encoded the way anchor encodes it.
+fn anchor_ix(name: &str, args: impl AnchorSerialize, accounts: Vec<AccountMeta>) -> Instruction {
+    let mut data = hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+    args.serialize(&mut data).unwrap();
+    Instruction {
+        program_id: cypher::ID,
+        accounts,
+        data,
+    }
+}
+
+fn create_account(payer: &Pubkey, account: &Pubkey, len: usize, owner: &Pubkey) -> Instruction {
+    system_instruction::create_account(
+        payer,
+        account,
+        Rent::default().minimum_balance(len),
+        len as u64,
+        owner,
+    )
+}
+
+fn create_token_account(
+    payer: &Pubkey,
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        create_account(payer, account, TokenAccount::LEN, &spl_token::ID),
+        spl_token::instruction::initialize_account(&spl_token::ID, account, mint, owner).unwrap(),
+    ]
+}
+
+async fn send(
+    rpc: &Arc<dyn RpcBackend>,
+    payer: &Keypair,
+    signers: &[&Keypair],
+    ixs: &[Instruction],
+) {
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    let hash = rpc.get_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &all_signers, hash);
+    rpc.send_and_confirm_transaction(&tx).await.unwrap();
+}
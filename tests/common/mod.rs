@@ -0,0 +1,468 @@
+#![allow(dead_code)]
+pub mod group_setup;
+#[cfg(unix)]
+pub mod signing_daemon;
+pub mod validator;
+
+use {
+    anchor_lang::Discriminator,
+    cypher::{
+        constants::QUOTE_TOKEN_IDX,
+        utils::{
+            derive_cypher_user_address, derive_cypher_user_address_with_number,
+            derive_open_orders_address, get_zero_copy_account,
+        },
+        CypherGroup, CypherUser,
+    },
+    cypher_interactive::keystore::{write_keystore, Keystore, PASSPHRASE_ENV_VAR},
+    serde_json::json,
+    serum_dex::state::{MarketStateV2, OpenOrders},
+    solana_sdk::{
+        account::Account,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+    },
+    std::{
+        fs::{create_dir_all, remove_dir_all, remove_file, write},
+        io::{Read, Write},
+        mem::size_of,
+        path::{Path, PathBuf},
+        process::{Command, ExitStatus, Stdio},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+pub const MARKET_NAME: &str = "SOL";
+pub const GROUP_NAME: &str = "fixtures";
+pub const COIN_LOT_SIZE: u64 = 1_000_000;
+pub const PC_LOT_SIZE: u64 = 100;
+pub const BID_PRICE_LOTS: u64 = 3_200;
+pub const ASK_PRICE_LOTS: u64 = 3_300;
+pub const ORDER_QUANTITY_LOTS: u64 = 5;
+
+/// How long we give the services to pick up the fixtures before sending commands.
+const STARTUP_DELAY: Duration = Duration::from_secs(3);
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+// serum account flags
+const INITIALIZED: u64 = 1;
+const MARKET: u64 = 1 << 1;
+const OPEN_ORDERS: u64 = 1 << 2;
+const EVENT_QUEUE: u64 = 1 << 4;
+const BIDS: u64 = 1 << 5;
+const ASKS: u64 = 1 << 6;
+
+/// A working directory holding keypairs, a group config and account fixtures for a single market,
+/// with one resting bid and one resting ask that belong to us.
+pub struct TestEnv {
+    pub dir: PathBuf,
+    pub owner: Keypair,
+    pub group: Pubkey,
+    pub cypher_user: Pubkey,
+    pub market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub open_orders: Pubkey,
+}
+
+impl TestEnv {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "cypher-interactive-{}-{}",
+            name,
+            std::process::id()
+        ));
+        _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("cfg")).unwrap();
+        create_dir_all(dir.join("fixtures")).unwrap();
+
+        let owner = Keypair::new();
+        let group = Pubkey::new_unique();
+        let cypher_user = derive_cypher_user_address(&group, &owner.pubkey()).0;
+        let market = Pubkey::new_unique();
+        let open_orders = derive_open_orders_address(&market, &cypher_user).0;
+
+        let env = Self {
+            dir,
+            owner,
+            group,
+            cypher_user,
+            market,
+            bids: Pubkey::new_unique(),
+            asks: Pubkey::new_unique(),
+            event_queue: Pubkey::new_unique(),
+            open_orders,
+        };
+        env.write_keypair();
+        env.write_config();
+        env.write_fixtures();
+        env
+    }
+
+    /// Runs the cli against the fixtures, feeding it the given commands followed by `exit`,
+    /// and returns everything it printed.
+    pub fn run(&self, commands: &[&str]) -> String {
+        self.run_with_args(&[], commands)
+    }
+
+    /// Like [`TestEnv::run`], passing the given extra arguments to the cli.
+    pub fn run_with_args(&self, args: &[&str], commands: &[&str]) -> String {
+        let keypair_path = self.dir.join("owner.json");
+        let keypair_path = keypair_path.to_str().unwrap();
+        self.run_with_keys(&["-u", keypair_path, "-s", keypair_path], args, commands)
+    }
+
+    /// Like [`TestEnv::run`], signing with a fresh delegate keypair and without the owner keypair.
+    pub fn run_as_delegate(&self, commands: &[&str]) -> String {
+        let delegate = Keypair::new();
+        let keypair_path = self.dir.join("delegate.json");
+        let bytes = serde_json::to_string(&delegate.to_bytes().to_vec()).unwrap();
+        write(&keypair_path, bytes).unwrap();
+
+        let owner = self.owner.pubkey().to_string();
+        let keypair_path = keypair_path.to_str().unwrap();
+        self.run_with_keys(&["--owner", &owner, "-s", keypair_path], &[], commands)
+    }
+
+    /// Like [`TestEnv::run`], signing through the signing daemon listening on the given socket.
+    pub fn run_with_signer_socket(&self, socket_path: &Path, commands: &[&str]) -> String {
+        let keypair_path = self.dir.join("owner.json");
+        self.run_with_keys(
+            &[
+                "-u",
+                keypair_path.to_str().unwrap(),
+                "--signer-socket",
+                socket_path.to_str().unwrap(),
+            ],
+            &[],
+            commands,
+        )
+    }
+
+    /// Like [`TestEnv::run`], with the owner keypair encrypted into a keystore whose passphrase comes from the environment.
+    pub fn run_with_keystore(&self, passphrase: &str, commands: &[&str]) -> String {
+        let keystore = Keystore::encrypt(&self.owner, passphrase).unwrap();
+        let keystore_path = self.dir.join("owner.keystore.json");
+        _ = remove_file(&keystore_path);
+        write_keystore(&keystore_path, &keystore).unwrap();
+
+        let keystore_path = keystore_path.to_str().unwrap();
+        self.run_process(
+            &["-u", keystore_path, "-s", keystore_path],
+            &[(PASSPHRASE_ENV_VAR, passphrase)],
+            commands,
+        )
+    }
+
+    fn run_with_keys(&self, keys: &[&str], args: &[&str], commands: &[&str]) -> String {
+        let args: Vec<&str> = keys.iter().chain(args).copied().collect();
+        self.run_process(&args, &[], commands)
+    }
+
+    fn run_process(&self, args: &[&str], envs: &[(&str, &str)], commands: &[&str]) -> String {
+        let (status, output, stderr) = self.run_to_exit(args, envs, commands);
+        assert!(
+            status.success(),
+            "the cli exited with {}\nstdout:\n{}\nstderr:\n{}",
+            status,
+            output,
+            stderr
+        );
+
+        output
+    }
+
+    /// Like [`TestEnv::run_with_args`] for a run which is expected to fail, returns everything it printed.
+    pub fn run_failing(&self, args: &[&str]) -> String {
+        let keypair_path = self.dir.join("owner.json");
+        let keypair_path = keypair_path.to_str().unwrap();
+        let args: Vec<&str> = ["-u", keypair_path, "-s", keypair_path]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect();
+        let (status, output, _) = self.run_to_exit(&args, &[], &[]);
+        assert!(
+            !status.success(),
+            "the cli was expected to fail\nstdout:\n{}",
+            output
+        );
+
+        output
+    }
+
+    fn run_to_exit(
+        &self,
+        args: &[&str],
+        envs: &[(&str, &str)],
+        commands: &[&str],
+    ) -> (ExitStatus, String, String) {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_cypher-interactive"))
+            .current_dir(&self.dir)
+            // keeps a user overlay on this machine out of the tests
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .env_remove("CYPHER_CONFIG")
+            .env_remove("CYPHER_CONFIG_OVERLAY")
+            .envs(envs.iter().copied())
+            .args(["-c", "devnet", "-g", GROUP_NAME])
+            .args(["--fixtures", "fixtures"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            _ = stdout.read_to_string(&mut output);
+            output
+        });
+
+        thread::sleep(STARTUP_DELAY);
+        let mut stdin = child.stdin.take().unwrap();
+        // a run which fails at startup is gone before it reads anything
+        for command in commands {
+            _ = writeln!(stdin, "{}", command);
+        }
+        _ = writeln!(stdin, "exit");
+        drop(stdin);
+
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if started.elapsed() > RUN_TIMEOUT {
+                _ = child.kill();
+                panic!("the cli did not exit within {:?}", RUN_TIMEOUT);
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+
+        let output = reader.join().unwrap();
+        let mut stderr = String::new();
+        _ = child.stderr.take().unwrap().read_to_string(&mut stderr);
+
+        (status, output, stderr)
+    }
+
+    fn write_keypair(&self) {
+        let bytes = serde_json::to_string(&self.owner.to_bytes().to_vec()).unwrap();
+        write(self.dir.join("owner.json"), bytes).unwrap();
+    }
+
+    fn write_config(&self) {
+        let config = json!({
+            "clusters": {
+                "devnet": { "rpcUrl": "http://localhost:8899", "pubsubUrl": "ws://localhost:8900" },
+                "mainnet": { "rpcUrl": "http://localhost:8899", "pubsubUrl": "ws://localhost:8900" }
+            },
+            "groups": [
+                {
+                    "cluster": "devnet",
+                    "name": GROUP_NAME,
+                    "quoteSymbol": "USDC",
+                    "address": self.group.to_string(),
+                    "programId": Pubkey::default().to_string(),
+                    "serumProgramId": Pubkey::default().to_string(),
+                    "tokens": [],
+                    "oracles": [],
+                    "markets": [
+                        {
+                            "name": MARKET_NAME,
+                            "baseSymbol": "SOL",
+                            "quoteSymbol": "USDC",
+                            "marketType": "spot",
+                            "pairBaseSymbol": "SOL",
+                            "pairQuoteSymbol": "USDC",
+                            "address": self.market.to_string(),
+                            "baseDecimals": 9,
+                            "quoteDecimals": 6,
+                            "marketIndex": 0,
+                            "bids": self.bids.to_string(),
+                            "asks": self.asks.to_string(),
+                            "eventQueue": self.event_queue.to_string()
+                        }
+                    ]
+                }
+            ]
+        });
+        write(
+            self.dir.join("cfg").join("group.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn write_fixtures(&self) {
+        self.write_fixture("group", &self.group, anchor_account::<CypherGroup>());
+        self.write_fixture("user", &self.cypher_user, anchor_account::<CypherUser>());
+        self.write_fixture("market", &self.market, self.market_account());
+        self.write_fixture(
+            "open_orders",
+            &self.open_orders,
+            serum_account(INITIALIZED | OPEN_ORDERS, size_of::<OpenOrders>() - 8),
+        );
+        self.write_fixture(
+            "event_queue",
+            &self.event_queue,
+            serum_account(INITIALIZED | EVENT_QUEUE, 24 + 88 * 16),
+        );
+        self.write_fixture(
+            "bids",
+            &self.bids,
+            self.slab_account(INITIALIZED | BIDS, BID_PRICE_LOTS, ORDER_QUANTITY_LOTS, 1),
+        );
+        self.write_fixture(
+            "asks",
+            &self.asks,
+            self.slab_account(INITIALIZED | ASKS, ASK_PRICE_LOTS, ORDER_QUANTITY_LOTS, 2),
+        );
+    }
+
+    /// Rewrites the asks fixture so it holds a single order of ours for the given quantity at the ask price.
+    pub fn set_ask_quantity(&self, quantity_lots: u64) {
+        self.write_fixture(
+            "asks",
+            &self.asks,
+            self.slab_account(INITIALIZED | ASKS, ASK_PRICE_LOTS, quantity_lots, 2),
+        );
+    }
+
+    /// Adds a fixture for the owner's sub-account with the given number and returns its address.
+    pub fn add_sub_account(&self, account_number: u64) -> Pubkey {
+        let cypher_user = derive_cypher_user_address_with_number(
+            &self.group,
+            &self.owner.pubkey(),
+            account_number,
+        )
+        .0;
+        self.write_fixture(
+            &format!("user-{}", account_number),
+            &cypher_user,
+            anchor_account::<CypherUser>(),
+        );
+        cypher_user
+    }
+
+    /// Rewrites the group fixture so it lists our market at the given index, with a token of the given mint.
+    ///
+    /// Only the fields a config sync reads are set, their offsets are taken from the accessors the sync uses.
+    pub fn list_market_in_group(
+        &self,
+        market_index: usize,
+        mint: &Pubkey,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) {
+        let mut data = anchor_account::<CypherGroup>();
+        let group = get_zero_copy_account::<CypherGroup>(&Account {
+            data: data.clone(),
+            ..Account::default()
+        });
+        let start = group.as_ref() as *const CypherGroup as usize;
+        // the account data starts with the discriminator
+        let offset_of = |field: usize| 8 + field - start;
+
+        let market = group.get_cypher_market(market_index).unwrap();
+        let token = group.get_cypher_token(market_index).unwrap();
+        let quote_token = group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        let dex_market = offset_of(&market.dex_market as *const Pubkey as usize);
+        let token_mint = offset_of(&token.mint as *const Pubkey as usize);
+        let token_decimals = offset_of(&token.decimals as *const u8 as usize);
+        let quote_decimals_offset = offset_of(&quote_token.decimals as *const u8 as usize);
+
+        data[dex_market..dex_market + 32].copy_from_slice(self.market.as_ref());
+        data[token_mint..token_mint + 32].copy_from_slice(mint.as_ref());
+        data[token_decimals] = base_decimals;
+        data[quote_decimals_offset] = quote_decimals;
+        self.write_fixture("group", &self.group, data);
+    }
+
+    fn write_fixture(&self, name: &str, pubkey: &Pubkey, data: Vec<u8>) {
+        let fixture = json!({
+            "pubkey": pubkey.to_string(),
+            "account": {
+                "lamports": 1_000_000_000_u64,
+                "data": [base64::encode(&data), "base64"],
+                "owner": Pubkey::default().to_string(),
+                "executable": false,
+                "rentEpoch": 0
+            }
+        });
+        write(
+            self.dir.join("fixtures").join(format!("{}.json", name)),
+            serde_json::to_string_pretty(&fixture).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn market_account(&self) -> Vec<u8> {
+        let mut data = serum_account(INITIALIZED | MARKET, size_of::<MarketStateV2>() - 8);
+        // offsets into the market state, after the `serum` padding
+        data[5 + 280..5 + 312].copy_from_slice(self.bids.as_ref());
+        data[5 + 312..5 + 344].copy_from_slice(self.asks.as_ref());
+        data[5 + 248..5 + 280].copy_from_slice(self.event_queue.as_ref());
+        data[5 + 344..5 + 352].copy_from_slice(&COIN_LOT_SIZE.to_le_bytes());
+        data[5 + 352..5 + 360].copy_from_slice(&PC_LOT_SIZE.to_le_bytes());
+        data
+    }
+
+    /// A bids or asks account holding a single order of ours at the given price.
+    fn slab_account(
+        &self,
+        flags: u64,
+        price_lots: u64,
+        quantity_lots: u64,
+        seq_num: u64,
+    ) -> Vec<u8> {
+        let mut slab: Vec<u8> = Vec::new();
+        slab.extend_from_slice(&1_u64.to_le_bytes()); // bump_index
+        slab.extend_from_slice(&0_u64.to_le_bytes()); // free_list_len
+        slab.extend_from_slice(&0_u32.to_le_bytes()); // free_list_head
+        slab.extend_from_slice(&0_u32.to_le_bytes()); // root_node
+        slab.extend_from_slice(&1_u64.to_le_bytes()); // leaf_count
+
+        let key = (u128::from(price_lots) << 64) | u128::from(seq_num);
+        slab.extend_from_slice(&2_u32.to_le_bytes()); // leaf tag
+        slab.push(0); // owner_slot
+        slab.push(0); // fee_tier
+        slab.extend_from_slice(&[0, 0]);
+        slab.extend_from_slice(&key.to_le_bytes());
+        slab.extend_from_slice(self.open_orders.as_ref());
+        slab.extend_from_slice(&quantity_lots.to_le_bytes());
+        slab.extend_from_slice(&seq_num.to_le_bytes()); // client_order_id
+        slab.resize(32 + 72 * 16, 0);
+
+        let mut data = serum_account(flags, slab.len());
+        data[5 + 8..5 + 8 + slab.len()].copy_from_slice(&slab);
+        data
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        _ = remove_dir_all(&self.dir);
+    }
+}
+
+/// A zeroed zero-copy anchor account with the discriminator of the given type.
+fn anchor_account<T: Discriminator>() -> Vec<u8> {
+    let mut data = T::discriminator().to_vec();
+    data.resize(8 + size_of::<T>(), 0);
+    data
+}
+
+/// A zeroed serum account with the given account flags followed by `len` bytes,
+/// wrapped in the `serum` and `padding` markers.
+fn serum_account(flags: u64, len: usize) -> Vec<u8> {
+    let mut data = b"serum".to_vec();
+    data.extend_from_slice(&flags.to_le_bytes());
+    data.resize(5 + 8 + len, 0);
+    data.extend_from_slice(b"padding");
+    data
+}
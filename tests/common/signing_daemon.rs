@@ -0,0 +1,96 @@
+use {
+    cypher_interactive::remote_signer::{
+        read_frame, write_frame, REQUEST_PUBKEY, REQUEST_SIGN, RESPONSE_ERR, RESPONSE_OK,
+    },
+    solana_sdk::signature::{Keypair, Signer},
+    std::{
+        fs::remove_file,
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    },
+};
+
+/// A signing daemon serving a single keypair over a Unix socket, standing in for the real one in CI.
+/// It stops listening once this is dropped.
+pub struct SigningDaemon {
+    pub socket_path: PathBuf,
+    pub pubkey: solana_sdk::pubkey::Pubkey,
+    refuse: Arc<AtomicBool>,
+    signed: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+}
+
+impl SigningDaemon {
+    pub fn start(dir: &Path, keypair: Keypair) -> Self {
+        let socket_path = dir.join("signer.sock");
+        _ = remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let daemon = Self {
+            socket_path,
+            pubkey: keypair.pubkey(),
+            refuse: Arc::new(AtomicBool::new(false)),
+            signed: Arc::new(AtomicUsize::new(0)),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+
+        let refuse = Arc::clone(&daemon.refuse);
+        let signed = Arc::clone(&daemon.signed);
+        let stop = Arc::clone(&daemon.stop);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let (tag, payload) = match read_frame(&mut stream) {
+                    Ok(f) => f,
+                    Err(_) => continue,
+                };
+                _ = match tag {
+                    REQUEST_PUBKEY => {
+                        write_frame(&mut stream, RESPONSE_OK, keypair.pubkey().as_ref())
+                    }
+                    REQUEST_SIGN if refuse.load(Ordering::SeqCst) => {
+                        write_frame(&mut stream, RESPONSE_ERR, b"signing is disabled")
+                    }
+                    REQUEST_SIGN => {
+                        signed.fetch_add(1, Ordering::SeqCst);
+                        let signature = keypair.sign_message(&payload);
+                        write_frame(&mut stream, RESPONSE_OK, signature.as_ref())
+                    }
+                    _ => write_frame(&mut stream, RESPONSE_ERR, b"unknown request"),
+                };
+            }
+        });
+
+        daemon
+    }
+
+    /// Makes the daemon refuse every signing request from now on.
+    pub fn refuse(&self) {
+        self.refuse.store(true, Ordering::SeqCst);
+    }
+
+    /// How many messages the daemon signed so far.
+    pub fn signed(&self) -> usize {
+        self.signed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for SigningDaemon {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wakes the listener up so it sees the stop flag
+        _ = UnixStream::connect(&self.socket_path);
+        _ = remove_file(&self.socket_path);
+    }
+}
@@ -0,0 +1,244 @@
+use {
+    cypher::quote_mint,
+    cypher_interactive::{
+        config::{Cluster, ClusterConfig},
+        rpc::RpcBackend,
+    },
+    serde::Deserialize,
+    solana_client::{nonblocking, rpc_client::RpcClient},
+    solana_sdk::{
+        commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+    },
+    std::{
+        fs::{read_dir, read_to_string, remove_dir_all},
+        path::{Path, PathBuf},
+        process::{Child, Command, Stdio},
+        str::FromStr,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// Where the programs and accounts for the local validator are expected,
+/// see the README in that directory for how to populate it.
+pub const VALIDATOR_FIXTURES_DIR: &str = "tests/fixtures/validator";
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const AIRDROP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The subset of `solana account --output json` we need to know which address an account goes to.
+#[derive(Deserialize)]
+struct AccountFixture {
+    pubkey: String,
+}
+
+/// The account fixture holding the price account the test market is listed with.
+const ORACLE_FIXTURE: &str = "oracle.json";
+
+/// A `solana-test-validator` running on its own ports and ledger, with every program and account
+/// in the fixtures directory loaded at genesis. The validator is killed once this is dropped.
+pub struct LocalValidator {
+    process: Child,
+    ledger: PathBuf,
+    pub rpc_url: String,
+    pub dex_program_id: Pubkey,
+    pub oracle: Pubkey,
+}
+
+impl LocalValidator {
+    /// Boots a validator with its rpc on the given port, the ports right after it are used
+    /// for pubsub, the faucet and gossip.
+    ///
+    /// Panics if the fixtures or the `solana-test-validator` binary are missing, the tests
+    /// which need them are `#[ignore]`d instead.
+    pub fn start(name: &str, rpc_port: u16) -> Self {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join(VALIDATOR_FIXTURES_DIR);
+        let programs: Vec<(Pubkey, PathBuf)> =
+            Self::fixture_files(&fixtures.join("programs"), "so")
+                .into_iter()
+                .map(|path| {
+                    // programs are named after the address they are deployed at, e.g. `<program id>.so`
+                    let program_id = path.file_stem().unwrap().to_str().unwrap();
+                    (Pubkey::from_str(program_id).unwrap(), path)
+                })
+                .collect();
+        for (program_id, name) in [(cypher::ID, "cypher"), (faucet::ID, "faucet")] {
+            assert!(
+                programs.iter().any(|(p, _)| *p == program_id),
+                "the {} program {} is missing from {}",
+                name,
+                program_id,
+                fixtures.join("programs").display()
+            );
+        }
+        // the serum dex is the only program we do not know the address of
+        let dex_programs: Vec<Pubkey> = programs
+            .iter()
+            .map(|(p, _)| *p)
+            .filter(|p| *p != cypher::ID && *p != faucet::ID)
+            .collect();
+        assert!(
+            dex_programs.len() == 1,
+            "expected exactly one serum dex program in {}, found {}",
+            fixtures.join("programs").display(),
+            dex_programs.len()
+        );
+
+        let accounts: Vec<(Pubkey, PathBuf)> =
+            Self::fixture_files(&fixtures.join("accounts"), "json")
+                .into_iter()
+                .map(|path| {
+                    let fixture: AccountFixture =
+                        serde_json::from_str(&read_to_string(&path).unwrap()).unwrap();
+                    (Pubkey::from_str(&fixture.pubkey).unwrap(), path)
+                })
+                .collect();
+        assert!(
+            accounts.iter().any(|(p, _)| *p == quote_mint::ID),
+            "the quote mint {} is missing from {}",
+            quote_mint::ID,
+            fixtures.join("accounts").display()
+        );
+        let oracle = match accounts
+            .iter()
+            .find(|(_, path)| path.file_name().unwrap() == ORACLE_FIXTURE)
+        {
+            Some((p, _)) => *p,
+            None => panic!(
+                "the oracle fixture {} is missing from {}",
+                ORACLE_FIXTURE,
+                fixtures.join("accounts").display()
+            ),
+        };
+
+        let ledger = std::env::temp_dir().join(format!(
+            "cypher-interactive-validator-{}-{}",
+            name,
+            std::process::id()
+        ));
+        _ = remove_dir_all(&ledger);
+
+        let mut command = Command::new("solana-test-validator");
+        command
+            .arg("--quiet")
+            .arg("--reset")
+            .args(["--ledger", ledger.to_str().unwrap()])
+            .args(["--rpc-port", &rpc_port.to_string()])
+            .args(["--faucet-port", &(rpc_port + 2).to_string()])
+            .args(["--gossip-port", &(rpc_port + 3).to_string()])
+            .args([
+                "--dynamic-port-range",
+                &format!("{}-{}", rpc_port + 10, rpc_port + 40),
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        for (program_id, path) in &programs {
+            command
+                .arg("--bpf-program")
+                .arg(program_id.to_string())
+                .arg(path);
+        }
+        for (pubkey, path) in &accounts {
+            command.arg("--account").arg(pubkey.to_string()).arg(path);
+        }
+
+        let process = match command.spawn() {
+            Ok(p) => p,
+            Err(e) => panic!("could not start solana-test-validator: {}", e),
+        };
+
+        let validator = Self {
+            process,
+            ledger,
+            rpc_url: format!("http://127.0.0.1:{}", rpc_port),
+            dex_program_id: dex_programs[0],
+            oracle,
+        };
+        validator.wait_until_ready();
+        validator
+    }
+
+    fn fixture_files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+        let entries = match read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => {
+                return Vec::new();
+            }
+        };
+
+        let mut files: Vec<PathBuf> = entries
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(extension))
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn blocking_client(&self) -> RpcClient {
+        RpcClient::new_with_commitment(self.rpc_url.to_string(), CommitmentConfig::confirmed())
+    }
+
+    fn wait_until_ready(&self) {
+        let client = self.blocking_client();
+        let started = Instant::now();
+        while client.get_slot().is_err() {
+            if started.elapsed() > STARTUP_TIMEOUT {
+                panic!(
+                    "solana-test-validator did not start within {:?}",
+                    STARTUP_TIMEOUT
+                );
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    fn rpc_port(&self) -> u16 {
+        self.rpc_url.rsplit(':').next().unwrap().parse().unwrap()
+    }
+
+    /// The cluster as the cli sees it when given this validator's RPC URL.
+    pub fn cluster(&self) -> Cluster {
+        Cluster {
+            name: "localnet".to_string(),
+            config: ClusterConfig {
+                rpc_url: self.rpc_url.to_string(),
+                pubsub_url: format!("ws://127.0.0.1:{}", self.rpc_port() + 1),
+            },
+        }
+    }
+
+    /// An rpc backend talking to this validator, the same way the cli talks to a cluster.
+    pub fn rpc(&self) -> Arc<dyn RpcBackend> {
+        Arc::new(nonblocking::rpc_client::RpcClient::new_with_commitment(
+            self.rpc_url.to_string(),
+            CommitmentConfig::confirmed(),
+        ))
+    }
+
+    /// Airdrops the given amount of SOL and waits until it has landed.
+    pub fn fund(&self, pubkey: &Pubkey, sol: u64) {
+        let client = self.blocking_client();
+        let lamports = sol * LAMPORTS_PER_SOL;
+        client.request_airdrop(pubkey, lamports).unwrap();
+
+        let started = Instant::now();
+        while client.get_balance(pubkey).unwrap_or_default() < lamports {
+            if started.elapsed() > AIRDROP_TIMEOUT {
+                panic!(
+                    "airdrop to {} did not land within {:?}",
+                    pubkey, AIRDROP_TIMEOUT
+                );
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+impl Drop for LocalValidator {
+    fn drop(&mut self) {
+        _ = self.process.kill();
+        _ = self.process.wait();
+        _ = remove_dir_all(&self.ledger);
+    }
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use {
+    common::{TestEnv, GROUP_NAME, MARKET_NAME},
+    cypher_interactive::{
+        config::{
+            discover_cypher_config, fetch_cypher_group, load_cypher_config, sync_group_config,
+            CypherConfigOverlay,
+        },
+        rpc::{FixturesRpcBackend, RpcBackend},
+        CypherInteractiveError,
+    },
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{fs::write, sync::Arc},
+};
+
+#[test]
+fn overlay_overrides_rpc_urls_and_adds_groups() {
+    let env = TestEnv::new("config-overlay");
+    let overlay_path = env.dir.join("overlay.json");
+    write(
+        &overlay_path,
+        r#"{
+            "clusters": { "devnet": { "rpcUrl": "http://localhost:18899" } },
+            "groups": [{
+                "cluster": "devnet",
+                "name": "custom",
+                "quoteSymbol": "USDC",
+                "address": "11111111111111111111111111111111",
+                "programId": "11111111111111111111111111111111",
+                "serumProgramId": "11111111111111111111111111111111",
+                "tokens": [],
+                "oracles": [],
+                "markets": []
+            }]
+        }"#,
+    )
+    .unwrap();
+
+    let config = discover_cypher_config(
+        Some(&env.dir.join("cfg").join("group.json")),
+        Some(&overlay_path),
+    )
+    .unwrap();
+
+    assert_eq!(config.clusters["devnet"].rpc_url, "http://localhost:18899");
+    assert_eq!(config.clusters["devnet"].pubsub_url, "ws://localhost:8900");
+    assert!(config.get_group(GROUP_NAME).is_some());
+    assert!(config.get_group("custom").is_some());
+}
+
+#[test]
+fn validation_reports_every_problem() {
+    let env = TestEnv::new("config-invalid");
+    let mut config = load_cypher_config(&env.dir.join("cfg").join("group.json")).unwrap();
+    let mut market = config.groups[0].markets[0].clone();
+    market.name = "ETH".to_string();
+    market.bids = "not-a-pubkey".to_string();
+    config.groups[0].markets.push(market);
+    config.groups[0].address = "also-not-a-pubkey".to_string();
+
+    let problems = match config.validate() {
+        Err(CypherInteractiveError::ConfigInvalid(p)) => p,
+        r => panic!("expected the config to be invalid, got {:?}", r),
+    };
+
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems
+        .iter()
+        .any(|p| p.contains("address 'also-not-a-pubkey'")));
+    assert!(problems.iter().any(|p| p.contains("bids 'not-a-pubkey'")));
+    assert!(problems
+        .iter()
+        .any(|p| p.contains("marketIndex 0 is also used by market SOL")));
+}
+
+#[test]
+fn overlay_clusters_need_both_urls_to_be_added() {
+    let env = TestEnv::new("config-new-cluster");
+    let mut config = load_cypher_config(&env.dir.join("cfg").join("group.json")).unwrap();
+    let overlay: CypherConfigOverlay = serde_json::from_str(
+        r#"{ "clusters": {
+            "testnet": { "rpcUrl": "http://localhost" },
+            "staging": { "rpcUrl": "http://staging:8899", "pubsubUrl": "ws://staging:8900" }
+        } }"#,
+    )
+    .unwrap();
+
+    assert!(config.apply_overlay(overlay).is_err());
+    assert!(config.get_cluster("testnet").is_err());
+    assert_eq!(
+        config.get_cluster("staging").unwrap().config.rpc_url,
+        "http://staging:8899"
+    );
+}
+
+#[test]
+fn clusters_resolve_by_name_localnet_or_url_and_typos_are_errors() {
+    let env = TestEnv::new("config-clusters");
+    let config = load_cypher_config(&env.dir.join("cfg").join("group.json")).unwrap();
+    let signature = Signature::default();
+
+    let devnet = config.get_cluster("devnet").unwrap();
+    assert!(devnet
+        .get_explorer_tx_url(&signature)
+        .ends_with("?cluster=devnet"));
+    let mainnet = config.get_cluster("mainnet").unwrap();
+    assert!(!mainnet.get_explorer_tx_url(&signature).contains('?'));
+
+    let localnet = config.get_cluster("localnet").unwrap();
+    assert_eq!(localnet.config.rpc_url, "http://127.0.0.1:8899");
+    assert!(localnet
+        .get_explorer_tx_url(&signature)
+        .ends_with("?cluster=custom&customUrl=http%3A%2F%2F127.0.0.1%3A8899"));
+
+    let custom = config
+        .get_cluster("https://rpc.example.com:8899/key")
+        .unwrap();
+    assert_eq!(custom.config.pubsub_url, "wss://rpc.example.com:8900/key");
+
+    assert!(matches!(
+        config.get_cluster("devnte"),
+        Err(CypherInteractiveError::UnknownCluster(_))
+    ));
+}
+
+#[test]
+fn an_explicit_config_path_must_exist() {
+    let env = TestEnv::new("config-missing");
+
+    assert!(matches!(
+        discover_cypher_config(Some(&env.dir.join("missing.json")), None),
+        Err(CypherInteractiveError::ConfigRead)
+    ));
+}
+
+#[tokio::test]
+async fn sync_adds_listed_markets_updates_known_ones_and_drops_unlisted_ones() {
+    let env = TestEnv::new("config-sync");
+    let mint = Pubkey::new_unique();
+    env.list_market_in_group(0, &mint, 9, 6);
+    let rpc: Arc<dyn RpcBackend> =
+        Arc::new(FixturesRpcBackend::load(&env.dir.join("fixtures")).unwrap());
+    let group = fetch_cypher_group(&env.group, Arc::clone(&rpc))
+        .await
+        .unwrap();
+    let config = load_cypher_config(&env.dir.join("cfg").join("group.json")).unwrap();
+    let group_config = config.get_group(GROUP_NAME).unwrap();
+
+    // the fixtures config already describes the market the way it is on chain
+    let (synced, report) = sync_group_config(&group, group_config, Arc::clone(&rpc))
+        .await
+        .unwrap();
+    assert!(report.is_empty(), "{:?}", report);
+    assert_eq!(synced.markets, group_config.markets);
+
+    // a stale market is updated from its dex market, one which is no longer listed is dropped
+    let mut stale = group_config.clone();
+    stale.markets[0].event_queue = Pubkey::default().to_string();
+    let mut unlisted = stale.markets[0].clone();
+    unlisted.name = "ETH".to_string();
+    unlisted.market_index = 1;
+    stale.markets.push(unlisted);
+    let (synced, report) = sync_group_config(&group, &stale, Arc::clone(&rpc))
+        .await
+        .unwrap();
+    assert_eq!(report.updated, vec![MARKET_NAME.to_string()]);
+    assert_eq!(report.removed, vec!["ETH".to_string()]);
+    assert_eq!(synced.markets.len(), 1);
+    assert_eq!(synced.markets[0].event_queue, env.event_queue.to_string());
+
+    // a market missing from the config is added, named after its index until it is renamed
+    let mut empty = group_config.clone();
+    empty.markets.clear();
+    let (synced, report) = sync_group_config(&group, &empty, Arc::clone(&rpc))
+        .await
+        .unwrap();
+    assert_eq!(report.added, vec!["MARKET0".to_string()]);
+    let market = &synced.markets[0];
+    assert_eq!(market.address, env.market.to_string());
+    assert_eq!(market.bids, env.bids.to_string());
+    assert_eq!(market.asks, env.asks.to_string());
+    assert_eq!(market.event_queue, env.event_queue.to_string());
+    assert_eq!((market.base_decimals, market.quote_decimals), (9, 6));
+    assert!(synced
+        .tokens
+        .iter()
+        .any(|t| t.symbol == "MARKET0" && t.mint == mint.to_string()));
+
+    let mut synced_config = config.clone();
+    synced_config.set_group(synced);
+    assert!(synced_config.validate().is_ok());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use {
+    common::{TestEnv, MARKET_NAME},
+    cypher_interactive::{
+        keystore::{load_keypair, parse_keypair_file, KeypairFile, Keystore, ScryptParams},
+        CypherInteractiveError,
+    },
+    solana_sdk::signature::{Keypair, Signer},
+};
+
+#[test]
+fn keystore_round_trips_with_the_right_passphrase_only() {
+    let keypair = Keypair::new();
+    let keystore = Keystore::encrypt(&keypair, "correct horse").unwrap();
+    assert_eq!(keystore.pubkey, keypair.pubkey().to_string());
+
+    let decrypted = keystore.decrypt("correct horse").unwrap();
+    assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+
+    assert!(matches!(
+        keystore.decrypt("battery staple"),
+        Err(CypherInteractiveError::KeypairDecrypt)
+    ));
+}
+
+#[test]
+fn keystores_with_costlier_scrypt_parameters_are_rejected() {
+    let keystore = Keystore::encrypt(&Keypair::new(), "correct horse").unwrap();
+
+    for (log_n, r, p) in [(18, 8, 1), (17, 9, 1), (17, 8, 2), (63, u32::MAX, u32::MAX)] {
+        let mut tampered = keystore.clone();
+        tampered.kdf_params = ScryptParams { log_n, r, p };
+        assert!(
+            matches!(
+                tampered.decrypt("correct horse"),
+                Err(CypherInteractiveError::KeystoreUnsupported(_))
+            ),
+            "{:?} should be rejected",
+            tampered.kdf_params
+        );
+    }
+}
+
+#[test]
+fn keypair_files_are_parsed_as_byte_arrays_or_base58() {
+    let keypair = Keypair::new();
+
+    let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+    match parse_keypair_file(&format!("{}\n", json)).unwrap() {
+        KeypairFile::Plain(kp) => assert_eq!(kp.pubkey(), keypair.pubkey()),
+        KeypairFile::Encrypted(_) => panic!("a byte array is not a keystore"),
+    }
+
+    match parse_keypair_file(&keypair.to_base58_string()).unwrap() {
+        KeypairFile::Plain(kp) => assert_eq!(kp.pubkey(), keypair.pubkey()),
+        KeypairFile::Encrypted(_) => panic!("a base58 key is not a keystore"),
+    }
+}
+
+#[test]
+fn malformed_keypair_files_are_errors() {
+    for contents in [
+        "",
+        "[1, 2, 300]",
+        "[1, 2, 3]",
+        "not-base58-0OIl",
+        "{\"version\": 1}",
+    ] {
+        assert!(
+            parse_keypair_file(contents).is_err(),
+            "{:?} should not parse",
+            contents
+        );
+    }
+
+    let env = TestEnv::new("keystore-missing");
+    assert!(matches!(
+        load_keypair(&env.dir.join("missing.json")),
+        Err(CypherInteractiveError::KeypairFileOpen)
+    ));
+}
+
+#[test]
+fn cli_unlocks_an_encrypted_keypair() {
+    let env = TestEnv::new("keystore-cli");
+    let output = env.run_with_keystore(
+        "correct horse",
+        &[&format!("limit buy {} 1 30", MARKET_NAME)],
+    );
+
+    assert!(
+        output.contains(&format!(
+            "Loaded user keypair with pubkey: {}",
+            env.owner.pubkey()
+        )),
+        "{}",
+        output
+    );
+    // the same keystore for the owner and the signer is only decrypted once
+    assert!(
+        output.contains("Signing with the user keypair."),
+        "{}",
+        output
+    );
+    assert!(
+        !output.contains("Loading signer keypair from"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Successfully placed order."), "{}", output);
+}
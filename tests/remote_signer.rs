@@ -0,0 +1,53 @@
+#![cfg(unix)]
+mod common;
+
+use {
+    common::{signing_daemon::SigningDaemon, TestEnv, MARKET_NAME},
+    cypher_interactive::remote_signer::RemoteSigner,
+    solana_sdk::signature::{Keypair, Signer},
+};
+
+#[test]
+fn remote_signer_signs_with_the_daemon_key() {
+    let env = TestEnv::new("remote-signer");
+    let daemon = SigningDaemon::start(&env.dir, Keypair::new());
+
+    let signer = RemoteSigner::connect(&daemon.socket_path).unwrap();
+    assert_eq!(signer.pubkey(), daemon.pubkey);
+
+    let message = b"cypher";
+    let signature = signer.try_sign_message(message).unwrap();
+    assert!(signature.verify(daemon.pubkey.as_ref(), message));
+    assert_eq!(daemon.signed(), 1);
+
+    daemon.refuse();
+    assert!(signer.try_sign_message(message).is_err());
+}
+
+#[test]
+fn remote_signer_fails_without_a_daemon() {
+    let env = TestEnv::new("remote-signer-missing");
+
+    assert!(RemoteSigner::connect(&env.dir.join("missing.sock")).is_err());
+}
+
+#[test]
+fn limit_order_is_signed_by_the_daemon() {
+    let env = TestEnv::new("remote-signer-cli");
+    let daemon = SigningDaemon::start(
+        &env.dir,
+        Keypair::from_bytes(&env.owner.to_bytes()).unwrap(),
+    );
+    let output = env.run_with_signer_socket(
+        &daemon.socket_path,
+        &[&format!("limit buy {} 1 30", MARKET_NAME)],
+    );
+
+    assert!(
+        output.contains("Connecting to signing daemon"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Successfully placed order."), "{}", output);
+    assert!(daemon.signed() > 0);
+}
@@ -0,0 +1,271 @@
+//! End to end tests against a local `solana-test-validator` running the cypher, serum dex and faucet programs.
+//!
+//! These need the validator binary and the fixtures described in `tests/fixtures/validator/README.md`,
+//! and fail without them. Run them with `cargo test --test validator -- --ignored`.
+//! Every test creates its own group and market before driving the cli against it.
+mod common;
+
+use {
+    common::{group_setup::TestGroup, validator::LocalValidator},
+    cypher::{
+        utils::{derive_cypher_user_address, derive_open_orders_address, get_zero_copy_account},
+        CypherGroup,
+    },
+    cypher_interactive::{
+        config::CypherMarketConfig,
+        market_handler::{Handler, HandlerContext, LimitOrderInfo, MarketContext},
+        providers::{OpenOrdersContext, OrderBook},
+        risk::RiskEngine,
+        rpc::RpcBackend,
+        utils::{
+            deposit_quote_token, get_open_orders, get_or_init_cypher_user, get_or_init_open_orders,
+            get_serum_market, request_airdrop, MarginCheck, MarketUnits,
+        },
+    },
+    serum_dex::matching::Side,
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+    },
+    std::{str::FromStr, sync::Arc, time::Duration},
+    tokio::{sync::broadcast::channel, time::sleep},
+};
+
+const DEPOSIT_AMOUNT: u64 = 1_000_000_000;
+
+async fn fetch_group(rpc: &Arc<dyn RpcBackend>, group: &Pubkey) -> Box<CypherGroup> {
+    let account = rpc.get_account(group).await.unwrap().unwrap();
+    get_zero_copy_account::<CypherGroup>(&account)
+}
+
+fn first_market(group: &TestGroup) -> CypherMarketConfig {
+    group
+        .config
+        .markets
+        .first()
+        .expect("the test group has no markets")
+        .clone()
+}
+
+// the validator helpers use the blocking rpc client, which needs a multi threaded runtime
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn cypher_user_and_open_orders_are_only_created_once() {
+    let validator = LocalValidator::start("accounts", 18_899);
+    let test_group = TestGroup::create(&validator).await;
+    let rpc = validator.rpc();
+    let owner = Keypair::new();
+    validator.fund(&owner.pubkey(), 10);
+
+    let group = test_group.address;
+    let cypher_user = derive_cypher_user_address(&group, &owner.pubkey()).0;
+
+    get_or_init_cypher_user(
+        &owner,
+        &group,
+        &cypher_user,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    let created = rpc.get_account(&cypher_user).await.unwrap().unwrap();
+    get_or_init_cypher_user(
+        &owner,
+        &group,
+        &cypher_user,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    // the second call must find the account instead of trying to create it again
+    let fetched = rpc.get_account(&cypher_user).await.unwrap().unwrap();
+    assert_eq!(created.data, fetched.data);
+
+    let market = Pubkey::from_str(&first_market(&test_group).address).unwrap();
+    let open_orders = derive_open_orders_address(&market, &cypher_user).0;
+    for _ in 0..2 {
+        get_or_init_open_orders(
+            &owner,
+            &group,
+            &cypher_user,
+            &market,
+            &open_orders,
+            Arc::clone(&rpc),
+            &validator.cluster(),
+        )
+        .await
+        .unwrap();
+    }
+    assert!(rpc.get_account(&open_orders).await.unwrap().is_some());
+}
+
+// the validator helpers use the blocking rpc client, which needs a multi threaded runtime
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn limit_order_is_placed_and_cancelled() {
+    let validator = LocalValidator::start("orders", 19_899);
+    let test_group = TestGroup::create(&validator).await;
+    let rpc = validator.rpc();
+    let owner = Arc::new(Keypair::new());
+    validator.fund(&owner.pubkey(), 10);
+
+    let group = test_group.address;
+    let cypher_user = derive_cypher_user_address(&group, &owner.pubkey()).0;
+    get_or_init_cypher_user(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+
+    // fund the quote token account from the faucet and move it into the cypher account
+    request_airdrop(owner.as_ref(), Arc::clone(&rpc))
+        .await
+        .unwrap();
+    let cypher_group = fetch_group(&rpc, &group).await;
+    deposit_quote_token(
+        owner.as_ref(),
+        &cypher_user,
+        &cypher_group,
+        Arc::clone(&rpc),
+        DEPOSIT_AMOUNT,
+    )
+    .await
+    .unwrap();
+
+    let market_config = first_market(&test_group);
+    let market = Pubkey::from_str(&market_config.address).unwrap();
+    let open_orders = derive_open_orders_address(&market, &cypher_user).0;
+    get_or_init_open_orders(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        &market,
+        &open_orders,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+
+    let dex_market = get_serum_market(Arc::clone(&rpc), market).await.unwrap();
+    let units = MarketUnits::new(
+        market_config.base_decimals,
+        market_config.quote_decimals,
+        dex_market.coin_lot_size,
+        dex_market.pc_lot_size,
+    );
+    let (shutdown_send, _) = channel::<bool>(1);
+    let (oo_send, oo_recv) = channel::<OpenOrdersContext>(16);
+    let (_ob_send, ob_recv) = channel::<Arc<OrderBook>>(16);
+    let handler = Arc::new(Handler::new(
+        Box::new(MarketContext {
+            name: market_config.name.to_string(),
+            market_index: market_config.market_index,
+            signer: Arc::clone(&owner),
+            cypher_user_pk: cypher_user,
+            dex_market_pk: market,
+            open_orders_pk: open_orders,
+            units,
+        }),
+        Arc::clone(&rpc),
+        shutdown_send.subscribe(),
+        oo_recv,
+        ob_recv,
+        Some(dex_market),
+        MarginCheck::default(),
+        Arc::new(RiskEngine::default()),
+    ));
+    let cloned_handler = Arc::clone(&handler);
+    tokio::spawn(async move {
+        cloned_handler.start().await;
+    });
+
+    // the lowest possible bid for a single lot, it will never cross the book
+    let order_info = LimitOrderInfo {
+        symbol: market_config.name.to_string(),
+        price: units.tick_size(),
+        amount: units.native_base_to_ui(units.coin_lot_size),
+        side: Side::Bid,
+        force: false,
+    };
+    let user = get_or_init_cypher_user(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    let ctx = HandlerContext {
+        user,
+        group: fetch_group(&rpc, &group).await,
+        hash: Box::new(rpc.get_latest_blockhash().await.unwrap()),
+    };
+    handler.limit_order(ctx, &order_info).await.unwrap();
+
+    // the handler learns about its orders from the open orders provider, feed it the account directly
+    let placed = get_or_init_open_orders(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        &market,
+        &open_orders,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    let orders = get_open_orders(&placed);
+    assert_eq!(orders.len(), 1);
+    let order = &orders[0];
+    assert_eq!(order.side, Side::Bid);
+    assert_eq!(order.price, 1);
+
+    oo_send
+        .send(OpenOrdersContext {
+            open_orders: placed,
+            pubkey: open_orders,
+        })
+        .unwrap();
+    while handler.get_open_orders().await.is_err() {
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    let user = get_or_init_cypher_user(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    let ctx = HandlerContext {
+        user,
+        group: fetch_group(&rpc, &group).await,
+        hash: Box::new(rpc.get_latest_blockhash().await.unwrap()),
+    };
+    handler.cancel_order(ctx, order.order_id).await.unwrap();
+
+    let cancelled = get_or_init_open_orders(
+        owner.as_ref(),
+        &group,
+        &cypher_user,
+        &market,
+        &open_orders,
+        Arc::clone(&rpc),
+        &validator.cluster(),
+    )
+    .await
+    .unwrap();
+    assert!(get_open_orders(&cancelled).is_empty());
+
+    _ = shutdown_send.send(true);
+}